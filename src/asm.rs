@@ -1,40 +1,57 @@
+use std::collections::HashMap;
+
 #[derive(Debug)]
 pub struct Program {
-    function: Function,
+    functions: Vec<Function>,
+    static_variables: Vec<StaticVariable>,
+    // Layout of the object each pseudo register stands for
+    layouts: HashMap<String, Layout>,
 }
 
 impl Program {
-    pub fn new(function: Function) -> Self {
+    pub fn new(functions: Vec<Function>, static_variables: Vec<StaticVariable>, layouts: HashMap<String, Layout>) -> Self {
         Program {
-            function
+            functions,
+            static_variables,
+            layouts,
         }
     }
 
-    pub fn from_function(function: Function) -> Self {
-        Self {
-            function
-        }
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
     }
 
-    pub fn function(&self) -> &Function {
-        &self.function
+    pub fn static_variables(&self) -> &[StaticVariable] {
+        &self.static_variables
     }
 
-    pub fn into_function(self) -> Function {
-        self.function
+    pub fn decompose(self) -> (Vec<Function>, Vec<StaticVariable>, HashMap<String, Layout>) {
+        (self.functions, self.static_variables, self.layouts)
     }
 }
 
+/**
+Size and alignment in bytes of an object, which can be an array rather than a single value.
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct Layout {
+    pub size: i64,
+    pub alignment: i64,
+}
+
 #[derive(Debug)]
 pub struct Function {
     name: String,
+    // Whether the function is visible from other files
+    global: bool,
     instructions: Vec<Instruction>,
 }
 
 impl Function {
-    pub fn new(name: String, instructions: Vec<Instruction>) -> Self {
+    pub fn new(name: String, global: bool, instructions: Vec<Instruction>) -> Self {
         Function {
             name,
+            global,
             instructions,
         }
     }
@@ -43,26 +60,176 @@ impl Function {
         &self.name
     }
 
+    pub fn global(&self) -> bool {
+        self.global
+    }
+
     pub fn instructions(&self) -> &Vec<Instruction> {
         &self.instructions
     }
 
-    pub fn decompose(self) -> (String, Vec<Instruction>) {
-        (self.name, self.instructions)
+    pub fn decompose(self) -> (String, bool, Vec<Instruction>) {
+        (self.name, self.global, self.instructions)
+    }
+}
+
+/**
+Variable with static storage duration. Variables defined in another file have no initial value, they are only there so
+that the references to them are known to be in memory.
+*/
+#[derive(Debug)]
+pub struct StaticVariable {
+    name: String,
+    global: bool,
+    alignment: i64,
+    init: Option<Vec<StaticInit>>,
+}
+
+impl StaticVariable {
+    pub fn new(name: String, global: bool, alignment: i64, init: Option<Vec<StaticInit>>) -> Self {
+        StaticVariable {
+            name,
+            global,
+            alignment,
+            init,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn global(&self) -> bool {
+        self.global
+    }
+
+    pub fn alignment(&self) -> i64 {
+        self.alignment
+    }
+
+    pub fn init(&self) -> Option<&[StaticInit]> {
+        self.init.as_deref()
+    }
+}
+
+/**
+Part of the initial value of a variable with static storage duration, as the bits of an integer of the size of a
+scalar, or a number of zero bytes.
+*/
+#[derive(Debug, Copy, Clone)]
+pub enum StaticInit {
+    Byte(u8),
+    Word(u16),
+    Long(u32),
+    Quad(u64),
+    Double(f64),
+    Zero(i64),
+}
+
+impl StaticInit {
+    /**
+    Whether every byte of the value is zero, so that it can go in the section which is zeroed when the program starts.
+    */
+    pub fn is_zero(&self) -> bool {
+        match self {
+            StaticInit::Byte(value) => *value == 0,
+            StaticInit::Word(value) => *value == 0,
+            StaticInit::Long(value) => *value == 0,
+            StaticInit::Quad(value) => *value == 0,
+            StaticInit::Double(value) => value.to_bits() == 0,
+            StaticInit::Zero(_) => true,
+        }
+    }
+
+    pub fn bytes(&self) -> i64 {
+        match self {
+            StaticInit::Byte(_) => 1,
+            StaticInit::Word(_) => 2,
+            StaticInit::Long(_) => 4,
+            StaticInit::Quad(_) | StaticInit::Double(_) => 8,
+            StaticInit::Zero(bytes) => *bytes,
+        }
     }
 }
 
+/**
+Size of the integer operands of an instruction, which gives the suffix of its mnemonic and the names of its registers.
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Size {
+    Byte,
+    Word,
+    Long,
+    Quad,
+}
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
-    Mov { src: Operand, dest: Operand },
-    Unary(UnaryOperator, Operand),
-    Binary(BinaryOperator, Operand, Operand),
-    Idiv(Operand),
-    Cdq,
+    Mov { size: Size, src: Operand, dest: Operand },
+    // Sign and zero extension of `src` to the larger size of `dest`
+    Movsx { src_size: Size, dest_size: Size, src: Operand, dest: Operand },
+    Movzx { src_size: Size, dest_size: Size, src: Operand, dest: Operand },
+    Movsd { src: Operand, dest: Operand },
+    Lea { src: Operand, dest: Operand },
+    Unary(UnaryOperator, Size, Operand),
+    // The size of the operations on doubles is the one of a double
+    Binary(BinaryOperator, Size, Operand, Operand),
+    // Signed and unsigned division of %edx:%eax, or %rdx:%rax, leaving the quotient in %eax and the remainder in %edx
+    Idiv(Size, Operand),
+    Div(Size, Operand),
+    // Sign-extends %eax into %edx (`cdq`), or %rax into %rdx (`cqo`)
+    Cdq(Size),
+    // Conversions between doubles and signed integers of `size`
+    Cvtsi2sd { size: Size, src: Operand, dest: Operand },
+    Cvttsd2si { size: Size, src: Operand, dest: Operand },
+    Cmp(Size, Operand, Operand),
+    Comisd(Operand, Operand),
+    Jmp(String),
+    JmpCC(CondCode, String),
+    // Jump to the entry of `table` at the index in %eax, through %rdx. The emitter places the table in a read-only
+    // section.
+    JmpTable { table: String, targets: Vec<String> },
+    SetCC(CondCode, Operand),
+    Label(String),
     AllocateStack(i64),
+    DeallocateStack(i64),
+    // Pushes 8 bytes, of which smaller types only use the lowest ones
+    Push(Operand),
+    Call(String),
     Ret,
 }
 
+impl Instruction {
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Instruction::Mov { src, dest, .. }
+            | Instruction::Movsx { src, dest, .. }
+            | Instruction::Movzx { src, dest, .. }
+            | Instruction::Movsd { src, dest }
+            | Instruction::Lea { src, dest }
+            | Instruction::Cvtsi2sd { src, dest, .. }
+            | Instruction::Cvttsd2si { src, dest, .. }
+            | Instruction::Binary(_, _, src, dest)
+            | Instruction::Cmp(_, src, dest)
+            | Instruction::Comisd(src, dest) => vec![src, dest],
+            Instruction::Unary(_, _, operand)
+            | Instruction::Idiv(_, operand)
+            | Instruction::Div(_, operand)
+            | Instruction::SetCC(_, operand)
+            | Instruction::Push(operand) => vec![operand],
+            Instruction::Cdq(_)
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC(_, _)
+            | Instruction::JmpTable { .. }
+            | Instruction::Label(_)
+            | Instruction::AllocateStack(_)
+            | Instruction::DeallocateStack(_)
+            | Instruction::Call(_)
+            | Instruction::Ret => vec![],
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum UnaryOperator {
     Neg,
@@ -74,22 +241,104 @@ pub enum BinaryOperator {
     Add,
     Sub,
     Mul,
+    And,
+    Or,
+    Xor,
+    Sal,
+    Sar,
+    Shr,
+
+    Addsd,
+    Subsd,
+    Mulsd,
+    Divsd,
+    Xorpd,
 }
 
+impl BinaryOperator {
+    pub fn is_double(&self) -> bool {
+        matches!(self, BinaryOperator::Addsd | BinaryOperator::Subsd | BinaryOperator::Mulsd | BinaryOperator::Divsd | BinaryOperator::Xorpd)
+    }
+}
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/**
+Condition tested by conditional jumps and `setcc`. `A`, `AE`, `B`, `BE` are the unsigned conditions, which are also
+set by `comisd`, and `P` tells that one of the compared doubles was NaN.
+*/
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum CondCode {
+    E,
+    NE,
+    L,
+    LE,
+    G,
+    GE,
+    A,
+    AE,
+    B,
+    BE,
+    P,
+    NP,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Operand {
+    // Bits of an integer, read at the size of the instruction
     Imm(u64),
     Register(Register),
     Pseudo(String),
+    // Part of the array pseudo register `name` at `offset` bytes from its beginning
+    PseudoMem { name: String, offset: i64 },
     Stack(i64),
+    // Memory at `offset` bytes from the address held by `base`
+    Memory { base: Register, offset: i64 },
+    // Memory at the address held by `base` plus `scale` times the one held by `index`, where `scale` is 1, 2, 4 or 8
+    Indexed { base: Register, index: Register, scale: i64 },
+    // Memory holding the bytes of a string literal, which the emitter places in a read-only section
+    StringLiteral(Vec<u8>),
+    // Memory holding a double, which the emitter places in a read-only section
+    DoubleConstant(f64),
+    // Variable with static storage duration, addressed relative to the instruction pointer
+    Data(String),
+}
+
+impl Operand {
+    pub fn is_memory(&self) -> bool {
+        matches!(self, Operand::Stack(_) | Operand::Memory { .. } | Operand::Indexed { .. } | Operand::StringLiteral(_) | Operand::DoubleConstant(_) | Operand::Data(_))
+    }
+
+    /**
+    Whether the operand is an immediate which instructions other than `mov` to a register can't encode, because
+    they sign-extend 4-byte immediates to 8 bytes.
+    */
+    pub fn is_large_immediate(&self, size: Size) -> bool {
+        match self {
+            Operand::Imm(value) => size == Size::Quad && i32::try_from(*value as i64).is_err(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Register {
     AX,
+    CX,
     DX,
+    DI,
+    SI,
+    R8,
+    R9,
     R10,
     R11,
+    XMM0,
+    XMM1,
+    XMM2,
+    XMM3,
+    XMM4,
+    XMM5,
+    XMM6,
+    XMM7,
+    XMM14,
+    XMM15,
 }
 
@@ -1,5 +1,5 @@
 use crate::asm;
-use crate::asm::{BinaryOperator, Instruction, Operand, Program, Register};
+use crate::asm::{BinaryOperator, Instruction, Operand, Program, Register, Size};
 use crate::asm_pass::AsmPass;
 
 pub struct BinaryOperation {}
@@ -10,72 +10,170 @@ impl BinaryOperation {
     }
 
     fn handle_function(&mut self, function: asm::Function) -> asm::Function {
-        let (name, ins) = function.decompose();
-        let instructions: Vec<_> = ins.into_iter().map(|instruction| self.handle_instruction(instruction)).flatten().collect();
+        let (name, global, ins) = function.decompose();
+        let instructions: Vec<_> = ins.into_iter().flat_map(|instruction| self.handle_instruction(instruction)).collect();
 
-        asm::Function::new(name, instructions)
+        asm::Function::new(name, global, instructions)
     }
 
     fn handle_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
         match instruction {
-            Instruction::Idiv(operand) => {
-                if let Operand::Imm(value) = operand {
+            Instruction::Idiv(size, operand) => self.handle_division(size, operand, Instruction::Idiv),
+            Instruction::Div(size, operand) => self.handle_division(size, operand, Instruction::Div),
+            Instruction::Binary(operator, size, Operand::StringLiteral(value), dest) => {
+                let mut instructions = vec![
+                    Instruction::Lea {
+                        src: Operand::StringLiteral(value),
+                        dest: Operand::Register(Register::R10)
+                    }
+                ];
+                instructions.extend(self.handle_instruction(Instruction::Binary(operator, size, Operand::Register(Register::R10), dest)));
+                instructions
+            }
+            Instruction::Binary(operator, size, src, dest) if operator.is_double() && dest.is_memory() => {
+                // SSE instructions can only write to a register
+                vec![
+                    Instruction::Movsd {
+                        src: dest.clone(),
+                        dest: Operand::Register(Register::XMM15)
+                    },
+                    Instruction::Binary(operator, size, src, Operand::Register(Register::XMM15)),
+                    Instruction::Movsd {
+                        src: Operand::Register(Register::XMM15),
+                        dest
+                    }
+                ]
+            }
+            Instruction::Binary(operator, size, src, dest) if src.is_large_immediate(size) => {
+                let mut instructions = vec![
+                    Instruction::Mov {
+                        size,
+                        src,
+                        dest: Operand::Register(Register::R10)
+                    }
+                ];
+                instructions.extend(self.handle_instruction(Instruction::Binary(operator, size, Operand::Register(Register::R10), dest)));
+                instructions
+            }
+            Instruction::Binary(operator, size, src, dest) if matches!(operator, BinaryOperator::Add | BinaryOperator::Sub | BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Xor) => {
+                if src.is_memory() && dest.is_memory() {
                     vec![
                         Instruction::Mov {
-                            src: Operand::Imm(value),
+                            size,
+                            src,
                             dest: Operand::Register(Register::R10)
                         },
-                        Instruction::Idiv(
-                            Operand::Register(Register::R10)
-                        )
+                        Instruction::Binary(operator, size, Operand::Register(Register::R10), dest)
                     ]
                 } else {
-                    vec![Instruction::Idiv(operand)]
+                    vec![Instruction::Binary(operator, size, src, dest)]
                 }
             }
-            Instruction::Binary(operator, src, dest) if operator == BinaryOperator::Add || operator == BinaryOperator::Sub => {
-                match (src, dest) {
-                    (Operand::Stack(src_offset), Operand::Stack(dest_offset)) => {
-                        vec![
-                            Instruction::Mov {
-                                src: Operand::Stack(src_offset),
-                                dest: Operand::Register(Register::R10)
-                            },
-                            Instruction::Binary(
-                                operator,
-                                Operand::Register(Register::R10),
-                                Operand::Stack(dest_offset)
-                            )
-                        ]
-                    }
-                    (src, dest) => vec![Instruction::Binary(operator, src, dest)]
-                }
-            }
-            Instruction::Binary(operator, src, dest) if operator == BinaryOperator::Mul => {
-                if let Operand::Stack(dest_offset) = dest {
+            Instruction::Binary(operator, size, src, dest) if operator == BinaryOperator::Mul => {
+                if dest.is_memory() {
                     vec![
                         Instruction::Mov {
-                            src: Operand::Stack(dest_offset),
+                            size,
+                            src: dest.clone(),
                             dest: Operand::Register(Register::R11)
                         },
-                        Instruction::Binary(operator, src, Operand::Register(Register::R11)),
+                        Instruction::Binary(operator, size, src, Operand::Register(Register::R11)),
                         Instruction::Mov {
+                            size,
                             src: Operand::Register(Register::R11),
-                            dest: Operand::Stack(dest_offset)
+                            dest
                         }
                     ]
                 } else {
-                    vec![Instruction::Binary(operator, src, dest)]
+                    vec![Instruction::Binary(operator, size, src, dest)]
                 }
             }
+            Instruction::Cmp(size, Operand::StringLiteral(value), right) => {
+                let mut instructions = vec![
+                    Instruction::Lea {
+                        src: Operand::StringLiteral(value),
+                        dest: Operand::Register(Register::R10)
+                    }
+                ];
+                instructions.extend(self.handle_instruction(Instruction::Cmp(size, Operand::Register(Register::R10), right)));
+                instructions
+            }
+            Instruction::Cmp(size, left, Operand::StringLiteral(value)) => {
+                vec![
+                    Instruction::Lea {
+                        src: Operand::StringLiteral(value),
+                        dest: Operand::Register(Register::R11)
+                    },
+                    Instruction::Cmp(size, left, Operand::Register(Register::R11))
+                ]
+            }
+            Instruction::Cmp(size, left, right) if (left.is_memory() && right.is_memory()) || left.is_large_immediate(size) => {
+                let mut instructions = vec![
+                    Instruction::Mov {
+                        size,
+                        src: left,
+                        dest: Operand::Register(Register::R10)
+                    }
+                ];
+                instructions.extend(self.handle_instruction(Instruction::Cmp(size, Operand::Register(Register::R10), right)));
+                instructions
+            }
+            Instruction::Cmp(size, left, Operand::Imm(value)) => {
+                // The second operand of cmp can't be an immediate
+                vec![
+                    Instruction::Mov {
+                        size,
+                        src: Operand::Imm(value),
+                        dest: Operand::Register(Register::R11)
+                    },
+                    Instruction::Cmp(size, left, Operand::Register(Register::R11))
+                ]
+            }
+            Instruction::Comisd(left, right) if !matches!(right, Operand::Register(_)) => {
+                vec![
+                    Instruction::Movsd {
+                        src: right,
+                        dest: Operand::Register(Register::XMM15)
+                    },
+                    Instruction::Comisd(left, Operand::Register(Register::XMM15))
+                ]
+            }
             instruction => vec![instruction]
         }
     }
+
+    /**
+    The divisor of `idiv` and `div` can't be an immediate, nor the address of a string literal.
+    */
+    fn handle_division(&mut self, size: Size, operand: Operand, division: fn(Size, Operand) -> Instruction) -> Vec<Instruction> {
+        match operand {
+            Operand::StringLiteral(value) => {
+                vec![
+                    Instruction::Lea {
+                        src: Operand::StringLiteral(value),
+                        dest: Operand::Register(Register::R10)
+                    },
+                    division(size, Operand::Register(Register::R10))
+                ]
+            }
+            Operand::Imm(value) => {
+                vec![
+                    Instruction::Mov {
+                        size,
+                        src: Operand::Imm(value),
+                        dest: Operand::Register(Register::R10)
+                    },
+                    division(size, Operand::Register(Register::R10))
+                ]
+            }
+            operand => vec![division(size, operand)]
+        }
+    }
 }
 
 impl AsmPass for BinaryOperation {
     fn run(&mut self, program: Program) -> Program {
-        let function = self.handle_function(program.into_function());
-        Program::from_function(function)
+        let (functions, static_variables, layouts) = program.decompose();
+        Program::new(functions.into_iter().map(|function| self.handle_function(function)).collect(), static_variables, layouts)
     }
 }
\ No newline at end of file
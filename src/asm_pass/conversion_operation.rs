@@ -0,0 +1,129 @@
+use crate::asm;
+use crate::asm::{Instruction, Operand, Program, Register, Size};
+use crate::asm_pass::AsmPass;
+
+/**
+This pass rewrites the conversions, between integers of different sizes and between integers and doubles, whose
+operands can't be encoded.
+*/
+pub struct ConversionOperation {}
+
+impl ConversionOperation {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn handle_function(&mut self, function: asm::Function) -> asm::Function {
+        let (name, global, ins) = function.decompose();
+        let instructions: Vec<_> = ins.into_iter().flat_map(|instruction| self.handle_instruction(instruction)).collect();
+
+        asm::Function::new(name, global, instructions)
+    }
+
+    fn handle_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
+        match instruction {
+            Instruction::Cvtsi2sd { size, src, dest } => {
+                let mut instructions = vec![];
+
+                // The source can't be an immediate and the destination has to be a register
+                let src = self.immediate_to_register(size, src, &mut instructions);
+
+                if dest.is_memory() {
+                    instructions.push(Instruction::Cvtsi2sd {
+                        size,
+                        src,
+                        dest: Operand::Register(Register::XMM15),
+                    });
+                    instructions.push(Instruction::Movsd {
+                        src: Operand::Register(Register::XMM15),
+                        dest,
+                    });
+                } else {
+                    instructions.push(Instruction::Cvtsi2sd { size, src, dest });
+                }
+
+                instructions
+            }
+            Instruction::Cvttsd2si { size, src, dest } if dest.is_memory() => {
+                vec![
+                    Instruction::Cvttsd2si {
+                        size,
+                        src,
+                        dest: Operand::Register(Register::R11),
+                    },
+                    Instruction::Mov {
+                        size,
+                        src: Operand::Register(Register::R11),
+                        dest,
+                    }
+                ]
+            }
+            // Writing the lowest 4 bytes of a register clears the upper ones, so there is no `movzlq`
+            Instruction::Movzx { src_size: Size::Long, dest_size: Size::Quad, src, dest } if dest.is_memory() => {
+                vec![
+                    Instruction::Mov {
+                        size: Size::Long,
+                        src,
+                        dest: Operand::Register(Register::R11),
+                    },
+                    Instruction::Mov {
+                        size: Size::Quad,
+                        src: Operand::Register(Register::R11),
+                        dest,
+                    }
+                ]
+            }
+            Instruction::Movzx { src_size: Size::Long, dest_size: Size::Quad, src, dest } => {
+                vec![Instruction::Mov { size: Size::Long, src, dest }]
+            }
+            Instruction::Movsx { src_size, dest_size, src, dest } => {
+                self.handle_extension(src_size, dest_size, src, dest, |src, dest| Instruction::Movsx { src_size, dest_size, src, dest })
+            }
+            Instruction::Movzx { src_size, dest_size, src, dest } => {
+                self.handle_extension(src_size, dest_size, src, dest, |src, dest| Instruction::Movzx { src_size, dest_size, src, dest })
+            }
+            instruction => vec![instruction]
+        }
+    }
+
+    /**
+    The source of `movsx` and `movzx` can't be an immediate and the destination has to be a register.
+    */
+    fn handle_extension(&mut self, src_size: Size, dest_size: Size, src: Operand, dest: Operand, extension: impl Fn(Operand, Operand) -> Instruction) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        let src = self.immediate_to_register(src_size, src, &mut instructions);
+
+        if dest.is_memory() {
+            instructions.push(extension(src, Operand::Register(Register::R11)));
+            instructions.push(Instruction::Mov {
+                size: dest_size,
+                src: Operand::Register(Register::R11),
+                dest,
+            });
+        } else {
+            instructions.push(extension(src, dest));
+        }
+
+        instructions
+    }
+
+    fn immediate_to_register(&mut self, size: Size, operand: Operand, instructions: &mut Vec<Instruction>) -> Operand {
+        if let Operand::Imm(value) = operand {
+            instructions.push(Instruction::Mov {
+                size,
+                src: Operand::Imm(value),
+                dest: Operand::Register(Register::R10),
+            });
+            Operand::Register(Register::R10)
+        } else {
+            operand
+        }
+    }
+}
+
+impl AsmPass for ConversionOperation {
+    fn run(&mut self, program: Program) -> Program {
+        let (functions, static_variables, layouts) = program.decompose();
+        Program::new(functions.into_iter().map(|function| self.handle_function(function)).collect(), static_variables, layouts)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::asm;
-use crate::asm::{Instruction, Operand, Program, Register};
+use crate::asm::{Instruction, Operand, Program, Register, Size};
 use crate::asm_pass::AsmPass;
 
 pub struct InvalidMovRewrite {}
@@ -8,29 +8,67 @@ impl InvalidMovRewrite {
     pub fn new() -> Self { Self {} }
 
     fn handle_function(&mut self, function: asm::Function) -> asm::Function {
-        let (name, ins) = function.decompose();
-        let instructions: Vec<_> = ins.into_iter().map(|instruction| self.handle_instruction(instruction)).flatten().collect();
+        let (name, global, ins) = function.decompose();
+        let instructions: Vec<_> = ins.into_iter().flat_map(|instruction| self.handle_instruction(instruction)).collect();
 
-        asm::Function::new(name, instructions)
+        asm::Function::new(name, global, instructions)
     }
 
     fn handle_instruction(&mut self, instruction: Instruction) -> Vec<Instruction> {
         match instruction {
-            Instruction::Mov { src, dest } => {
-                match (src, dest) {
-                    (Operand::Stack(offset_src), Operand::Stack(offset_dst)) => {
+            // Only a `mov` to a register can take an immediate which doesn't fit in 4 bytes
+            Instruction::Mov { size, src, dest } if dest.is_memory() && (src.is_memory() || src.is_large_immediate(size)) => {
+                vec![
+                    Instruction::Mov {
+                        size,
+                        src,
+                        dest: Operand::Register(Register::R10)
+                    },
+                    Instruction::Mov {
+                        size,
+                        src: Operand::Register(Register::R10),
+                        dest,
+                    }
+                ]
+            }
+            Instruction::Push(src) if src.is_large_immediate(Size::Quad) => {
+                vec![
+                    Instruction::Mov {
+                        size: Size::Quad,
+                        src,
+                        dest: Operand::Register(Register::R10)
+                    },
+                    Instruction::Push(Operand::Register(Register::R10)),
+                ]
+            }
+            Instruction::Movsd { src, dest } if src.is_memory() && dest.is_memory() => {
+                vec![
+                    Instruction::Movsd {
+                        src,
+                        dest: Operand::Register(Register::XMM14),
+                    },
+                    Instruction::Movsd {
+                        src: Operand::Register(Register::XMM14),
+                        dest,
+                    }
+                ]
+            }
+            Instruction::Lea { src, dest } => {
+                match dest {
+                    Operand::Register(_) => vec![Instruction::Lea { src, dest }],
+                    dest => {
                         vec![
-                            Instruction::Mov {
-                                src: Operand::Stack(offset_src),
-                                dest: Operand::Register(Register::R10)
+                            Instruction::Lea {
+                                src,
+                                dest: Operand::Register(Register::R10),
                             },
                             Instruction::Mov {
+                                size: Size::Quad,
                                 src: Operand::Register(Register::R10),
-                                dest: Operand::Stack(offset_dst),
+                                dest,
                             }
                         ]
                     }
-                    (src, dest) => vec![Instruction::Mov { src, dest }]
                 }
             }
             instruction => vec![instruction]
@@ -40,7 +78,7 @@ impl InvalidMovRewrite {
 
 impl AsmPass for InvalidMovRewrite {
     fn run(&mut self, program: Program) -> Program {
-        let function = self.handle_function(program.into_function());
-        Program::from_function(function)
+        let (functions, static_variables, layouts) = program.decompose();
+        Program::new(functions.into_iter().map(|function| self.handle_function(function)).collect(), static_variables, layouts)
     }
 }
\ No newline at end of file
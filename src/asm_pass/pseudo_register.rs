@@ -1,57 +1,129 @@
 use crate::asm;
-use crate::asm::{Instruction, Operand, Program};
+use crate::asm::{Instruction, Layout, Operand, Program};
 use crate::asm_pass::AsmPass;
 use crate::stack_allocator::StackAllocator;
+use std::collections::{HashMap, HashSet};
 
 /**
-This pass looks for all the pseudo register references in the asm tree and replace them with a stack offset.
+This pass looks for all the pseudo register references in the asm tree and replace them with a stack offset, or with
+the variable itself when it has static storage duration.
 */
 pub struct PseudoRegister {
     stack_allocator: StackAllocator,
+    static_variables: HashSet<String>,
+    layouts: HashMap<String, Layout>,
 }
 
 impl PseudoRegister {
     pub fn new() -> Self {
         Self {
-            stack_allocator: StackAllocator::new()
+            stack_allocator: StackAllocator::new(),
+            static_variables: HashSet::new(),
+            layouts: HashMap::new(),
         }
     }
 
     fn handle_function(&mut self, function: asm::Function) -> asm::Function {
-        let (name, ins) = function.decompose();
+        // Every function has its own frame
+        self.stack_allocator = StackAllocator::new();
+
+        let (name, global, ins) = function.decompose();
         let mut instructions: Vec<_> = ins.into_iter().map(|instruction| self.handle_instruction(instruction)).collect();
 
-        instructions.insert(0, Instruction::AllocateStack(self.stack_allocator.stack_size()));
+        // The stack is 16-byte aligned at calls, and stays so as long as the frame size is a multiple of 16
+        let stack_size = (self.stack_allocator.stack_size() + 15) / 16 * 16;
+        instructions.insert(0, Instruction::AllocateStack(stack_size));
 
-        asm::Function::new(name, instructions)
+        asm::Function::new(name, global, instructions)
     }
 
     fn handle_instruction(&mut self, instruction: Instruction) -> Instruction {
         match instruction {
-            Instruction::Mov { src, dest } => {
+            Instruction::Mov { size, src, dest } => {
                 Instruction::Mov {
+                    size,
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Movsx { src_size, dest_size, src, dest } => {
+                Instruction::Movsx {
+                    src_size,
+                    dest_size,
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Movzx { src_size, dest_size, src, dest } => {
+                Instruction::Movzx {
+                    src_size,
+                    dest_size,
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Movsd { src, dest } => {
+                Instruction::Movsd {
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Cvtsi2sd { size, src, dest } => {
+                Instruction::Cvtsi2sd {
+                    size,
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Cvttsd2si { size, src, dest } => {
+                Instruction::Cvttsd2si {
+                    size,
                     src: self.handle_operand(src),
                     dest: self.handle_operand(dest),
                 }
             }
-            Instruction::Unary(operator, operand) => { Instruction::Unary(operator, self.handle_operand(operand)) }
-            Instruction::Binary(operator, src, dst) => { Instruction::Binary(operator, self.handle_operand(src), self.handle_operand(dst)) }
-            Instruction::Idiv(operand) => { Instruction::Idiv(self.handle_operand(operand)) }
+            Instruction::Lea { src, dest } => {
+                Instruction::Lea {
+                    src: self.handle_operand(src),
+                    dest: self.handle_operand(dest),
+                }
+            }
+            Instruction::Unary(operator, size, operand) => { Instruction::Unary(operator, size, self.handle_operand(operand)) }
+            Instruction::Binary(operator, size, src, dst) => { Instruction::Binary(operator, size, self.handle_operand(src), self.handle_operand(dst)) }
+            Instruction::Idiv(size, operand) => { Instruction::Idiv(size, self.handle_operand(operand)) }
+            Instruction::Div(size, operand) => { Instruction::Div(size, self.handle_operand(operand)) }
+            Instruction::Cmp(size, left, right) => { Instruction::Cmp(size, self.handle_operand(left), self.handle_operand(right)) }
+            Instruction::Comisd(left, right) => { Instruction::Comisd(self.handle_operand(left), self.handle_operand(right)) }
+            Instruction::SetCC(condition, operand) => { Instruction::SetCC(condition, self.handle_operand(operand)) }
+            Instruction::Push(operand) => { Instruction::Push(self.handle_operand(operand)) }
             instruction => instruction
         }
     }
 
     fn handle_operand(&mut self, operand: Operand) -> Operand {
         match operand {
-            Operand::Pseudo(pseudo_register) => { Operand::Stack(self.stack_allocator.get_stack_offset(pseudo_register.as_str())) }
+            Operand::Pseudo(pseudo_register) if self.static_variables.contains(&pseudo_register) => { Operand::Data(pseudo_register) }
+            Operand::Pseudo(pseudo_register) => { Operand::Stack(self.get_stack_offset(&pseudo_register)) }
+            Operand::PseudoMem { name, .. } if self.static_variables.contains(&name) => {
+                unreachable!("Only local arrays are initialized in place")
+            }
+            Operand::PseudoMem { name, offset } => { Operand::Stack(self.get_stack_offset(&name) + offset) }
             operand => operand
         }
     }
+
+    fn get_stack_offset(&mut self, pseudo_register: &str) -> i64 {
+        let layout = self.layouts[pseudo_register];
+        self.stack_allocator.get_stack_offset(pseudo_register, layout.size, layout.alignment)
+    }
 }
 
 impl AsmPass for PseudoRegister {
     fn run(&mut self, program: Program) -> Program {
-        let function = self.handle_function(program.into_function());
-        Program::from_function(function)
+        let (functions, static_variables, layouts) = program.decompose();
+        self.static_variables = static_variables.iter().map(|variable| variable.name().to_owned()).collect();
+        self.layouts = layouts;
+        let functions = functions.into_iter().map(|function| self.handle_function(function)).collect();
+        Program::new(functions, static_variables, std::mem::take(&mut self.layouts))
     }
 }
\ No newline at end of file
@@ -1,31 +1,56 @@
+use crate::lexer::Span;
+use std::fmt::{Display, Formatter};
+
 #[derive(Debug)]
 pub struct Program {
-    function: Function,
+    declarations: Vec<Declaration>,
 }
 
 impl Program {
-    pub fn new(function: Function) -> Self {
+    pub fn new(declarations: Vec<Declaration>) -> Self {
         Program {
-            function
+            declarations
         }
     }
 
-    pub fn function(&self) -> &Function {
-        &self.function
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    pub fn into_declarations(self) -> Vec<Declaration> {
+        self.declarations
     }
 }
 
+/**
+Function declaration, which is also a definition when it has a body.
+*/
 #[derive(Debug)]
 pub(crate) struct Function {
     name: String,
-    body: Statement,
+    return_type: Type,
+    params: Vec<Parameter>,
+    body: Option<Block>,
+    storage_class: Option<StorageClass>,
+    span: Span,
 }
 
 impl Function {
-    pub(crate) fn new(name: String, body: Statement) -> Function {
+    pub(crate) fn new(
+        name: String,
+        return_type: Type,
+        params: Vec<Parameter>,
+        body: Option<Block>,
+        storage_class: Option<StorageClass>,
+        span: Span,
+    ) -> Function {
         Function {
             name,
+            return_type,
+            params,
             body,
+            storage_class,
+            span,
         }
     }
 
@@ -33,40 +58,468 @@ impl Function {
         &self.name
     }
 
-    pub fn body(&self) -> &Statement {
-        &self.body
+    pub fn return_type(&self) -> &Type {
+        &self.return_type
+    }
+
+    pub fn params(&self) -> &[Parameter] {
+        &self.params
+    }
+
+    pub fn body(&self) -> Option<&Block> {
+        self.body.as_ref()
     }
+
+    pub fn storage_class(&self) -> Option<StorageClass> {
+        self.storage_class
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn decompose(self) -> (String, Type, Vec<Parameter>, Option<Block>, Option<StorageClass>, Span) {
+        (self.name, self.return_type, self.params, self.body, self.storage_class, self.span)
+    }
+}
+
+/**
+Parameter of a function, whose name can be left out when the function is only declared.
+*/
+#[derive(Debug)]
+pub(crate) struct Parameter {
+    pub name: Option<String>,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/**
+Compound statement, which opens a new scope.
+*/
+#[derive(Debug)]
+pub(crate) struct Block {
+    items: Vec<BlockItem>,
+}
+
+impl Block {
+    pub(crate) fn new(items: Vec<BlockItem>) -> Block {
+        Block {
+            items
+        }
+    }
+
+    pub fn items(&self) -> &[BlockItem] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<BlockItem> {
+        self.items
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum BlockItem {
+    Statement(Statement),
+    Declaration(Declaration),
+}
+
+#[derive(Debug)]
+pub(crate) enum Declaration {
+    Variable(VariableDeclaration),
+    Function(Function),
+}
+
+#[derive(Debug)]
+pub(crate) struct VariableDeclaration {
+    pub name: String,
+    pub ty: Type,
+    pub init: Option<Initializer>,
+    pub storage_class: Option<StorageClass>,
+    pub span: Span,
+}
+
+/**
+Initializer of a variable. A brace-enclosed list initializes the elements of an array in order, and the elements it
+leaves out are zero.
+*/
+#[derive(Debug)]
+pub(crate) enum Initializer {
+    Single(Expression),
+    // The span is the one of the opening brace
+    Compound(Vec<Initializer>, Span),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum StorageClass {
+    Static,
+    Extern,
 }
 
 #[derive(Debug)]
 pub(crate) enum Statement {
-    Return { expr: Expression }
+    Return { expr: Expression, span: Span },
+    Expression(Expression),
+    If { condition: Expression, then: Box<Statement>, otherwise: Option<Box<Statement>> },
+    Compound(Block),
+    While { condition: Expression, body: Box<Statement>, label: String },
+    DoWhile { body: Box<Statement>, condition: Expression, label: String },
+    For { init: Box<ForInit>, condition: Option<Expression>, post: Option<Expression>, body: Box<Statement>, label: String },
+    Switch { condition: Expression, body: Box<Statement>, label: String, cases: SwitchCases, span: Span },
+    Case { value: Expression, body: Box<Statement>, label: String, span: Span },
+    Default { body: Box<Statement>, label: String, span: Span },
+    Labeled { label: String, body: Box<Statement>, span: Span },
+    Goto { label: String, span: Span },
+    // The label of `break` and `continue` is the one of the statement they jump out of, set by loop labelling
+    Break { label: String, span: Span },
+    Continue { label: String, span: Span },
+    Null,
+}
+
+/**
+Targets of a `switch`, collected by loop labelling. The value of each `case` is converted to the type of the
+controlling expression.
+*/
+#[derive(Debug, Default)]
+pub(crate) struct SwitchCases {
+    pub cases: Vec<(i64, String)>,
+    pub default: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ForInit {
+    Declaration(Vec<VariableDeclaration>),
+    Expression(Option<Expression>),
 }
 
 #[derive(Debug)]
 pub(crate) enum Expression {
     Factor(Factor),
-    Binary { left: Box<Expression>, operator: BinaryOperator, right: Box<Expression> },
+    Binary { left: Box<Expression>, operator: BinaryOperator, right: Box<Expression>, span: Span },
+    Assignment { left: Box<Expression>, right: Box<Expression>, span: Span },
+    CompoundAssignment { operator: BinaryOperator, left: Box<Expression>, right: Box<Expression>, span: Span },
+    Conditional { condition: Box<Expression>, then: Box<Expression>, otherwise: Box<Expression>, span: Span },
 }
 
 #[derive(Debug)]
 pub(crate) enum Factor {
-    Constant(u64),
-    Unary(UnaryOperator, Box<Factor>),
+    Constant(Constant),
+    Var(String, Span),
+    Call(String, Vec<Expression>, Span),
+    StringLiteral(Vec<u8>),
+    Unary(UnaryOperator, Box<Factor>, Span),
+    Increment(IncrementOperator, Box<Factor>, Span),
+    AddressOf(Box<Factor>, Span),
+    Dereference(Box<Factor>, Span),
+    // `a[i]`, which is `*(a + i)`
+    Subscript(Box<Factor>, Box<Expression>, Span),
+    Cast(Type, Box<Factor>, Span),
     Expression(Box<Expression>),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Constant {
+    // Bits of a value of an integer or pointer type, zero-extended from the size of the type
+    Integer(u64, Type),
+    Double(f64),
+}
+
+impl Constant {
+    pub fn int(value: i32) -> Self {
+        Constant::Integer(value as u32 as u64, Type::Int)
+    }
+
+    /**
+    Constant of the integer type `ty` holding the lowest bits of `value`.
+    */
+    pub fn integer(value: u64, ty: Type) -> Self {
+        let bits = ty.size() * 8;
+        Constant::Integer(if bits == 64 { value } else { value & ((1 << bits) - 1) }, ty)
+    }
+
+    pub fn ty(&self) -> Type {
+        match self {
+            Constant::Integer(_, ty) => ty.clone(),
+            Constant::Double(_) => Type::Double,
+        }
+    }
+
+    /**
+    Value of an integer constant, whose bits are sign-extended when its type is signed.
+    */
+    pub fn integer_value(&self) -> Option<i128> {
+        match *self {
+            Constant::Integer(value, ref ty) if ty.is_signed() => {
+                let shift = 64 - ty.size() * 8;
+                Some(((value << shift) as i64 >> shift) as i128)
+            }
+            Constant::Integer(value, _) => Some(value as i128),
+            Constant::Double(_) => None,
+        }
+    }
+
+    pub fn to_double(&self) -> f64 {
+        match self {
+            Constant::Double(value) => *value,
+            constant => constant.integer_value().unwrap() as f64,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Constant::Integer(value, _) => *value == 0,
+            Constant::Double(value) => *value == 0.0,
+        }
+    }
+
+    /**
+    Convert the constant to `ty` the way a cast would. Integers wrap around to the size of the new type, and doubles
+    whose integer part doesn't fit in `ty` give `None`, since their conversion is undefined. A pointer constant only
+    comes from converting an integer, like the null pointer.
+    */
+    pub fn convert(&self, ty: Type) -> Option<Constant> {
+        match (self, ty) {
+            (_, Type::Double) => Some(Constant::Double(self.to_double())),
+            (Constant::Integer(..), ty) => Some(Constant::integer(self.integer_value().unwrap() as u64, ty)),
+            (Constant::Double(_), Type::Pointer(_)) => None,
+            (Constant::Double(value), ty) => {
+                let truncated = value.trunc();
+                let (min, max) = ty.range();
+                if truncated >= min as f64 && truncated <= max as f64 {
+                    Some(Constant::integer(truncated as i128 as u64, ty))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub(crate) enum Type {
+    // Plain `char` is a distinct type, but has the same representation as `signed char`
+    Char,
+    SChar,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Double,
+    Pointer(Box<Type>),
+    // Element type and number of elements, which is unknown for an array only declared with `extern`
+    Array(Box<Type>, Option<u64>),
+}
+
+impl Type {
+    /**
+    Size of the type in bytes, which is zero for an array of unknown size.
+    */
+    pub fn size(&self) -> u64 {
+        match self {
+            Type::Char | Type::SChar | Type::UChar => 1,
+            Type::Short | Type::UShort => 2,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+            Type::Array(element, count) => element.size() * count.unwrap_or(0),
+        }
+    }
+
+    /**
+    Alignment of the type in bytes. Scalars are aligned to their size, and arrays like their elements.
+    */
+    pub fn alignment(&self) -> u64 {
+        match self {
+            Type::Array(element, _) => element.alignment(),
+            ty => ty.size(),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Char | Type::SChar | Type::UChar | Type::Short | Type::UShort | Type::Int | Type::UInt | Type::Long | Type::ULong)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || *self == Type::Double
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Type::Array(..))
+    }
+
+    /**
+    Whether the size of the type is known. Only an array declared without a size is incomplete.
+    */
+    pub fn is_complete(&self) -> bool {
+        !matches!(self, Type::Array(_, None))
+    }
+
+    /**
+    Type of a name declared both with this type and with `other`, if they are compatible: an array of unknown size
+    takes the size given by the other declaration.
+    */
+    pub fn composite(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            _ if self == other => Some(self.clone()),
+            (Type::Array(element, count), Type::Array(other_element, other_count)) if element == other_element && (count.is_none() || other_count.is_none()) => {
+                Some(Type::Array(element.clone(), count.or(*other_count)))
+            }
+            _ => None,
+        }
+    }
+
+    /**
+    Type of the value of an expression of this type: an array decays to a pointer to its first element.
+    */
+    pub fn decay(self) -> Type {
+        match self {
+            Type::Array(element, _) => Type::Pointer(element),
+            ty => ty,
+        }
+    }
+
+    /**
+    Type of the object a pointer points to.
+    */
+    pub fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(referenced) => Some(referenced),
+            _ => None,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Char | Type::SChar | Type::Short | Type::Int | Type::Long | Type::Double)
+    }
+
+    /**
+    Smallest and largest values of an integer type.
+    */
+    pub fn range(&self) -> (i128, i128) {
+        let bits = self.size() * 8;
+        if self.is_signed() {
+            (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+        } else {
+            (0, (1 << bits) - 1)
+        }
+    }
+
+    /**
+    Integer promotion: the types smaller than `int` are promoted to `int`, which can represent all their values.
+    */
+    pub fn promote(&self) -> Type {
+        if self.is_integer() && self.size() < Type::Int.size() { Type::Int } else { self.clone() }
+    }
+
+    /**
+    Usual arithmetic conversions: the type in which a binary operation on `self` and `other` is done. Between two
+    promoted integer types, the larger one wins, and the unsigned one when they have the same size.
+    */
+    pub fn common(&self, other: &Type) -> Type {
+        let (left, right) = (self.promote(), other.promote());
+
+        if left == Type::Double || right == Type::Double {
+            Type::Double
+        } else if left == right {
+            left
+        } else if left.size() == right.size() {
+            if left.is_signed() { right } else { left }
+        } else if left.size() > right.size() {
+            left
+        } else {
+            right
+        }
+    }
+}
+
+impl Display for Type {
+    /**
+    Write the type the way it is spelled in a cast, with the derived types around the place of the name: `int **`,
+    `int *[3]`, `int (*)[3]`.
+    */
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut declarator = String::new();
+        let mut ty = self;
+        loop {
+            match ty {
+                Type::Pointer(referenced) => {
+                    declarator.insert(0, '*');
+                    // `[]` binds tighter than `*`
+                    if referenced.is_array() {
+                        declarator = format!("({})", declarator);
+                    }
+                    ty = referenced;
+                }
+                Type::Array(element, count) => {
+                    match count {
+                        Some(count) => declarator.push_str(&format!("[{}]", count)),
+                        None => declarator.push_str("[]"),
+                    }
+                    ty = element;
+                }
+                _ => break,
+            }
+        }
+
+        let name = match ty {
+            Type::Pointer(_) | Type::Array(..) => unreachable!(),
+            Type::Char => "char",
+            Type::SChar => "signed char",
+            Type::UChar => "unsigned char",
+            Type::Short => "short",
+            Type::UShort => "unsigned short",
+            Type::Int => "int",
+            Type::UInt => "unsigned int",
+            Type::Long => "long",
+            Type::ULong => "unsigned long",
+            Type::Double => "double",
+        };
+        match declarator.chars().next() {
+            None => write!(f, "{}", name),
+            Some('[') => write!(f, "{}{}", name, declarator),
+            Some(_) => write!(f, "{} {}", name, declarator),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum UnaryOperator {
     Complement,
     Negate,
+    Not,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IncrementOperator {
+    PrefixIncrement,
+    PrefixDecrement,
+    PostfixIncrement,
+    PostfixDecrement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum BinaryOperator {
     Add,
     Sub,
     Mul,
     Div,
     Rem,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
 }
\ No newline at end of file
@@ -1,83 +1,445 @@
 use crate::asm;
 use crate::asm::Instruction::Binary;
-use crate::asm::{Operand, Register};
+use crate::asm::{CondCode, Operand, Register, Size};
+use crate::ast::{Constant, Type};
 use crate::tacky;
+use std::collections::HashMap;
+
+type Symbols = HashMap<String, Type>;
+
+// Registers of the System V calling convention, in the order of the arguments they hold
+const INT_ARGUMENT_REGISTERS: [Register; 6] = [Register::DI, Register::SI, Register::DX, Register::CX, Register::R8, Register::R9];
+const DOUBLE_ARGUMENT_REGISTERS: [Register; 8] = [
+    Register::XMM0, Register::XMM1, Register::XMM2, Register::XMM3, Register::XMM4, Register::XMM5, Register::XMM6, Register::XMM7,
+];
 
 pub fn codegen(program: &tacky::Program) -> asm::Program {
+    let layouts = program.symbols().iter()
+        .map(|(name, ty)| (name.clone(), asm::Layout { size: ty.size() as i64, alignment: alignment(ty) }))
+        .collect();
+
     asm::Program::new(
-        codegen_function(program.function())
+        program.functions().iter().map(|function| codegen_function(function, program.symbols())).collect(),
+        program.static_variables().iter().map(|variable| codegen_static_variable(variable, program.symbols())).collect(),
+        layouts,
     )
 }
 
-fn codegen_function(function: &tacky::Function) -> asm::Function {
+/**
+Alignment of a variable of type `ty`. The System V ABI aligns the arrays of 16 bytes or more to 16 bytes, so that they
+can be accessed with SSE instructions.
+*/
+fn alignment(ty: &Type) -> i64 {
+    match ty {
+        Type::Array(..) if ty.size() >= 16 => 16,
+        ty => ty.alignment() as i64,
+    }
+}
+
+fn codegen_static_variable(variable: &tacky::StaticVariable, symbols: &Symbols) -> asm::StaticVariable {
+    let init = variable.init().map(|init| init.iter().map(|init| match *init {
+        tacky::StaticInit::Constant(Constant::Integer(value, ref ty)) => match size(ty) {
+            Size::Byte => asm::StaticInit::Byte(value as u8),
+            Size::Word => asm::StaticInit::Word(value as u16),
+            Size::Long => asm::StaticInit::Long(value as u32),
+            Size::Quad => asm::StaticInit::Quad(value),
+        },
+        tacky::StaticInit::Constant(Constant::Double(value)) => asm::StaticInit::Double(value),
+        tacky::StaticInit::Zero(bytes) => asm::StaticInit::Zero(bytes as i64),
+    }).collect());
+
+    let alignment = alignment(&symbols[variable.name()]);
+    asm::StaticVariable::new(variable.name().to_owned(), variable.global(), alignment, init)
+}
+
+fn codegen_function(function: &tacky::Function, symbols: &Symbols) -> asm::Function {
     let mut instruction = vec![];
+
+    // Copy the parameters from where the caller put them, the arguments passed on the stack are above the return
+    // address and the saved %rbp
+    let types: Vec<_> = function.params().iter().map(|param| symbols[param].clone()).collect();
+    let mut stack_offset = 16;
+    for ((param, ty), register) in function.params().iter().zip(&types).zip(argument_registers(&types)) {
+        let src = match register {
+            Some(register) => Operand::Register(register),
+            None => {
+                stack_offset += 8;
+                Operand::Stack(stack_offset - 8)
+            }
+        };
+
+        instruction.push(codegen_move(ty, src, Operand::Pseudo(param.clone())));
+    }
+
     for instr in function.instructions() {
-        codegen_instruction(instr, &mut instruction);
+        codegen_instruction(instr, symbols, &mut instruction);
     }
 
-    asm::Function::new(function.identifier().to_owned(), instruction)
+    asm::Function::new(function.identifier().to_owned(), function.global(), instruction)
 }
 
-fn codegen_instruction(instr: &tacky::Instruction, instructions: &mut Vec<asm::Instruction>) {
+fn codegen_instruction(instr: &tacky::Instruction, symbols: &Symbols, instructions: &mut Vec<asm::Instruction>) {
     match instr {
-        tacky::Instruction::Return { val } => codegen_ret(instructions, val),
-        tacky::Instruction::Unary { operator, src, dst } => codegen_unary(instructions, operator, src, dst),
-        tacky::Instruction::Binary { operator, lhs, rhs, dst } => codegen_binary(instructions, operator, lhs, rhs, dst)
+        tacky::Instruction::Return { val } => codegen_ret(instructions, symbols, val),
+        tacky::Instruction::Unary { operator, src, dst } => codegen_unary(instructions, symbols, operator, src, dst),
+        tacky::Instruction::Binary { operator, lhs, rhs, dst } => codegen_binary(instructions, symbols, operator, lhs, rhs, dst),
+        tacky::Instruction::SignExtend { src, dst } => {
+            instructions.push(asm::Instruction::Movsx {
+                src_size: size(&value_type(src, symbols)),
+                dest_size: size(&value_type(dst, symbols)),
+                src: codegen_operand(src),
+                dest: codegen_operand(dst),
+            })
+        }
+        tacky::Instruction::ZeroExtend { src, dst } => {
+            instructions.push(asm::Instruction::Movzx {
+                src_size: size(&value_type(src, symbols)),
+                dest_size: size(&value_type(dst, symbols)),
+                src: codegen_operand(src),
+                dest: codegen_operand(dst),
+            })
+        }
+        // Integers are little-endian, so the lowest bytes are the first ones in memory
+        tacky::Instruction::Truncate { src, dst } => {
+            instructions.push(codegen_copy(size(&value_type(dst, symbols)), codegen_operand(src), codegen_operand(dst)))
+        }
+        tacky::Instruction::IntToDouble { src, dst } => {
+            instructions.push(asm::Instruction::Cvtsi2sd {
+                size: size(&value_type(src, symbols)),
+                src: codegen_operand(src),
+                dest: codegen_operand(dst),
+            })
+        }
+        tacky::Instruction::DoubleToInt { src, dst } => {
+            instructions.push(asm::Instruction::Cvttsd2si {
+                size: size(&value_type(dst, symbols)),
+                src: codegen_operand(src),
+                dest: codegen_operand(dst),
+            })
+        }
+        tacky::Instruction::Copy { src, dst } => {
+            instructions.push(codegen_move(&value_type(src, symbols), codegen_operand(src), codegen_operand(dst)))
+        }
+        tacky::Instruction::GetAddress { src, dst } => {
+            instructions.push(asm::Instruction::Lea { src: codegen_operand(src), dest: codegen_operand(dst) })
+        }
+        // The pointer goes through %rax, which is never live across TACKY instructions
+        tacky::Instruction::Load { src_ptr, dst } => {
+            instructions.push(codegen_copy(Size::Quad, codegen_operand(src_ptr), Operand::Register(Register::AX)));
+            instructions.push(codegen_move(&value_type(dst, symbols), Operand::Memory { base: Register::AX, offset: 0 }, codegen_operand(dst)));
+        }
+        tacky::Instruction::Store { src, dst_ptr } => {
+            instructions.push(codegen_copy(Size::Quad, codegen_operand(dst_ptr), Operand::Register(Register::AX)));
+            instructions.push(codegen_move(&value_type(src, symbols), codegen_operand(src), Operand::Memory { base: Register::AX, offset: 0 }));
+        }
+        tacky::Instruction::AddPtr { ptr, index, scale, dst } => codegen_add_ptr(instructions, ptr, index, *scale, dst),
+        tacky::Instruction::CopyToOffset { src, dst, offset } => {
+            let tacky::Value::Var { identifier } = dst else { unreachable!("Only variables are initialized in place") };
+            let dest = Operand::PseudoMem { name: identifier.clone(), offset: *offset as i64 };
+            instructions.push(codegen_move(&value_type(src, symbols), codegen_operand(src), dest));
+        }
+        tacky::Instruction::FunCall { name, args, dst } => codegen_call(instructions, symbols, name, args, dst),
+        tacky::Instruction::Jump { target } => instructions.push(asm::Instruction::Jmp(target.clone())),
+        tacky::Instruction::JumpIfZero { condition, target } => {
+            instructions.push(asm::Instruction::Cmp(size(&value_type(condition, symbols)), Operand::Imm(0), codegen_operand(condition)));
+            instructions.push(asm::Instruction::JmpCC(CondCode::E, target.clone()));
+        }
+        tacky::Instruction::JumpIfNotZero { condition, target } => {
+            instructions.push(asm::Instruction::Cmp(size(&value_type(condition, symbols)), Operand::Imm(0), codegen_operand(condition)));
+            instructions.push(asm::Instruction::JmpCC(CondCode::NE, target.clone()));
+        }
+        tacky::Instruction::JumpTable { index, table, targets, default } => {
+            // Above is the unsigned comparison, which also catches the negative indices. Moving a 4-byte index clears
+            // the upper bytes of %rax.
+            let index_size = size(&value_type(index, symbols));
+            let index = codegen_operand(index);
+            instructions.push(asm::Instruction::Cmp(index_size, Operand::Imm(targets.len() as u64 - 1), index.clone()));
+            instructions.push(asm::Instruction::JmpCC(CondCode::A, default.clone()));
+            instructions.push(asm::Instruction::Mov { size: index_size, src: index, dest: Operand::Register(Register::AX) });
+            instructions.push(asm::Instruction::JmpTable { table: table.clone(), targets: targets.clone() });
+        }
+        tacky::Instruction::Label(label) => instructions.push(asm::Instruction::Label(label.clone())),
+    }
+}
+
+fn value_type(value: &tacky::Value, symbols: &Symbols) -> Type {
+    match value {
+        tacky::Value::Constant(constant) => constant.ty(),
+        tacky::Value::StringLiteral(_) => Type::Pointer(Box::new(Type::Char)),
+        tacky::Value::Var { identifier } => symbols[identifier].clone(),
     }
 }
 
-fn codegen_ret(instructions: &mut Vec<asm::Instruction>, val: &tacky::Value) {
-    let src_operand = codegen_operand(val);
+fn size(ty: &Type) -> Size {
+    match ty.size() {
+        1 => Size::Byte,
+        2 => Size::Word,
+        4 => Size::Long,
+        _ => Size::Quad,
+    }
+}
 
-    instructions.push(
-        asm::Instruction::Mov {
-            src: src_operand,
-            dest: asm::Operand::Register(asm::Register::AX),
+/**
+Register in which each argument of a call is passed, or `None` for the arguments passed on the stack.
+*/
+fn argument_registers(types: &[Type]) -> Vec<Option<Register>> {
+    let mut int_registers = INT_ARGUMENT_REGISTERS.iter();
+    let mut double_registers = DOUBLE_ARGUMENT_REGISTERS.iter();
+
+    types.iter().map(|ty| match ty {
+        Type::Double => double_registers.next().cloned(),
+        _ => int_registers.next().cloned(),
+    }).collect()
+}
+
+fn codegen_move(ty: &Type, src: asm::Operand, dest: asm::Operand) -> asm::Instruction {
+    match ty {
+        Type::Double => asm::Instruction::Movsd { src, dest },
+        ty => codegen_copy(size(ty), src, dest),
+    }
+}
+
+/**
+Call a function following the System V calling convention: arguments go in registers first, and the others are pushed
+on the stack from the last one, so that the first one ends up right above the return address.
+*/
+fn codegen_call(instructions: &mut Vec<asm::Instruction>, symbols: &Symbols, name: &str, args: &[tacky::Value], dst: &tacky::Value) {
+    let types: Vec<_> = args.iter().map(|arg| value_type(arg, symbols)).collect();
+    let registers = argument_registers(&types);
+
+    let stack_args: Vec<_> = args.iter().zip(&types).zip(&registers)
+        .filter(|(_, register)| register.is_none())
+        .map(|(arg, _)| arg)
+        .collect();
+
+    // The stack has to be 16-byte aligned at the call, once the arguments are pushed
+    let padding = if stack_args.len() % 2 == 1 { 8 } else { 0 };
+    let stack_size = 8 * stack_args.len() as i64 + padding;
+    if padding != 0 {
+        instructions.push(asm::Instruction::AllocateStack(padding));
+    }
+
+    for ((arg, ty), register) in args.iter().zip(&types).zip(registers) {
+        if let Some(register) = register {
+            instructions.push(codegen_move(ty, codegen_operand(arg), Operand::Register(register)));
         }
-    );
+    }
+
+    for (arg, ty) in stack_args.into_iter().rev() {
+        match (arg, codegen_operand(arg)) {
+            // The immediate is pushed as 8 bytes, so it has to hold the value rather than the bits of the type
+            (tacky::Value::Constant(constant @ Constant::Integer(..)), _) => {
+                instructions.push(asm::Instruction::Push(Operand::Imm(constant.integer_value().unwrap() as u64)));
+            }
+            (_, operand @ Operand::Register(_)) => instructions.push(asm::Instruction::Push(operand)),
+            // A string literal is an address, which has to be loaded with lea before being pushed
+            (_, operand) if ty.size() == 8 && !matches!(operand, Operand::StringLiteral(_)) => instructions.push(asm::Instruction::Push(operand)),
+            // Pushing a smaller type from memory would read past it
+            (_, operand) => {
+                instructions.push(codegen_move(ty, operand, Operand::Register(Register::AX)));
+                instructions.push(asm::Instruction::Push(Operand::Register(Register::AX)));
+            }
+        }
+    }
 
+    instructions.push(asm::Instruction::Call(name.to_owned()));
+
+    if stack_size != 0 {
+        instructions.push(asm::Instruction::DeallocateStack(stack_size));
+    }
+
+    let ty = value_type(dst, symbols);
+    let result = match ty {
+        Type::Double => Register::XMM0,
+        _ => Register::AX,
+    };
+    instructions.push(codegen_move(&ty, Operand::Register(result), codegen_operand(dst)));
+}
+
+/**
+Move a pointer by `index` elements of `scale` bytes. The pointer goes through %rax and the index through %rdx, so that
+the sum is computed with a single `lea`, which scales the index by 1, 2, 4 or 8. The other scales are multiplied in
+beforehand.
+*/
+fn codegen_add_ptr(instructions: &mut Vec<asm::Instruction>, ptr: &tacky::Value, index: &tacky::Value, scale: u64, dst: &tacky::Value) {
+    instructions.push(codegen_copy(Size::Quad, codegen_operand(ptr), Operand::Register(Register::AX)));
+
+    // A constant offset is the displacement of the address, if it fits in its 4 bytes
+    let offset = match index {
+        tacky::Value::Constant(constant) => constant.integer_value().and_then(|index| i32::try_from(index * scale as i128).ok()),
+        _ => None,
+    };
+
+    let src = match (index, offset) {
+        (_, Some(offset)) => Operand::Memory { base: Register::AX, offset: offset as i64 },
+        (index, None) => {
+            instructions.push(asm::Instruction::Mov { size: Size::Quad, src: codegen_operand(index), dest: Operand::Register(Register::DX) });
+            let scale = match scale {
+                1 | 2 | 4 | 8 => scale,
+                _ => {
+                    instructions.push(Binary(asm::BinaryOperator::Mul, Size::Quad, Operand::Imm(scale), Operand::Register(Register::DX)));
+                    1
+                }
+            };
+            Operand::Indexed { base: Register::AX, index: Register::DX, scale: scale as i64 }
+        }
+    };
+
+    instructions.push(asm::Instruction::Lea { src, dest: codegen_operand(dst) });
+}
+
+fn codegen_ret(instructions: &mut Vec<asm::Instruction>, symbols: &Symbols, val: &tacky::Value) {
+    let ty = value_type(val, symbols);
+    let result = if ty == Type::Double { Register::XMM0 } else { Register::AX };
+
+    instructions.push(codegen_move(&ty, codegen_operand(val), Operand::Register(result)));
     instructions.push(asm::Instruction::Ret);
 }
 
 fn codegen_operand(operand: &tacky::Value) -> asm::Operand {
     match operand {
         tacky::Value::Var { identifier } => asm::Operand::Pseudo(identifier.clone()),
-        tacky::Value::Constant(value) => asm::Operand::Imm(value.clone()),
+        tacky::Value::Constant(Constant::Integer(value, _)) => asm::Operand::Imm(*value),
+        tacky::Value::Constant(Constant::Double(value)) => asm::Operand::DoubleConstant(*value),
+        tacky::Value::StringLiteral(value) => asm::Operand::StringLiteral(value.clone()),
+    }
+}
+
+/**
+Copy the `size` lowest bytes of `src` into `dest`.
+
+The value of a string literal is its address, which has to be computed with `lea` rather than loaded with `mov`.
+*/
+fn codegen_copy(size: Size, src: asm::Operand, dest: asm::Operand) -> asm::Instruction {
+    if let asm::Operand::StringLiteral(_) = src {
+        asm::Instruction::Lea { src, dest }
+    } else {
+        asm::Instruction::Mov { size, src, dest }
     }
 }
 
-fn codegen_unary(instructions: &mut Vec<asm::Instruction>, operator: &tacky::UnaryOperator, src: &tacky::Value, dst: &tacky::Value) {
+fn codegen_unary(instructions: &mut Vec<asm::Instruction>, symbols: &Symbols, operator: &tacky::UnaryOperator, src: &tacky::Value, dst: &tacky::Value) {
     let src_operand = codegen_operand(src);
     let dst_operand = codegen_operand(dst);
-    let op = codegen_unary_op(operator);
 
     if let asm::Operand::Imm(_) = dst_operand {
         unreachable!("Destination operand cannot be a value");
     }
 
+    let ty = value_type(src, symbols);
+
+    if let tacky::UnaryOperator::Not = operator {
+        // The emitter has already compared doubles with zero
+        instructions.push(asm::Instruction::Cmp(size(&ty), Operand::Imm(0), src_operand));
+        codegen_set_condition(instructions, CondCode::E, dst_operand);
+
+        return;
+    }
+
+    if ty == Type::Double {
+        // Only the negation is defined on doubles, it flips the sign bit
+        instructions.push(
+            asm::Instruction::Movsd {
+                src: src_operand,
+                dest: dst_operand.clone(),
+            }
+        );
+
+        instructions.push(
+            Binary(asm::BinaryOperator::Xorpd, Size::Quad, Operand::DoubleConstant(-0.0), dst_operand)
+        );
+
+        return;
+    }
+
+    let op = codegen_unary_op(operator);
+
     instructions.push(
-        asm::Instruction::Mov {
-            src: src_operand,
-            dest: dst_operand.clone(),
-        }
+        codegen_copy(size(&ty), src_operand, dst_operand.clone())
     );
 
     instructions.push(
-        asm::Instruction::Unary(op, dst_operand)
+        asm::Instruction::Unary(op, size(&ty), dst_operand)
     );
 }
 
 fn codegen_unary_op(unary_op: &tacky::UnaryOperator) -> asm::UnaryOperator {
     match unary_op {
         tacky::UnaryOperator::Negate => asm::UnaryOperator::Neg,
-        tacky::UnaryOperator::Complement => asm::UnaryOperator::Not
+        tacky::UnaryOperator::Complement => asm::UnaryOperator::Not,
+        tacky::UnaryOperator::Not => unreachable!("The logical not is a comparison with zero"),
     }
 }
 
-fn codegen_binary(instructions: &mut Vec<asm::Instruction>, operator: &tacky::BinaryOperator, left: &tacky::Value, right: &tacky::Value, dst: &tacky::Value) {
+/**
+Set `dst` to 1 if the flags match `condition`, to 0 otherwise. `setcc` only writes the lowest byte of its operand, so
+the other bytes are cleared first, with a `mov` which leaves the flags as they are.
+*/
+fn codegen_set_condition(instructions: &mut Vec<asm::Instruction>, condition: CondCode, dst: asm::Operand) {
+    instructions.push(asm::Instruction::Mov { size: Size::Long, src: Operand::Imm(0), dest: dst.clone() });
+    instructions.push(asm::Instruction::SetCC(condition, dst));
+}
+
+/**
+Compare two doubles with `comisd`. When an operand is NaN, the flags are set as if they were equal, with the parity
+flag on top: `==` has to check that it's clear and `!=` that it's set. `<` and `<=` swap their operands so that NaN
+makes every ordered comparison false.
+*/
+fn codegen_double_comparison(instructions: &mut Vec<asm::Instruction>, operator: &tacky::BinaryOperator, left: asm::Operand, right: asm::Operand, dst: asm::Operand) {
+    let (src, dest, condition) = match operator {
+        tacky::BinaryOperator::Equal => (right, left, CondCode::E),
+        tacky::BinaryOperator::NotEqual => (right, left, CondCode::NE),
+        tacky::BinaryOperator::GreaterThan => (right, left, CondCode::A),
+        tacky::BinaryOperator::GreaterOrEqual => (right, left, CondCode::AE),
+        tacky::BinaryOperator::LessThan => (left, right, CondCode::A),
+        tacky::BinaryOperator::LessOrEqual => (left, right, CondCode::AE),
+        _ => unreachable!("{:?} is not a comparison", operator),
+    };
+
+    instructions.push(asm::Instruction::Comisd(src, dest));
+    codegen_set_condition(instructions, condition, dst.clone());
+
+    let (parity, combine) = match operator {
+        tacky::BinaryOperator::Equal => (CondCode::NP, asm::BinaryOperator::And),
+        tacky::BinaryOperator::NotEqual => (CondCode::P, asm::BinaryOperator::Or),
+        _ => return,
+    };
+    codegen_set_condition(instructions, parity, Operand::Register(Register::R11));
+    instructions.push(Binary(combine, Size::Long, Operand::Register(Register::R11), dst));
+}
+
+fn codegen_binary(instructions: &mut Vec<asm::Instruction>, symbols: &Symbols, operator: &tacky::BinaryOperator, left: &tacky::Value, right: &tacky::Value, dst: &tacky::Value) {
     let left_operand = codegen_operand(left);
     let right_operand = codegen_operand(right);
     let dst_operand = codegen_operand(dst);
+    let ty = value_type(left, symbols);
+    let size = size(&ty);
+
+    if operator.is_comparison() {
+        if ty == Type::Double {
+            codegen_double_comparison(instructions, operator, left_operand, right_operand, dst_operand);
+        } else {
+            instructions.push(asm::Instruction::Cmp(size, right_operand, left_operand));
+            codegen_set_condition(instructions, codegen_condition(operator, ty), dst_operand);
+        }
+
+        return;
+    }
+
+    if ty == Type::Double {
+        instructions.push(
+            asm::Instruction::Movsd {
+                src: left_operand,
+                dest: dst_operand.clone(),
+            }
+        );
+
+        instructions.push(
+            Binary(codegen_double_binary_op(operator), size, right_operand, dst_operand)
+        );
+
+        return;
+    }
 
     match operator {
         tacky::BinaryOperator::Add
@@ -86,48 +448,72 @@ fn codegen_binary(instructions: &mut Vec<asm::Instruction>, operator: &tacky::Bi
             let op = codegen_binary_op(operator);
 
             instructions.push(
-                asm::Instruction::Mov {
-                    src: left_operand,
-                    dest: dst_operand.clone(),
-                }
+                codegen_copy(size, left_operand, dst_operand.clone())
             );
 
             instructions.push(
-                Binary(op, right_operand, dst_operand)
+                Binary(op, size, right_operand, dst_operand)
             )
         }
 
-        tacky::BinaryOperator::Divide
-        | tacky::BinaryOperator::Remainder => {
-            instructions.push(asm::Instruction::Mov {
-                src: left_operand,
-                dest: asm::Operand::Register(Register::AX),
-            });
+        tacky::BinaryOperator::BitwiseAnd
+        | tacky::BinaryOperator::BitwiseOr
+        | tacky::BinaryOperator::BitwiseXor => {
+            instructions.push(
+                codegen_copy(size, left_operand, dst_operand.clone())
+            );
 
             instructions.push(
-                asm::Instruction::Cdq
+                Binary(codegen_binary_op(operator), size, right_operand, dst_operand)
+            )
+        }
+
+        tacky::BinaryOperator::ShiftLeft
+        | tacky::BinaryOperator::ShiftRight => {
+            // The count of a shift is either an immediate or %cl, and only its lowest bits are used whatever its type
+            let count = match right_operand {
+                Operand::Imm(count) => Operand::Imm(count & 0xff),
+                count => {
+                    instructions.push(codegen_move(&value_type(right, symbols), count, Operand::Register(Register::CX)));
+                    Operand::Register(Register::CX)
+                }
+            };
+
+            instructions.push(
+                codegen_copy(size, left_operand, dst_operand.clone())
             );
 
             instructions.push(
-                asm::Instruction::Idiv(right_operand)
+                Binary(codegen_shift_op(operator, ty), size, count, dst_operand)
+            )
+        }
+
+        tacky::BinaryOperator::Divide
+        | tacky::BinaryOperator::Remainder => {
+            instructions.push(
+                codegen_copy(size, left_operand, asm::Operand::Register(Register::AX))
             );
 
-            if *operator == tacky::BinaryOperator::Divide {
-                instructions.push(
-                    asm::Instruction::Mov {
-                        src: Operand::Register(Register::AX),
-                        dest: dst_operand,
-                    }
-                );
+            // The dividend spans %edx:%eax, whose upper half is the sign extension of the lower one for a signed
+            // division, and zero for an unsigned one
+            if ty.is_signed() {
+                instructions.push(asm::Instruction::Cdq(size));
+                instructions.push(asm::Instruction::Idiv(size, right_operand));
             } else {
-                instructions.push(
-                    asm::Instruction::Mov {
-                        src: Operand::Register(Register::DX),
-                        dest: dst_operand,
-                    }
-                );
+                instructions.push(asm::Instruction::Mov { size, src: Operand::Imm(0), dest: Operand::Register(Register::DX) });
+                instructions.push(asm::Instruction::Div(size, right_operand));
             }
+
+            let result = if *operator == tacky::BinaryOperator::Divide { Register::AX } else { Register::DX };
+            instructions.push(
+                asm::Instruction::Mov {
+                    size,
+                    src: Operand::Register(result),
+                    dest: dst_operand,
+                }
+            );
         }
+        _ => unreachable!("Comparisons are handled separately"),
     }
 }
 
@@ -136,6 +522,58 @@ fn codegen_binary_op(binary_op: &tacky::BinaryOperator) -> asm::BinaryOperator {
         tacky::BinaryOperator::Add => asm::BinaryOperator::Add,
         tacky::BinaryOperator::Subtract => asm::BinaryOperator::Sub,
         tacky::BinaryOperator::Multiply => asm::BinaryOperator::Mul,
-        _ => { unreachable!("Division and remainder are handled separately"); }
+        tacky::BinaryOperator::BitwiseAnd => asm::BinaryOperator::And,
+        tacky::BinaryOperator::BitwiseOr => asm::BinaryOperator::Or,
+        tacky::BinaryOperator::BitwiseXor => asm::BinaryOperator::Xor,
+        _ => { unreachable!("Division, remainder, shifts and comparisons are handled separately"); }
+    }
+}
+
+/**
+Right shifts copy the sign bit of signed operands (arithmetic shift), and shift zeros in otherwise (logical shift).
+*/
+fn codegen_shift_op(binary_op: &tacky::BinaryOperator, ty: Type) -> asm::BinaryOperator {
+    match binary_op {
+        _ if ty == Type::Double => unreachable!("Doubles can't be shifted"),
+        tacky::BinaryOperator::ShiftLeft => asm::BinaryOperator::Sal,
+        tacky::BinaryOperator::ShiftRight if ty.is_signed() => asm::BinaryOperator::Sar,
+        tacky::BinaryOperator::ShiftRight => asm::BinaryOperator::Shr,
+        _ => unreachable!("{:?} is not a shift", binary_op),
     }
-}
\ No newline at end of file
+}
+
+/**
+Condition under which a comparison of integers of type `ty` holds. Unsigned integers are compared with the conditions
+on the carry flag, below and above, rather than on the sign flag.
+*/
+fn codegen_condition(binary_op: &tacky::BinaryOperator, ty: Type) -> CondCode {
+    match (binary_op, ty.is_signed()) {
+        (tacky::BinaryOperator::Equal, _) => CondCode::E,
+        (tacky::BinaryOperator::NotEqual, _) => CondCode::NE,
+        (tacky::BinaryOperator::LessThan, true) => CondCode::L,
+        (tacky::BinaryOperator::LessOrEqual, true) => CondCode::LE,
+        (tacky::BinaryOperator::GreaterThan, true) => CondCode::G,
+        (tacky::BinaryOperator::GreaterOrEqual, true) => CondCode::GE,
+        (tacky::BinaryOperator::LessThan, false) => CondCode::B,
+        (tacky::BinaryOperator::LessOrEqual, false) => CondCode::BE,
+        (tacky::BinaryOperator::GreaterThan, false) => CondCode::A,
+        (tacky::BinaryOperator::GreaterOrEqual, false) => CondCode::AE,
+        _ => unreachable!("{:?} is not a comparison", binary_op),
+    }
+}
+
+fn codegen_double_binary_op(binary_op: &tacky::BinaryOperator) -> asm::BinaryOperator {
+    match binary_op {
+        tacky::BinaryOperator::Add => asm::BinaryOperator::Addsd,
+        tacky::BinaryOperator::Subtract => asm::BinaryOperator::Subsd,
+        tacky::BinaryOperator::Multiply => asm::BinaryOperator::Mulsd,
+        tacky::BinaryOperator::Divide => asm::BinaryOperator::Divsd,
+        tacky::BinaryOperator::Remainder
+        | tacky::BinaryOperator::BitwiseAnd
+        | tacky::BinaryOperator::BitwiseOr
+        | tacky::BinaryOperator::BitwiseXor
+        | tacky::BinaryOperator::ShiftLeft
+        | tacky::BinaryOperator::ShiftRight => { unreachable!("{:?} is not defined on doubles", binary_op); }
+        _ => { unreachable!("Comparisons are handled separately"); }
+    }
+}
@@ -67,9 +67,9 @@ impl LineWriter {
     }
 }
 
-impl Into<String> for CodeWriter {
-    fn into(self) -> String {
-        self.output
+impl From<CodeWriter> for String {
+    fn from(writer: CodeWriter) -> Self {
+        writer.output
     }
 }
 
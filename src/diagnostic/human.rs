@@ -0,0 +1,131 @@
+use crate::diagnostic::{Diagnostic, SourceMap};
+use crate::lexer::Span;
+
+/**
+Line of source code containing a span, along with the position of the span in that line.
+*/
+struct Snippet {
+    line_number: usize,
+    text: String,
+    // Whitespace that puts a marker under the first character of the span, tabs included
+    padding: String,
+    width: usize,
+}
+
+impl Snippet {
+    fn new(span: &Span, sources: &SourceMap) -> Option<Self> {
+        let source = sources.get(span.file())?;
+        if span.start() > source.len() || !source.is_char_boundary(span.start()) {
+            return None;
+        }
+
+        let line_start = source[..span.start()].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[span.start()..].find('\n').map_or(source.len(), |index| span.start() + index);
+        let end = span.end().clamp(span.start(), line_end);
+
+        let padding = source[line_start..span.start()].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        Some(Snippet {
+            line_number: span.line(),
+            text: source[line_start..line_end].trim_end_matches('\r').to_owned(),
+            padding,
+            width: source[span.start()..end].chars().count().max(1),
+        })
+    }
+}
+
+/**
+Render a diagnostic for humans, quoting the labelled source lines like rustc does:
+
+```text
+error[E0201]: Expected token Semicolon, got ClosingBrace
+ --> main.c:2:14
+  |
+2 |     return 0 }
+  |              ^
+```
+*/
+pub fn render_human(diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+    let mut output = format!("{}[{}]: {}\n", diagnostic.severity(), diagnostic.code(), diagnostic.message());
+
+    let gutter = diagnostic.labels().iter().map(|label| label.span().line())
+        .chain(diagnostic.suggestions().iter().map(|suggestion| suggestion.span().line()))
+        .max()
+        .map_or(1, |line| line.to_string().len());
+    let blank = " ".repeat(gutter);
+
+    // Labels are grouped by file, in the order the files are first labelled, and sorted by line in each file
+    let mut files: Vec<&str> = vec![];
+    for label in diagnostic.labels() {
+        if !files.contains(&label.span().file()) {
+            files.push(label.span().file());
+        }
+    }
+    let mut labels: Vec<_> = diagnostic.labels().iter().collect();
+    labels.sort_by_key(|label| (files.iter().position(|file| *file == label.span().file()), label.span().line()));
+
+    let mut previous: Option<&Span> = None;
+    for (index, label) in labels.into_iter().enumerate() {
+        let span = label.span();
+        let same_file = previous.is_some_and(|previous| previous.file() == span.file());
+        let same_line = same_file && previous.is_some_and(|previous| previous.line() == span.line());
+
+        if !same_file {
+            // The first arrow gives the location of the problem itself
+            let (arrow, location) = match diagnostic.primary_span() {
+                Some(primary) if index == 0 && primary.file() == span.file() => ("-->", primary),
+                _ if index == 0 => ("-->", span),
+                _ => (":::", span),
+            };
+            output.push_str(&format!("{}{} {}\n", blank, arrow, location));
+        }
+
+        if let Some(snippet) = Snippet::new(span, sources) {
+            if !same_line {
+                if !same_file {
+                    output.push_str(&format!("{} |\n", blank));
+                }
+                output.push_str(&format!("{:>gutter$} | {}\n", snippet.line_number, snippet.text));
+            }
+
+            let marker = if label.is_primary() { "^" } else { "-" };
+            let underline = format!("{}{}", snippet.padding, marker.repeat(snippet.width));
+            let line = format!("{} | {} {}", blank, underline, label.message());
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+
+        previous = Some(span);
+    }
+
+    if !diagnostic.notes().is_empty() && previous.is_some() {
+        output.push_str(&format!("{} |\n", blank));
+    }
+    for note in diagnostic.notes() {
+        output.push_str(&format!("{} = note: {}\n", blank, note));
+    }
+
+    for suggestion in diagnostic.suggestions() {
+        let span = suggestion.span();
+        match Snippet::new(span, sources).filter(|_| !suggestion.replacement().contains('\n')) {
+            Some(snippet) => {
+                // Show the line once the suggestion is applied, with the new text underlined
+                let column = snippet.padding.chars().count();
+                let replaced_width = if span.start() == span.end() { 0 } else { snippet.width };
+                let prefix: String = snippet.text.chars().take(column).collect();
+                let suffix: String = snippet.text.chars().skip(column + replaced_width).collect();
+
+                let marker = if replaced_width == 0 { "+" } else { "~" };
+                output.push_str(&format!("help: {}\n", suggestion.message()));
+                output.push_str(&format!("{} |\n", blank));
+                output.push_str(&format!("{:>gutter$} | {}{}{}\n", snippet.line_number, prefix, suggestion.replacement(), suffix));
+                output.push_str(&format!("{} | {}{}\n", blank, snippet.padding, marker.repeat(suggestion.replacement().chars().count().max(1))));
+            }
+            None => output.push_str(&format!("{} = help: {}: `{}`\n", blank, suggestion.message(), suggestion.replacement())),
+        }
+    }
+
+    output
+}
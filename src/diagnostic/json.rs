@@ -0,0 +1,71 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+
+/**
+Render a diagnostic as a single line JSON object, for tools:
+
+```text
+{"code":"E0201","severity":"error","message":"Expected token Semicolon, got ClosingBrace",
+ "spans":[{"file":"main.c","line":2,"column":14,"byte_start":30,"byte_end":31,"is_primary":true,"label":""}],
+ "notes":[],"fixes":[{"message":"add a semicolon","span":{...},"replacement":";"}]}
+```
+
+Byte offsets are relative to the start of the file, and `byte_end` is exclusive.
+*/
+pub fn render_json(diagnostic: &Diagnostic) -> String {
+    let spans: Vec<_> = diagnostic.labels().iter().map(|label| {
+        format!(
+            "{},\"is_primary\":{},\"label\":{}}}",
+            span_fields(label.span()), label.is_primary(), string(label.message())
+        )
+    }).collect();
+
+    let notes: Vec<_> = diagnostic.notes().iter().map(|note| string(note)).collect();
+
+    let fixes: Vec<_> = diagnostic.suggestions().iter().map(|suggestion| {
+        format!(
+            "{{\"message\":{},\"span\":{}}},\"replacement\":{}}}",
+            string(suggestion.message()), span_fields(suggestion.span()), string(suggestion.replacement())
+        )
+    }).collect();
+
+    format!(
+        "{{\"code\":{},\"severity\":{},\"message\":{},\"spans\":[{}],\"notes\":[{}],\"fixes\":[{}]}}\n",
+        string(diagnostic.code().as_str()),
+        string(&diagnostic.severity().to_string()),
+        string(diagnostic.message()),
+        spans.join(","),
+        notes.join(","),
+        fixes.join(","),
+    )
+}
+
+/**
+Fields of a span object, without the closing brace so that more fields can follow.
+*/
+fn span_fields(span: &Span) -> String {
+    format!(
+        "{{\"file\":{},\"line\":{},\"column\":{},\"byte_start\":{},\"byte_end\":{}",
+        string(span.file()), span.line(), span.column(), span.start(), span.end()
+    )
+}
+
+fn string(value: &str) -> String {
+    let mut output = String::with_capacity(value.len() + 2);
+    output.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+    output
+}
@@ -0,0 +1,363 @@
+use crate::lexer::Span;
+use clap::ValueEnum;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+mod human;
+mod json;
+
+pub use self::human::*;
+pub use self::json::*;
+
+/**
+How diagnostics are printed on the standard error.
+*/
+#[derive(Debug, Eq, PartialEq, Copy, Clone, ValueEnum)]
+pub enum DiagnosticFormat {
+    Human,
+    Json,
+}
+
+impl DiagnosticFormat {
+    pub fn render(&self, diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+        match self {
+            DiagnosticFormat::Human => render_human(diagnostic, sources),
+            DiagnosticFormat::Json => render_json(diagnostic),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/**
+Kind of a diagnostic. Each kind has a code which never changes, so that tools can rely on it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    // Lexer
+    UnknownToken,
+    UnterminatedComment,
+    UnterminatedLiteral,
+    InvalidEscapeSequence,
+    InvalidCharacterConstant,
+    InvalidIntegerConstant,
+    IntegerConstantTooLarge,
+    InvalidFloatingConstant,
+    Unsupported,
+    RequiresNewerStandard,
+
+    // Preprocessor
+    InvalidDirective,
+    IncludeNotFound,
+    InvalidInclude,
+    IncludeTooDeep,
+    UnreadableFile,
+    ErrorDirective,
+    InvalidMacroDefinition,
+    MacroArgumentCount,
+    UnterminatedMacroInvocation,
+    InvalidTokenPaste,
+    UnterminatedConditional,
+    UnbalancedConditional,
+    InvalidPreprocessorExpression,
+    DivisionByZero,
+    WarningDirective,
+    MacroRedefined,
+
+    // Parser
+    UnexpectedToken,
+    UnexpectedEndOfFile,
+    InvalidArraySize,
+    NestedFunctionDefinition,
+    MissingParameterName,
+    ArrayReturnType,
+
+    // Semantic analysis
+    InvalidOperands,
+    UndeclaredIdentifier,
+    DuplicateDeclaration,
+    InvalidLvalue,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    CaseOutsideSwitch,
+    InvalidCaseLabel,
+    DuplicateCase,
+    UndefinedLabel,
+    DuplicateLabel,
+    UnusedLabel,
+    NotAFunction,
+    FunctionAsValue,
+    ConflictingTypes,
+    ArgumentCount,
+    ConflictingLinkage,
+    NonConstantInitializer,
+    InvalidStorageClass,
+    IncompatibleTypes,
+    InvalidInitializer,
+
+    // Assembler and linker
+    AssemblerFailed,
+    AssemblerWarning,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnknownToken => "E0001",
+            Code::UnterminatedComment => "E0002",
+            Code::UnterminatedLiteral => "E0003",
+            Code::InvalidEscapeSequence => "E0004",
+            Code::InvalidCharacterConstant => "E0005",
+            Code::InvalidIntegerConstant => "E0006",
+            Code::IntegerConstantTooLarge => "E0007",
+            Code::InvalidFloatingConstant => "E0008",
+            Code::Unsupported => "E0009",
+            Code::RequiresNewerStandard => "E0010",
+
+            Code::InvalidDirective => "E0101",
+            Code::IncludeNotFound => "E0102",
+            Code::InvalidInclude => "E0103",
+            Code::IncludeTooDeep => "E0104",
+            Code::UnreadableFile => "E0105",
+            Code::ErrorDirective => "E0106",
+            Code::InvalidMacroDefinition => "E0107",
+            Code::MacroArgumentCount => "E0108",
+            Code::UnterminatedMacroInvocation => "E0109",
+            Code::InvalidTokenPaste => "E0110",
+            Code::UnterminatedConditional => "E0111",
+            Code::UnbalancedConditional => "E0112",
+            Code::InvalidPreprocessorExpression => "E0113",
+            Code::DivisionByZero => "E0114",
+            Code::WarningDirective => "W0101",
+            Code::MacroRedefined => "W0102",
+
+            Code::UnexpectedToken => "E0201",
+            Code::UnexpectedEndOfFile => "E0202",
+            Code::InvalidArraySize => "E0203",
+            Code::NestedFunctionDefinition => "E0204",
+            Code::MissingParameterName => "E0205",
+            Code::ArrayReturnType => "E0206",
+
+            Code::InvalidOperands => "E0301",
+            Code::UndeclaredIdentifier => "E0302",
+            Code::DuplicateDeclaration => "E0303",
+            Code::InvalidLvalue => "E0304",
+            Code::BreakOutsideLoop => "E0305",
+            Code::ContinueOutsideLoop => "E0306",
+            Code::CaseOutsideSwitch => "E0307",
+            Code::InvalidCaseLabel => "E0308",
+            Code::DuplicateCase => "E0309",
+            Code::UndefinedLabel => "E0310",
+            Code::DuplicateLabel => "E0311",
+            Code::UnusedLabel => "W0301",
+            Code::NotAFunction => "E0312",
+            Code::FunctionAsValue => "E0313",
+            Code::ConflictingTypes => "E0314",
+            Code::ArgumentCount => "E0315",
+            Code::ConflictingLinkage => "E0316",
+            Code::NonConstantInitializer => "E0317",
+            Code::InvalidStorageClass => "E0318",
+            Code::IncompatibleTypes => "E0319",
+            Code::InvalidInitializer => "E0320",
+
+            Code::AssemblerFailed => "E0401",
+            Code::AssemblerWarning => "W0401",
+        }
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/**
+Span of source code highlighted by a diagnostic. The primary label points at the problem itself, secondary ones give
+context.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    span: Span,
+    message: String,
+    primary: bool,
+}
+
+impl Label {
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+}
+
+/**
+Edit of the source code which would fix the problem.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    message: String,
+    span: Span,
+    replacement: String,
+}
+
+impl Suggestion {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    code: Code,
+    message: String,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+    suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    fn new(severity: Severity, code: Code, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    /**
+    Point at the problem. When the span comes from a macro expansion, every macro invocation which led to it is
+    labelled as well.
+    */
+    pub fn with_primary_label(mut self, span: &Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span: span.clone(), message: message.into(), primary: true });
+        self.with_expansion_labels(span)
+    }
+
+    /**
+    Label every macro invocation which led to `span`.
+    */
+    pub fn with_expansion_labels(mut self, span: &Span) -> Self {
+        let mut expansion = span.expansion();
+        while let Some((macro_name, call_site)) = expansion {
+            let message = format!("in expansion of macro `{}`", macro_name);
+            self.labels.push(Label { span: call_site.clone(), message, primary: false });
+            expansion = call_site.expansion();
+        }
+
+        self
+    }
+
+    /**
+    Move the diagnostic of a token lexed from a spelling rather than from a file, like the result of `##`, to `span`.
+    Its labels and suggestions pointed into the spelling, which can't be quoted. The macro invocations are labelled
+    when the token is reported.
+    */
+    pub fn relocated(mut self, span: &Span) -> Self {
+        self.labels = vec![Label { span: span.clone(), message: String::new(), primary: true }];
+        self.suggestions.clear();
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: &Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span: span.clone(), message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, message: impl Into<String>, span: &Span, replacement: impl Into<String>) -> Self {
+        self.suggestions.push(Suggestion { message: message.into(), span: span.clone(), replacement: replacement.into() });
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| &label.span)
+    }
+}
+
+/**
+Text of every file read during the compilation, to quote it in diagnostics.
+*/
+#[derive(Default)]
+pub struct SourceMap {
+    files: RefCell<HashMap<Rc<str>, Rc<str>>>,
+}
+
+impl SourceMap {
+    pub fn add(&self, file: &str, text: &str) {
+        self.files.borrow_mut().insert(file.into(), text.into());
+    }
+
+    pub fn get(&self, file: &str) -> Option<Rc<str>> {
+        self.files.borrow().get(file).cloned()
+    }
+}
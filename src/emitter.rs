@@ -1,39 +1,233 @@
 use crate::asm;
-use crate::asm::{BinaryOperator, Instruction, Operand, Register, UnaryOperator};
+use crate::asm::{BinaryOperator, CondCode, Instruction, Operand, Register, Size, StaticInit, StaticVariable, UnaryOperator};
 use crate::codewriter::{CodeWriter, LineWriter};
+use std::collections::{HashMap, HashSet};
+
+/**
+Constants referenced by the program that live in the read-only section, each with its unique local label.
+*/
+struct ReadOnlyData {
+    labels: HashMap<Vec<u8>, String>,
+    string_literals: Vec<Vec<u8>>,
+
+    // Doubles are identified by their bit pattern so that 0.0 and -0.0 are distinct
+    double_labels: HashMap<u64, String>,
+    doubles: Vec<f64>,
+
+    // Targets of each jump table, in the order of the entries
+    jump_tables: Vec<(String, Vec<String>)>,
+}
+
+impl ReadOnlyData {
+    fn collect(program: &asm::Program) -> Self {
+        let mut read_only_data = ReadOnlyData {
+            labels: HashMap::new(),
+            string_literals: vec![],
+            double_labels: HashMap::new(),
+            doubles: vec![],
+            jump_tables: vec![],
+        };
+
+        let instructions = || program.functions().iter().flat_map(|function| function.instructions());
+
+        for operand in instructions().flat_map(Instruction::operands) {
+            match operand {
+                Operand::StringLiteral(value) => read_only_data.insert_string_literal(value),
+                Operand::DoubleConstant(value) => read_only_data.insert_double(*value),
+                _ => {}
+            }
+        }
+
+        for instruction in instructions() {
+            if let Instruction::JmpTable { table, targets } = instruction {
+                read_only_data.jump_tables.push((table.clone(), targets.clone()));
+            }
+        }
+
+        read_only_data
+    }
+
+    fn insert_string_literal(&mut self, value: &[u8]) {
+        if !self.labels.contains_key(value) {
+            self.labels.insert(value.to_owned(), format!(".Lstr.{}", self.string_literals.len()));
+            self.string_literals.push(value.to_owned());
+        }
+    }
+
+    fn insert_double(&mut self, value: f64) {
+        if !self.double_labels.contains_key(&value.to_bits()) {
+            self.double_labels.insert(value.to_bits(), format!(".Ldbl.{}", self.doubles.len()));
+            self.doubles.push(value);
+        }
+    }
+
+    fn string_literal_label(&self, value: &[u8]) -> &str {
+        self.labels[value].as_str()
+    }
+
+    fn double_label(&self, value: f64) -> &str {
+        self.double_labels[&value.to_bits()].as_str()
+    }
+}
 
 pub fn emit(writer: &mut CodeWriter, program: &asm::Program) {
-    emit_function(writer, program.function());
+    let read_only_data = ReadOnlyData::collect(program);
+
+    // Functions which aren't defined here are called through the procedure linkage table
+    let defined: HashSet<_> = program.functions().iter().map(|function| function.name()).collect();
+
+    writer.write_line(".text");
+    for function in program.functions() {
+        emit_function(writer, function, &read_only_data, &defined);
+        writer.blank_line();
+    }
+    for variable in program.static_variables() {
+        emit_static_variable(writer, variable);
+    }
+    emit_read_only_data(writer, &read_only_data);
+    writer.write_line(".section .note.GNU-stack,\"\",@progbits");
+}
+
+fn emit_read_only_data(writer: &mut CodeWriter, read_only_data: &ReadOnlyData) {
+    if read_only_data.string_literals.is_empty() && read_only_data.doubles.is_empty() && read_only_data.jump_tables.is_empty() {
+        return;
+    }
+
+    writer.write_line(".section .rodata");
+    for value in &read_only_data.string_literals {
+        writer.write_line(format!("{}:", read_only_data.string_literal_label(value)).as_str());
+        writer.write_block(|writer| {
+            writer.line(|writer| {
+                writer.write(".asciz \"");
+                for byte in value {
+                    match byte {
+                        b'"' | b'\\' => writer.write(format!("\\{}", *byte as char).as_str()),
+                        0x20..=0x7E => writer.write((*byte as char).to_string().as_str()),
+                        _ => writer.write(format!("\\{:03o}", byte).as_str()),
+                    }
+                }
+                writer.write("\"");
+            })
+        });
+    }
+
+    for value in &read_only_data.doubles {
+        // xorpd reads 16 bytes from memory, which have to be aligned
+        writer.write_line(".balign 16");
+        writer.write_line(format!("{}:", read_only_data.double_label(*value)).as_str());
+        writer.write_block(|writer| {
+            writer.write_line(format!(".quad {:#018x}", value.to_bits()).as_str());
+        });
+    }
+
+    // Entries are offsets from the table rather than addresses, so that the table needs no relocation
+    for (table, targets) in &read_only_data.jump_tables {
+        writer.write_line(".balign 4");
+        writer.write_line(format!("{}:", local_label(table)).as_str());
+        writer.write_block(|writer| {
+            for target in targets {
+                writer.write_line(format!(".long {}-{}", local_label(target), local_label(table)).as_str());
+            }
+        });
+    }
     writer.blank_line();
-    //writer.write_line(".section .note.GNU-stack,\"\",@progbits");
 }
 
-fn emit_function(writer: &mut CodeWriter, function: &asm::Function) {
-    let function_name = if function.name() == "main" {
-        "_main"
-    } else {
-        function.name()
-    };
+/**
+Variables which are zero go in the section which the loader zeroes, so they take no room in the object file. Variables
+defined in another file are left to the linker.
+*/
+fn emit_static_variable(writer: &mut CodeWriter, variable: &StaticVariable) {
+    let Some(init) = variable.init() else { return };
+    let is_zero = init.iter().all(StaticInit::is_zero);
 
-    writer.write_line(format!(".global {}", function_name).as_str());
-    writer.write_line(format!("{}:", function_name).as_str());
+    if variable.global() {
+        writer.write_line(format!(".globl {}", variable.name()).as_str());
+    }
+    writer.write_line(if is_zero { ".bss" } else { ".data" });
+
+    writer.write_line(format!(".balign {}", variable.alignment()).as_str());
+    writer.write_line(format!("{}:", variable.name()).as_str());
+    writer.write_block(|writer| {
+        if is_zero {
+            writer.write_line(format!(".zero {}", init.iter().map(StaticInit::bytes).sum::<i64>()).as_str());
+            return;
+        }
+
+        for init in init {
+            match init {
+                StaticInit::Byte(value) => writer.write_line(format!(".byte {}", value).as_str()),
+                StaticInit::Word(value) => writer.write_line(format!(".short {}", value).as_str()),
+                StaticInit::Long(value) => writer.write_line(format!(".long {}", value).as_str()),
+                StaticInit::Quad(value) => writer.write_line(format!(".quad {}", value).as_str()),
+                StaticInit::Double(value) => writer.write_line(format!(".quad {:#018x}", value.to_bits()).as_str()),
+                StaticInit::Zero(bytes) => writer.write_line(format!(".zero {}", bytes).as_str()),
+            }
+        }
+    });
+    writer.blank_line();
+}
+
+fn emit_function(writer: &mut CodeWriter, function: &asm::Function, read_only_data: &ReadOnlyData, defined: &HashSet<&str>) {
+    // Static functions stay local to the file
+    if function.global() {
+        writer.write_line(format!(".globl {}", function.name()).as_str());
+    }
+    writer.write_line(format!("{}:", function.name()).as_str());
     writer.write_block(|writer| {
         writer.write_line("pushq %rbp");
         writer.write_line("movq %rsp, %rbp");
         function.instructions().iter().for_each(|inst| {
-            emit_instruction(writer, inst);
+            emit_instruction(writer, inst, read_only_data, defined);
         })
     })
 }
 
-fn emit_instruction(writer: &mut CodeWriter, instruction: &Instruction) {
+fn emit_instruction(writer: &mut CodeWriter, instruction: &Instruction, read_only_data: &ReadOnlyData, defined: &HashSet<&str>) {
     match instruction {
-        Instruction::Mov { src, dest } => {
+        Instruction::Mov { size, src, dest } => {
+            writer.line(|writer| {
+                writer.write(format!("mov{} ", suffix(*size)).as_str());
+                emit_operands(writer, read_only_data, (src, *size), (dest, *size));
+            })
+        }
+        Instruction::Movsx { src_size, dest_size, src, dest } => {
+            writer.line(|writer| {
+                writer.write(format!("movs{}{} ", suffix(*src_size), suffix(*dest_size)).as_str());
+                emit_operands(writer, read_only_data, (src, *src_size), (dest, *dest_size));
+            })
+        }
+        Instruction::Movzx { src_size, dest_size, src, dest } => {
             writer.line(|writer| {
-                writer.write("movl ");
-                emit_operand(writer, src);
-                writer.write(", ");
-                emit_operand(writer, dest);
+                writer.write(format!("movz{}{} ", suffix(*src_size), suffix(*dest_size)).as_str());
+                emit_operands(writer, read_only_data, (src, *src_size), (dest, *dest_size));
+            })
+        }
+        Instruction::Movsd { src, dest } => {
+            writer.line(|writer| {
+                writer.write("movsd ");
+                emit_operands(writer, read_only_data, (src, Size::Quad), (dest, Size::Quad));
+            })
+        }
+        Instruction::Cvtsi2sd { size, src, dest } => {
+            writer.line(|writer| {
+                writer.write(format!("cvtsi2sd{} ", suffix(*size)).as_str());
+                emit_operands(writer, read_only_data, (src, *size), (dest, Size::Quad));
+            })
+        }
+        Instruction::Cvttsd2si { size, src, dest } => {
+            writer.line(|writer| {
+                writer.write(format!("cvttsd2si{} ", suffix(*size)).as_str());
+                emit_operands(writer, read_only_data, (src, Size::Quad), (dest, *size));
+            })
+        }
+        Instruction::Lea { src, dest } => {
+            writer.line(|writer| {
+                writer.write("leaq ");
+                match dest {
+                    Operand::Register(_) => emit_operands(writer, read_only_data, (src, Size::Quad), (dest, Size::Quad)),
+                    _ => unreachable!("The destination of lea must be a register"),
+                }
             })
         }
         Instruction::Ret => {
@@ -41,64 +235,253 @@ fn emit_instruction(writer: &mut CodeWriter, instruction: &Instruction) {
             writer.write_line("popq %rbp");
             writer.write_line("ret")
         }
-        Instruction::Unary(op, operand) => {
+        Instruction::Unary(op, size, operand) => {
             writer.line(|writer| {
                 emit_unary_operator(writer, op);
-                writer.write(" ");
-                emit_operand(writer, operand);
+                writer.write(format!("{} ", suffix(*size)).as_str());
+                emit_operand(writer, read_only_data, operand, *size);
             })
         }
-        Instruction::Cdq => writer.write_line("cdq"),
-        Instruction::Idiv(operand) => {
+        Instruction::Cdq(Size::Quad) => writer.write_line("cqo"),
+        Instruction::Cdq(_) => writer.write_line("cdq"),
+        Instruction::Idiv(size, operand) => {
             writer.line(|writer| {
-                writer.write("idivl ");
-                emit_operand(writer, operand);
+                writer.write(format!("idiv{} ", suffix(*size)).as_str());
+                emit_operand(writer, read_only_data, operand, *size);
             })
         }
-        Instruction::Binary(op, left, right) => {
+        Instruction::Div(size, operand) => {
+            writer.line(|writer| {
+                writer.write(format!("div{} ", suffix(*size)).as_str());
+                emit_operand(writer, read_only_data, operand, *size);
+            })
+        }
+        Instruction::Binary(op, size, left, right) => {
             writer.line(|writer| {
                 emit_binary_operator(writer, op);
+                if !op.is_double() {
+                    writer.write(suffix(*size));
+                }
+                writer.write(" ");
+                match (op, left) {
+                    // The shift count is read from %cl
+                    (BinaryOperator::Sal | BinaryOperator::Sar | BinaryOperator::Shr, Operand::Register(_)) => {
+                        emit_operands(writer, read_only_data, (left, Size::Byte), (right, *size))
+                    }
+                    _ => emit_operands(writer, read_only_data, (left, *size), (right, *size)),
+                }
+            })
+        }
+        Instruction::Cmp(size, left, right) => {
+            writer.line(|writer| {
+                writer.write(format!("cmp{} ", suffix(*size)).as_str());
+                emit_operands(writer, read_only_data, (left, *size), (right, *size));
+            })
+        }
+        Instruction::Comisd(left, right) => {
+            writer.line(|writer| {
+                writer.write("comisd ");
+                emit_operands(writer, read_only_data, (left, Size::Quad), (right, Size::Quad));
+            })
+        }
+        Instruction::Jmp(label) => {
+            writer.write_line(format!("jmp {}", local_label(label)).as_str());
+        }
+        Instruction::JmpCC(condition, label) => {
+            writer.line(|writer| {
+                writer.write("j");
+                emit_condition(writer, condition);
+                writer.write(format!(" {}", local_label(label)).as_str());
+            })
+        }
+        Instruction::JmpTable { table, .. } => {
+            writer.write_line(format!("leaq {}(%rip), %rdx", local_label(table)).as_str());
+            writer.write_line("movslq (%rdx,%rax,4), %rax");
+            writer.write_line("addq %rdx, %rax");
+            writer.write_line("jmp *%rax");
+        }
+        Instruction::SetCC(condition, operand) => {
+            writer.line(|writer| {
+                writer.write("set");
+                emit_condition(writer, condition);
                 writer.write(" ");
-                emit_operand(writer, left);
-                writer.write(", ");
-                emit_operand(writer, right);
+                emit_operand(writer, read_only_data, operand, Size::Byte);
             })
         }
+        Instruction::Label(label) => {
+            writer.write_line(format!("{}:", local_label(label)).as_str());
+        }
         Instruction::AllocateStack(size) => {
             writer.write_line(format!("subq ${}, %rsp", size).as_str());
         }
+        Instruction::DeallocateStack(size) => {
+            writer.write_line(format!("addq ${}, %rsp", size).as_str());
+        }
+        Instruction::Push(operand) => {
+            writer.line(|writer| {
+                writer.write("pushq ");
+                emit_operand(writer, read_only_data, operand, Size::Quad);
+            })
+        }
+        Instruction::Call(name) if defined.contains(name.as_str()) => {
+            writer.write_line(format!("call {}", name).as_str());
+        }
+        Instruction::Call(name) => {
+            writer.write_line(format!("call {}@PLT", name).as_str());
+        }
+    }
+}
+
+fn suffix(size: Size) -> &'static str {
+    match size {
+        Size::Byte => "b",
+        Size::Word => "w",
+        Size::Long => "l",
+        Size::Quad => "q",
     }
 }
 
 fn emit_unary_operator(writer: &mut LineWriter, operator: &UnaryOperator) {
     match operator {
-        UnaryOperator::Neg => writer.write("negl"),
-        UnaryOperator::Not => writer.write("notl"),
+        UnaryOperator::Neg => writer.write("neg"),
+        UnaryOperator::Not => writer.write("not"),
     }
 }
 
 fn emit_binary_operator(writer: &mut LineWriter, operator: &BinaryOperator) {
     match operator {
-        BinaryOperator::Add => writer.write("addl"),
-        BinaryOperator::Sub => writer.write("subl"),
-        BinaryOperator::Mul => writer.write("imull"),
+        BinaryOperator::Add => writer.write("add"),
+        BinaryOperator::Sub => writer.write("sub"),
+        BinaryOperator::Mul => writer.write("imul"),
+        BinaryOperator::And => writer.write("and"),
+        BinaryOperator::Or => writer.write("or"),
+        BinaryOperator::Xor => writer.write("xor"),
+        BinaryOperator::Sal => writer.write("sal"),
+        BinaryOperator::Sar => writer.write("sar"),
+        BinaryOperator::Shr => writer.write("shr"),
+        BinaryOperator::Addsd => writer.write("addsd"),
+        BinaryOperator::Subsd => writer.write("subsd"),
+        BinaryOperator::Mulsd => writer.write("mulsd"),
+        BinaryOperator::Divsd => writer.write("divsd"),
+        BinaryOperator::Xorpd => writer.write("xorpd"),
     }
 }
 
-fn emit_operand(writer: &mut LineWriter, operand: &Operand) {
+fn emit_condition(writer: &mut LineWriter, condition: &CondCode) {
+    match condition {
+        CondCode::E => writer.write("e"),
+        CondCode::NE => writer.write("ne"),
+        CondCode::L => writer.write("l"),
+        CondCode::LE => writer.write("le"),
+        CondCode::G => writer.write("g"),
+        CondCode::GE => writer.write("ge"),
+        CondCode::A => writer.write("a"),
+        CondCode::AE => writer.write("ae"),
+        CondCode::B => writer.write("b"),
+        CondCode::BE => writer.write("be"),
+        CondCode::P => writer.write("p"),
+        CondCode::NP => writer.write("np"),
+    }
+}
+
+/**
+Labels of the TACKY program are local to the assembly file, they must not clash with function names.
+*/
+fn local_label(label: &str) -> String {
+    format!(".L{}", label)
+}
+
+fn emit_operands(writer: &mut LineWriter, read_only_data: &ReadOnlyData, (src, src_size): (&Operand, Size), (dest, dest_size): (&Operand, Size)) {
+    emit_operand(writer, read_only_data, src, src_size);
+    writer.write(", ");
+    emit_operand(writer, read_only_data, dest, dest_size);
+}
+
+/**
+Write an operand of an instruction whose operands are `size` bytes, which names the part of the registers it uses. The
+bits of an immediate are written as a signed integer of that size.
+*/
+fn emit_operand(writer: &mut LineWriter, read_only_data: &ReadOnlyData, operand: &Operand, size: Size) {
     match operand {
-        Operand::Register(register) => emit_register(writer, register),
-        Operand::Imm(value) => writer.write(format!("${}", value).as_str()),
+        Operand::Register(register) => emit_register(writer, register, size),
+        Operand::Imm(value) => {
+            let value = match size {
+                Size::Byte => *value as i8 as i64,
+                Size::Word => *value as i16 as i64,
+                Size::Long => *value as i32 as i64,
+                Size::Quad => *value as i64,
+            };
+            writer.write(format!("${}", value).as_str())
+        }
         Operand::Stack(offset) => writer.write(format!("{}(%rbp)", offset).as_str()),
-        Operand::Pseudo(_) => unreachable!("Pseudo registers should have been removed in the PseudoRegister pass"),
+        Operand::Memory { base, offset } => {
+            writer.write(format!("{}(", offset).as_str());
+            emit_register(writer, base, Size::Quad);
+            writer.write(")");
+        }
+        Operand::Indexed { base, index, scale } => {
+            writer.write("(");
+            emit_register(writer, base, Size::Quad);
+            writer.write(",");
+            emit_register(writer, index, Size::Quad);
+            writer.write(format!(",{})", scale).as_str());
+        }
+        Operand::StringLiteral(value) => writer.write(format!("{}(%rip)", read_only_data.string_literal_label(value)).as_str()),
+        Operand::DoubleConstant(value) => writer.write(format!("{}(%rip)", read_only_data.double_label(*value)).as_str()),
+        Operand::Data(name) => writer.write(format!("{}(%rip)", name).as_str()),
+        Operand::Pseudo(_) | Operand::PseudoMem { .. } => unreachable!("Pseudo registers should have been removed in the PseudoRegister pass"),
     }
 }
 
-fn emit_register(writer: &mut LineWriter, register: &Register) {
-    match register {
-        Register::AX => writer.write("%eax"),
-        Register::R10 => writer.write("%r10d"),
-        Register::DX => writer.write("%edx"),
-        Register::R11 => writer.write("%r11d"),
-    }
-}
\ No newline at end of file
+fn emit_register(writer: &mut LineWriter, register: &Register, size: Size) {
+    let name = match (register, size) {
+        (Register::AX, Size::Byte) => "%al",
+        (Register::AX, Size::Word) => "%ax",
+        (Register::AX, Size::Long) => "%eax",
+        (Register::AX, Size::Quad) => "%rax",
+        (Register::CX, Size::Byte) => "%cl",
+        (Register::CX, Size::Word) => "%cx",
+        (Register::CX, Size::Long) => "%ecx",
+        (Register::CX, Size::Quad) => "%rcx",
+        (Register::DX, Size::Byte) => "%dl",
+        (Register::DX, Size::Word) => "%dx",
+        (Register::DX, Size::Long) => "%edx",
+        (Register::DX, Size::Quad) => "%rdx",
+        (Register::DI, Size::Byte) => "%dil",
+        (Register::DI, Size::Word) => "%di",
+        (Register::DI, Size::Long) => "%edi",
+        (Register::DI, Size::Quad) => "%rdi",
+        (Register::SI, Size::Byte) => "%sil",
+        (Register::SI, Size::Word) => "%si",
+        (Register::SI, Size::Long) => "%esi",
+        (Register::SI, Size::Quad) => "%rsi",
+        (Register::R8, Size::Byte) => "%r8b",
+        (Register::R8, Size::Word) => "%r8w",
+        (Register::R8, Size::Long) => "%r8d",
+        (Register::R8, Size::Quad) => "%r8",
+        (Register::R9, Size::Byte) => "%r9b",
+        (Register::R9, Size::Word) => "%r9w",
+        (Register::R9, Size::Long) => "%r9d",
+        (Register::R9, Size::Quad) => "%r9",
+        (Register::R10, Size::Byte) => "%r10b",
+        (Register::R10, Size::Word) => "%r10w",
+        (Register::R10, Size::Long) => "%r10d",
+        (Register::R10, Size::Quad) => "%r10",
+        (Register::R11, Size::Byte) => "%r11b",
+        (Register::R11, Size::Word) => "%r11w",
+        (Register::R11, Size::Long) => "%r11d",
+        (Register::R11, Size::Quad) => "%r11",
+        // SSE registers have a single name
+        (Register::XMM0, _) => "%xmm0",
+        (Register::XMM1, _) => "%xmm1",
+        (Register::XMM2, _) => "%xmm2",
+        (Register::XMM3, _) => "%xmm3",
+        (Register::XMM4, _) => "%xmm4",
+        (Register::XMM5, _) => "%xmm5",
+        (Register::XMM6, _) => "%xmm6",
+        (Register::XMM7, _) => "%xmm7",
+        (Register::XMM14, _) => "%xmm14",
+        (Register::XMM15, _) => "%xmm15",
+    };
+    writer.write(name)
+}
@@ -1,18 +1,42 @@
-use regex::Regex;
-use std::str::FromStr;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::standard::Standard;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Identifier(String),
     OpenParenthesis,
     CloseParenthesis,
     OpeningBrace,
     ClosingBrace,
-    Constant(u64),
+    Constant(IntegerConstant),
+    FloatingConstant(f64),
+    CharLiteral(i32),
+    StringLiteral(Vec<u8>),
     Semicolon,
     KwInt,
     KwReturn,
     KwVoid,
+    KwDouble,
+    KwLong,
+    KwShort,
+    KwChar,
+    KwSigned,
+    KwUnsigned,
+    KwIf,
+    KwElse,
+    KwWhile,
+    KwDo,
+    KwFor,
+    KwBreak,
+    KwContinue,
+    KwSwitch,
+    KwCase,
+    KwDefault,
+    KwGoto,
+    KwStatic,
+    KwExtern,
     Hyphen,
     TwoHyphens,
     Tilde,
@@ -21,148 +45,1069 @@ pub enum Token {
     Asterisk,
     ForwardSlash,
     Percent,
+
+    OpenBracket,
+    CloseBracket,
+    Dot,
+    Arrow,
+    TwoPluses,
+    Ampersand,
+    Exclamation,
+    TwoLessThan,
+    TwoGreaterThan,
+    LessThan,
+    GreaterThan,
+    LessThanEqual,
+    GreaterThanEqual,
+    TwoEquals,
+    ExclamationEqual,
+    Caret,
+    Pipe,
+    TwoAmpersands,
+    TwoPipes,
+    QuestionMark,
+    Colon,
+    Comma,
+    Ellipsis,
+    Hash,
+    TwoHashes,
+
+    Equal,
+    AsteriskEqual,
+    ForwardSlashEqual,
+    PercentEqual,
+    PlusEqual,
+    HyphenEqual,
+    TwoLessThanEqual,
+    TwoGreaterThanEqual,
+    AmpersandEqual,
+    CaretEqual,
+    PipeEqual,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    fn base(&self) -> u64 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Radix::Binary => "binary",
+            Radix::Octal => "octal",
+            Radix::Decimal => "decimal",
+            Radix::Hexadecimal => "hexadecimal",
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum IntegerSuffix {
+    None,
+    Unsigned,
+    Long,
+    UnsignedLong,
+    LongLong,
+    UnsignedLongLong,
+}
+
+impl IntegerSuffix {
+    fn from_str(suffix: &str) -> Option<Self> {
+        let suffix = match suffix {
+            "" => IntegerSuffix::None,
+            "u" | "U" => IntegerSuffix::Unsigned,
+            "l" | "L" => IntegerSuffix::Long,
+            "ul" | "uL" | "Ul" | "UL" | "lu" | "lU" | "Lu" | "LU" => IntegerSuffix::UnsignedLong,
+            "ll" | "LL" => IntegerSuffix::LongLong,
+            "ull" | "uLL" | "Ull" | "ULL" | "llu" | "llU" | "LLu" | "LLU" => IntegerSuffix::UnsignedLongLong,
+            _ => return None,
+        };
+
+        Some(suffix)
+    }
+
+    fn is_unsigned(&self) -> bool {
+        matches!(self, IntegerSuffix::Unsigned | IntegerSuffix::UnsignedLong | IntegerSuffix::UnsignedLongLong)
+    }
+}
+
+/**
+Value of an integer literal, along with the information needed to give it a C type.
+*/
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct IntegerConstant {
+    value: u64,
+    radix: Radix,
+    suffix: IntegerSuffix,
+}
+
+impl IntegerConstant {
+    pub fn new(value: u64, radix: Radix, suffix: IntegerSuffix) -> Self {
+        IntegerConstant {
+            value,
+            radix,
+            suffix,
+        }
+    }
+
+    /**
+    Parse the spelling of an integer constant, prefix, digit separators and suffix included.
+    */
+    pub fn parse(spelling: &str, standard: Standard) -> Result<Self, Diagnostic> {
+        let (radix, digits) = match spelling.as_bytes() {
+            [b'0', b'x' | b'X', ..] => (Radix::Hexadecimal, &spelling[2..]),
+            [b'0', b'b' | b'B', ..] => {
+                if standard < Standard::C23 {
+                    return Err(Diagnostic::error(Code::RequiresNewerStandard, format!("Binary constant '{}' requires C23", spelling))
+                        .with_note("binary constants are supported with `--std c23`"));
+                }
+                (Radix::Binary, &spelling[2..])
+            }
+            [b'0', ..] => (Radix::Octal, &spelling[1..]),
+            _ => (Radix::Decimal, spelling),
+        };
+
+        let digits_end = digits
+            .find(|c: char| !(c.is_ascii_digit() || c == '\'' || (radix == Radix::Hexadecimal && c.is_ascii_hexdigit())))
+            .unwrap_or(digits.len());
+        let (digits, suffix) = digits.split_at(digits_end);
+
+        if digits.is_empty() && radix != Radix::Octal {
+            return Err(Diagnostic::error(Code::InvalidIntegerConstant, format!("Integer constant '{}' has no digits", spelling)));
+        }
+
+        let suffix = IntegerSuffix::from_str(suffix)
+            .ok_or_else(|| Diagnostic::error(Code::InvalidIntegerConstant, format!("Invalid suffix '{}' on integer constant", suffix)))?;
+
+        let mut value: u64 = 0;
+        let mut previous = None;
+        for c in digits.chars() {
+            if c == '\'' {
+                // C23 digit separators may only appear between two digits
+                if standard < Standard::C23 || previous.is_none_or(|previous| previous == '\'') {
+                    return Err(invalid_separator(spelling, standard));
+                }
+            } else {
+                let digit = c.to_digit(16).unwrap() as u64;
+                if digit >= radix.base() {
+                    return Err(Diagnostic::error(Code::InvalidIntegerConstant, format!("Invalid digit '{}' in {} constant '{}'", c, radix.name(), spelling)));
+                }
+
+                value = value.checked_mul(radix.base())
+                    .and_then(|value| value.checked_add(digit))
+                    .ok_or_else(|| Diagnostic::error(Code::IntegerConstantTooLarge, format!("Integer constant '{}' is too large", spelling)))?;
+            }
+            previous = Some(c);
+        }
+
+        if previous == Some('\'') {
+            return Err(invalid_separator(spelling, standard));
+        }
+
+        // Decimal constants without the 'u' suffix only get signed types
+        if radix == Radix::Decimal && !suffix.is_unsigned() && value > i64::MAX as u64 {
+            return Err(Diagnostic::error(Code::IntegerConstantTooLarge, format!("Integer constant '{}' is too large for any signed type", spelling))
+                .with_note("decimal constants without a `u` suffix must fit in `long long`"));
+        }
+
+        Ok(IntegerConstant::new(value, radix, suffix))
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        self.suffix.is_unsigned()
+    }
+
+    pub fn is_long(&self) -> bool {
+        !matches!(self.suffix, IntegerSuffix::None | IntegerSuffix::Unsigned)
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        self.radix == Radix::Decimal
+    }
+}
+
+fn invalid_separator(spelling: &str, standard: Standard) -> Diagnostic {
+    let diagnostic = Diagnostic::error(Code::InvalidIntegerConstant, format!("Invalid digit separator in integer constant '{}'", spelling));
+    if standard < Standard::C23 {
+        return diagnostic.with_note("digit separators are supported with `--std c23`");
+    }
+
+    diagnostic
+}
+
+/**
+Parse the spelling of a decimal or hexadecimal floating constant.
+*/
+fn parse_floating_constant(spelling: &str, standard: Standard) -> Result<f64, Diagnostic> {
+    let invalid = || Diagnostic::error(Code::InvalidFloatingConstant, format!("Invalid floating constant '{}'", spelling));
+
+    let mut digits = String::with_capacity(spelling.len());
+    let mut previous = None;
+    for c in spelling.chars() {
+        if c == '\'' {
+            // C23 digit separators may only appear between two digits
+            if standard < Standard::C23 || !previous.is_some_and(|previous: char| previous.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+        } else {
+            digits.push(c);
+        }
+        previous = Some(c);
+    }
+
+    let (is_hexadecimal, digits) = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(digits) => (true, digits),
+        None => (false, digits.as_str()),
+    };
+
+    let is_digit = |c: char| if is_hexadecimal { c.is_ascii_hexdigit() } else { c.is_ascii_digit() };
+    let mantissa_end = digits.find(|c: char| !is_digit(c) && c != '.').unwrap_or(digits.len());
+    let (mantissa, rest) = digits.split_at(mantissa_end);
+
+    if mantissa.matches('.').count() > 1 || !mantissa.contains(is_digit) {
+        return Err(invalid());
+    }
+
+    let exponent_markers: &[char] = if is_hexadecimal { &['p', 'P'] } else { &['e', 'E'] };
+    let (exponent, suffix) = match rest.strip_prefix(exponent_markers) {
+        Some(exponent) => {
+            let sign_length = if exponent.starts_with(['+', '-']) { 1 } else { 0 };
+            let exponent_end = exponent[sign_length..].find(|c: char| !c.is_ascii_digit())
+                .map_or(exponent.len(), |end| end + sign_length);
+            let (exponent, suffix) = exponent.split_at(exponent_end);
+            if exponent.len() == sign_length {
+                return Err(Diagnostic::error(Code::InvalidFloatingConstant, format!("Exponent has no digits in floating constant '{}'", spelling)));
+            }
+            (Some(exponent), suffix)
+        }
+        None if is_hexadecimal => {
+            return Err(Diagnostic::error(Code::InvalidFloatingConstant, format!("Hexadecimal floating constant '{}' requires an exponent", spelling)));
+        }
+        None => (None, rest),
+    };
+
+    match suffix {
+        "" => {}
+        "f" | "F" => return Err(Diagnostic::error(Code::Unsupported, format!("Constant '{}' has type float, which is not supported", spelling))),
+        "l" | "L" => return Err(Diagnostic::error(Code::Unsupported, format!("Constant '{}' has type long double, which is not supported", spelling))),
+        _ => return Err(Diagnostic::error(Code::InvalidFloatingConstant, format!("Invalid suffix '{}' on floating constant", suffix))),
+    }
+
+    let value = if is_hexadecimal {
+        // The exponent is only used to scale the value, so saturating it doesn't change the result
+        let exponent = exponent.unwrap().parse::<i32>().unwrap_or(if exponent.unwrap().starts_with('-') { i32::MIN } else { i32::MAX });
+        parse_hexadecimal_float(mantissa, exponent)
+    } else {
+        // Rust's parser is correctly rounded and accepts the same syntax once the suffix is gone
+        format!("{}{}", mantissa, exponent.map_or(String::new(), |exponent| format!("e{}", exponent)))
+            .parse::<f64>()
+            .map_err(|_| invalid())?
+    };
+
+    if value.is_infinite() {
+        return Err(Diagnostic::error(Code::InvalidFloatingConstant, format!("Floating constant '{}' exceeds the range of double", spelling)));
+    }
+
+    Ok(value)
+}
+
+/**
+Compute `mantissa * 2^exponent`, where `mantissa` is a sequence of hexadecimal digits with an optional point.
+*/
+fn parse_hexadecimal_float(mantissa: &str, exponent: i32) -> f64 {
+    let mut value: u64 = 0;
+    let mut exponent = exponent as i64;
+    let mut is_inexact = false;
+    let mut after_point = false;
+
+    for c in mantissa.chars() {
+        if c == '.' {
+            after_point = true;
+            continue;
+        }
+
+        let digit = c.to_digit(16).unwrap() as u64;
+        if value >> 60 == 0 {
+            value = value * 16 + digit;
+            if after_point {
+                exponent -= 4;
+            }
+        } else {
+            // Digits that don't fit anymore only matter for rounding
+            is_inexact |= digit != 0;
+            if !after_point {
+                exponent += 4;
+            }
+        }
+    }
+
+    if is_inexact {
+        // A sticky bit well below the 53 significant bits makes the conversion round correctly
+        value |= 1;
+    }
+
+    let mut result = value as f64;
+    while exponent > 1000 {
+        result *= 2f64.powi(1000);
+        exponent -= 1000;
+    }
+    while exponent < -1000 {
+        result *= 2f64.powi(-1000);
+        exponent += 1000;
+    }
+
+    result * 2f64.powi(exponent as i32)
+}
+
+/**
+Location of a token in the source code.
+
+Lines and columns are 1-based, `start` and `end` are the byte offsets of the token in the file. Tokens produced by a
+macro expansion also remember where the macro was invoked.
+*/
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    file: Rc<str>,
+    line: usize,
+    column: usize,
+    start: usize,
+    end: usize,
+    expansion: Option<Rc<Expansion>>,
+}
+
+/**
+Invocation of a macro whose replacement list contains a token.
+*/
+#[derive(Debug, PartialEq)]
+pub struct Expansion {
+    macro_name: String,
+    call_site: Span,
+}
+
+impl Span {
+    pub fn new(file: Rc<str>, line: usize, column: usize, start: usize, end: usize) -> Self {
+        Span {
+            file,
+            line,
+            column,
+            start,
+            end,
+            expansion: None,
+        }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /**
+    Macro whose expansion produced the token, and where that macro was invoked.
+    */
+    pub fn expansion(&self) -> Option<(&str, &Span)> {
+        self.expansion.as_ref().map(|expansion| (expansion.macro_name.as_str(), &expansion.call_site))
+    }
+
+    /**
+    Location in the source file of the outermost macro invocation which produced a token, or of the token itself if it
+    doesn't come from a macro expansion.
+    */
+    pub fn source_location(&self) -> &Span {
+        match &self.expansion {
+            Some(expansion) => expansion.call_site.source_location(),
+            None => self,
+        }
+    }
+
+    /**
+    Empty span right before the first character of this one, where text can be inserted.
+    */
+    pub fn shrink_to_start(&self) -> Span {
+        Span {
+            end: self.start,
+            ..self.clone()
+        }
+    }
+
+    /**
+    Empty span right after the last character of this one, where text can be appended. The column is counted in bytes
+    from the start of the span, which is exact for a token on a single line of ASCII characters.
+    */
+    pub fn shrink_to_end(&self) -> Span {
+        Span {
+            column: self.column + (self.end - self.start),
+            start: self.end,
+            ..self.clone()
+        }
+    }
+
+    /**
+    Location of a token of the replacement list of `macro_name`, once expanded at `call_site`.
+    */
+    pub fn expanded(&self, macro_name: &str, call_site: &Span) -> Span {
+        Span {
+            expansion: Some(Rc::new(Expansion {
+                macro_name: macro_name.to_owned(),
+                call_site: call_site.clone(),
+            })),
+            ..self.clone()
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
 }
 
+/**
+Place where translation phases 1 and 2 removed characters from the physical source.
+*/
+#[derive(Debug, Clone)]
+struct Splice {
+    // Offset in the logical source from which the splice has to be taken into account
+    offset: usize,
+    removed: usize,
+    newline: bool,
+}
+
+/**
+Source code after translation phases 1 and 2: trigraphs are replaced and lines ending with a backslash are spliced.
+
+The removed characters are remembered so that the tokenizer can report positions in the physical file.
+*/
+pub struct LogicalSource {
+    text: String,
+    splices: Vec<Splice>,
+}
+
+impl LogicalSource {
+    /**
+    Source made of the spelling of existing tokens, which already went through translation phases 1 and 2.
+    */
+    pub fn spelled(text: &str) -> Self {
+        LogicalSource {
+            text: text.to_owned(),
+            splices: vec![],
+        }
+    }
+
+    pub fn new(physical: &str, standard: Standard) -> Self {
+        // Trigraphs were removed in C23
+        let trigraphs = standard < Standard::C23;
+        if !(physical.contains('\\') || trigraphs && physical.contains("??")) {
+            return LogicalSource {
+                text: physical.to_owned(),
+                splices: vec![],
+            };
+        }
+
+        let input = physical.as_bytes();
+        let mut text = Vec::with_capacity(input.len());
+        let mut splices = vec![];
+        let mut removed = 0;
+        let mut position = 0;
+
+        while position < input.len() {
+            let (byte, length) = match input[position..] {
+                [b'?', b'?', third, ..] if trigraphs => match trigraph(third) {
+                    Some(replacement) => (replacement, 3),
+                    None => (b'?', 1),
+                },
+                [byte, ..] => (byte, 1),
+                [] => unreachable!(),
+            };
+
+            let newline_length = match (byte, &input[position + length..]) {
+                (b'\\', [b'\n', ..]) => 1,
+                (b'\\', [b'\r', b'\n', ..]) => 2,
+                _ => 0,
+            };
+
+            if newline_length > 0 {
+                removed += length + newline_length;
+                splices.push(Splice { offset: text.len(), removed: length + newline_length, newline: true });
+            } else {
+                text.push(byte);
+                if length > 1 {
+                    removed += length - 1;
+                    splices.push(Splice { offset: text.len(), removed: length - 1, newline: false });
+                }
+            }
+
+            position += length + newline_length;
+        }
+
+        debug_assert_eq!(text.len() + removed, input.len());
+
+        LogicalSource {
+            // Only ASCII characters are replaced or removed, so the text is still valid UTF-8
+            text: String::from_utf8(text).unwrap(),
+            splices,
+        }
+    }
+}
+
+fn trigraph(byte: u8) -> Option<u8> {
+    let replacement = match byte {
+        b'=' => b'#',
+        b'(' => b'[',
+        b'/' => b'\\',
+        b')' => b']',
+        b'\'' => b'^',
+        b'<' => b'{',
+        b'!' => b'|',
+        b'>' => b'}',
+        b'-' => b'~',
+        _ => return None,
+    };
+
+    Some(replacement)
+}
+
+/**
+Token as seen by the preprocessor, along with its spelling and the whitespace that precedes it.
+
+Tokens that can't be converted to a C token (e.g. `1.2.3`) are kept with an error, which is only reported if the token
+survives preprocessing.
+*/
+#[derive(Debug, Clone)]
+pub struct PreprocessingToken {
+    pub token: Result<Token, Diagnostic>,
+    pub span: Span,
+    pub spelling: String,
+    pub leading_whitespace: bool,
+    pub line_start: bool,
+}
+
+/**
+Hand-written scanner turning the source code into tokens.
+
+Every byte of the input is looked at a bounded number of times, so the tokenizer runs in linear time.
+*/
 pub struct Tokenizer<'a> {
-    identifier_regex: Regex,
-    constant_regex: Regex,
-    kw_int_regex: Regex,
-    kw_void_regex: Regex,
-    kw_return_regex: Regex,
-    open_parenthesis_regex: Regex,
-    close_parenthesis_regex: Regex,
-    open_brace_regex: Regex,
-    close_brace_regex: Regex,
-    semicolon_regex: Regex,
-    two_hyphens_regex: Regex,
-    hyphen_regex: Regex,
-    tilde_regex: Regex,
-    plus_regex: Regex,
-    asterisk_regex: Regex,
-    forward_slash_regex: Regex,
-    percent_regex: Regex,
-
-    input: &'a str,
+    file: Rc<str>,
+    input: &'a [u8],
+    standard: Standard,
+
+    splices: &'a [Splice],
+    next_splice: usize,
+    // Number of physical bytes removed before the current position
+    removed: usize,
+
+    position: usize,
+    line: usize,
+    column: usize,
 }
 
 impl<'a> Tokenizer<'a> {
-    pub fn new(source: &'a str) -> Self {
-        Tokenizer {
-            identifier_regex: Regex::new(r"^[a-zA-Z_]\w*\b").unwrap(),
-            constant_regex: Regex::new(r"^[0-9]+\b").unwrap(),
-            kw_int_regex: Regex::new(r"^int\b").unwrap(),
-            kw_void_regex: Regex::new(r"^void\b").unwrap(),
-            kw_return_regex: Regex::new(r"^return\b").unwrap(),
-            open_parenthesis_regex: Regex::new(r"^\(").unwrap(),
-            close_parenthesis_regex: Regex::new(r"^\)").unwrap(),
-            open_brace_regex: Regex::new(r"^\{").unwrap(),
-            close_brace_regex: Regex::new(r"^}").unwrap(),
-            semicolon_regex: Regex::new(r"^;").unwrap(),
-            hyphen_regex: Regex::new(r"^-").unwrap(),
-            two_hyphens_regex: Regex::new(r"^--").unwrap(),
-            tilde_regex: Regex::new(r"^~").unwrap(),
-            plus_regex: Regex::new(r"^\+").unwrap(),
-            asterisk_regex: Regex::new(r"^\*").unwrap(),
-            forward_slash_regex: Regex::new(r"^/").unwrap(),
-            percent_regex: Regex::new(r"^%").unwrap(),
-
-            input: &source,
-        }
-    }
-}
-
-impl Iterator for Tokenizer<'_> {
-    type Item = Result<Token, String>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    pub fn new(file: &str, source: &'a LogicalSource, standard: Standard) -> Self {
+        let mut tokenizer = Tokenizer {
+            file: Rc::from(file),
+            input: source.text.as_bytes(),
+            standard,
+            splices: &source.splices,
+            next_splice: 0,
+            removed: 0,
+            position: 0,
+            line: 1,
+            column: 1,
+        };
+        tokenizer.apply_splices();
+
+        tokenizer
+    }
+
+    /**
+    Scan the next preprocessing token, skipping whitespace and comments.
+    */
+    pub fn next_preprocessing_token(&mut self) -> Option<PreprocessingToken> {
+        let at_start = self.position == 0;
+        let whitespace = self.skip_whitespace();
+
+        let (leading_whitespace, newline) = match whitespace {
+            Ok(whitespace) => whitespace,
+            Err(diagnostic) => {
+                return Some(PreprocessingToken {
+                    span: diagnostic.primary_span().unwrap().clone(),
+                    token: Err(diagnostic),
+                    spelling: String::new(),
+                    leading_whitespace: true,
+                    line_start: false,
+                });
+            }
+        };
+
+        let byte = self.peek()?;
+        let (start, line, column) = (self.position, self.line, self.column);
+        let physical_start = self.position + self.removed;
+
+        let token = if is_identifier_start(byte) {
+            self.scan_identifier()
+        } else if byte.is_ascii_digit() || (byte == b'.' && self.input.get(self.position + 1).is_some_and(u8::is_ascii_digit)) {
+            self.scan_constant()
+        } else if byte == b'\'' || byte == b'"' {
+            self.scan_quoted(Encoding::Plain)
+        } else {
+            self.scan_punctuator()
+        };
+
+        let span = Span::new(self.file.clone(), line, column, physical_start, self.position + self.removed);
+        Some(PreprocessingToken {
+            token: token.map_err(|diagnostic| diagnostic.with_primary_label(&span, "")),
+            span,
+            spelling: self.text(start).to_owned(),
+            leading_whitespace,
+            line_start: at_start || newline,
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn bump(&mut self) {
+        let byte = self.input[self.position];
+        self.position += 1;
+
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if (byte & 0xC0) != 0x80 {
+            // Continuation bytes of a multibyte UTF-8 character don't start a new column
+            self.column += 1;
+        }
+
+        self.apply_splices();
+    }
+
+    fn apply_splices(&mut self) {
+        while let Some(splice) = self.splices.get(self.next_splice).filter(|splice| splice.offset == self.position) {
+            self.removed += splice.removed;
+            if splice.newline {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += splice.removed;
+            }
+            self.next_splice += 1;
+        }
+    }
+
+    fn bump_while<F>(&mut self, predicate: F)
+    where
+        F: Fn(u8) -> bool,
+    {
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
+        }
+    }
+
+    /**
+    Skip whitespace and comments, and tell whether anything was skipped and whether a new line was started.
+    */
+    fn skip_whitespace(&mut self) -> Result<(bool, bool), Diagnostic> {
+        let start = self.position;
+        let mut newline = false;
+
         loop {
-            let input = self.input.trim_start();
-            if input.len() == 0 {
-                return None;
-            }
-
-            if let Some(result) = self.identifier_regex.find(input) {
-                let identifier = result.as_str();
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                let token = if self.kw_int_regex.is_match(identifier) {
-                    Token::KwInt
-                } else if self.kw_return_regex.is_match(identifier) {
-                    Token::KwReturn
-                } else if self.kw_void_regex.is_match(identifier) {
-                    Token::KwVoid
-                } else {
-                    Token::Identifier(identifier.to_owned())
+            match self.input[self.position..] {
+                [b'\n', ..] => {
+                    newline = true;
+                    self.bump();
+                }
+                [byte, ..] if byte.is_ascii_whitespace() => self.bump(),
+                [b'/', b'/', ..] => self.bump_while(|byte| byte != b'\n'),
+                [b'/', b'*', ..] => {
+                    let (line, column, start) = (self.line, self.column, self.position + self.removed);
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.input[self.position..] {
+                            [b'*', b'/', ..] => {
+                                self.bump();
+                                self.bump();
+                                break;
+                            }
+                            [b'\n', ..] => {
+                                newline = true;
+                                self.bump();
+                            }
+                            [_, ..] => self.bump(),
+                            [] => {
+                                let span = Span::new(self.file.clone(), line, column, start, start + 2);
+                                return Err(Diagnostic::error(Code::UnterminatedComment, "Unterminated comment")
+                                    .with_primary_label(&span, "comment starts here"));
+                            }
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        Ok((self.position != start, newline))
+    }
+
+    fn text(&self, start: usize) -> &'a str {
+        // Tokens are only split on ASCII bytes, so the slice is always valid UTF-8
+        std::str::from_utf8(&self.input[start..self.position]).unwrap()
+    }
+
+    fn scan_identifier(&mut self) -> Result<Token, Diagnostic> {
+        let start = self.position;
+        self.bump_while(is_identifier_continue);
+
+        let identifier = self.text(start);
+        let token = match identifier {
+            "u8" if self.peek() == Some(b'"') => return self.scan_quoted(Encoding::Utf8),
+            "u8" if self.peek() == Some(b'\'') && self.standard >= Standard::C23 => return self.scan_quoted(Encoding::Utf8),
+            "L" | "u" | "U" if matches!(self.peek(), Some(b'"' | b'\'')) => {
+                // Scan the literal anyway so that the rest of the line is still tokenized correctly
+                self.scan_quoted(Encoding::Utf8)?;
+                return Err(Diagnostic::error(Code::Unsupported, format!("Wide literals ('{}' prefix) are not supported", identifier)));
+            }
+            _ => keyword(identifier).unwrap_or_else(|| Token::Identifier(identifier.to_owned())),
+        };
+
+        Ok(token)
+    }
+
+    /**
+    Scan a preprocessing number and interpret it as a constant.
+
+    The whole preprocessing number is consumed even if it is invalid so that `0x1G` is reported as a single bad
+    constant rather than a constant followed by an identifier.
+    */
+    fn scan_constant(&mut self) -> Result<Token, Diagnostic> {
+        let start = self.position;
+        loop {
+            match self.input[self.position..] {
+                [b'e' | b'E' | b'p' | b'P', b'+' | b'-', ..] => {
+                    self.bump();
+                    self.bump();
+                }
+                [byte, ..] if is_identifier_continue(byte) || byte == b'.' => self.bump(),
+                [b'\'', byte, ..] if self.standard >= Standard::C23 && is_identifier_continue(byte) => {
+                    self.bump();
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        let spelling = self.text(start);
+        let is_hexadecimal = spelling.starts_with("0x") || spelling.starts_with("0X");
+        let is_floating = spelling.contains('.') || if is_hexadecimal {
+            spelling.contains(['p', 'P'])
+        } else {
+            spelling.contains(['e', 'E'])
+        };
+
+        if is_floating {
+            parse_floating_constant(spelling, self.standard).map(Token::FloatingConstant)
+        } else {
+            IntegerConstant::parse(spelling, self.standard).map(Token::Constant)
+        }
+    }
+
+    /**
+    Scan a character constant or a string literal, starting at the opening quote.
+
+    The literal is always consumed up to its closing quote, even when one of its escape sequences is invalid.
+    */
+    fn scan_quoted(&mut self, encoding: Encoding) -> Result<Token, Diagnostic> {
+        let quote = self.peek().unwrap();
+        self.bump();
+
+        let mut value = vec![];
+        let mut error = None;
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => {
+                    return Err(Diagnostic::error(Code::UnterminatedLiteral, format!("Missing terminating {} character", quote as char)));
+                }
+                Some(byte) if byte == quote => {
+                    self.bump();
+                    break;
+                }
+                Some(b'\\') => {
+                    if let Err(err) = self.scan_escape_sequence(&mut value) {
+                        error.get_or_insert(err);
+                    }
+                }
+                Some(byte) => {
+                    value.push(byte);
+                    self.bump();
+                }
+            }
+        }
+
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        if quote == b'"' {
+            return Ok(Token::StringLiteral(value));
+        }
+
+        match (encoding, value.as_slice()) {
+            (_, []) => Err(Diagnostic::error(Code::InvalidCharacterConstant, "Empty character constant")),
+            // Plain char is signed on x86-64
+            (Encoding::Plain, [byte]) => Ok(Token::CharLiteral(*byte as i8 as i32)),
+            (Encoding::Utf8, [byte]) => Ok(Token::CharLiteral(*byte as i32)),
+            // Multi-character constants get the same implementation-defined value as with gcc
+            (Encoding::Plain, bytes) => Ok(Token::CharLiteral(bytes.iter().fold(0, |value: i32, byte| (value << 8) | *byte as i32))),
+            (Encoding::Utf8, _) => Err(Diagnostic::error(Code::InvalidCharacterConstant, "Character too large for a u8 character constant")),
+        }
+    }
+
+    /**
+    Decode the escape sequence starting at the backslash and append the bytes it stands for to `value`.
+    */
+    fn scan_escape_sequence(&mut self, value: &mut Vec<u8>) -> Result<(), Diagnostic> {
+        self.bump();
+
+        let Some(byte) = self.peek() else {
+            return Err(Diagnostic::error(Code::InvalidEscapeSequence, "Incomplete escape sequence"));
+        };
+
+        match byte {
+            b'0'..=b'7' => {
+                let start = self.position;
+                let mut code = 0u32;
+                while self.position - start < 3 && self.peek().is_some_and(|byte| (b'0'..=b'7').contains(&byte)) {
+                    code = code * 8 + (self.peek().unwrap() - b'0') as u32;
+                    self.bump();
+                }
+
+                let byte = u8::try_from(code).map_err(|_| Diagnostic::error(Code::InvalidEscapeSequence, format!("Octal escape sequence '\\{}' out of range", self.text(start))))?;
+                value.push(byte);
+            }
+            b'x' => {
+                self.bump();
+                let start = self.position;
+                self.bump_while(|byte| byte.is_ascii_hexdigit());
+
+                let digits = self.text(start);
+                if digits.is_empty() {
+                    return Err(Diagnostic::error(Code::InvalidEscapeSequence, "\\x used with no following hex digits"));
+                }
+
+                let code = digits.bytes().fold(0u32, |code, digit| code.saturating_mul(16).saturating_add((digit as char).to_digit(16).unwrap()));
+                let byte = u8::try_from(code).map_err(|_| Diagnostic::error(Code::InvalidEscapeSequence, format!("Hex escape sequence '\\x{}' out of range", digits)))?;
+                value.push(byte);
+            }
+            b'u' | b'U' => {
+                let length = if byte == b'u' { 4 } else { 8 };
+                self.bump();
+
+                let start = self.position;
+                while self.position - start < length && self.peek().is_some_and(|byte| byte.is_ascii_hexdigit()) {
+                    self.bump();
+                }
+
+                let digits = self.text(start);
+                if digits.len() != length {
+                    return Err(Diagnostic::error(Code::InvalidEscapeSequence, format!("Incomplete universal character name '\\{}{}'", byte as char, digits)));
+                }
+
+                let code_point = u32::from_str_radix(digits, 16).unwrap();
+                let character = char::from_u32(code_point)
+                    .filter(|c| *c as u32 >= 0xA0 || matches!(c, '$' | '@' | '`'))
+                    .ok_or_else(|| Diagnostic::error(Code::InvalidEscapeSequence, format!("Invalid universal character name '\\{}{}'", byte as char, digits)))?;
+
+                let mut buffer = [0; 4];
+                value.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+            }
+            _ => {
+                let escaped = match byte {
+                    b'\'' | b'"' | b'?' | b'\\' => byte,
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b'f' => 0x0C,
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'v' => 0x0B,
+                    _ => {
+                        let start = self.position;
+                        self.bump();
+                        self.bump_while(|byte| (byte & 0xC0) == 0x80);
+                        return Err(Diagnostic::error(Code::InvalidEscapeSequence, format!("Unknown escape sequence '\\{}'", self.text(start))));
+                    }
                 };
 
-                return Some(Ok(token));
-            } else if let Some(result) = self.constant_regex.find(input) {
-                let value = result.as_str();
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::Constant(u64::from_str(value).unwrap())));
-            } else if let Some(result) = self.open_brace_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::OpeningBrace));
-            } else if let Some(result) = self.close_brace_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::ClosingBrace));
-            } else if let Some(result) = self.open_parenthesis_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::OpenParenthesis));
-            } else if let Some(result) = self.semicolon_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::Semicolon));
-            } else if let Some(result) = self.close_parenthesis_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::CloseParenthesis));
-            } else if let Some(result) = self.two_hyphens_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::TwoHyphens));
-            } else if let Some(result) = self.hyphen_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::Hyphen));
-            } else if let Some(result) = self.tilde_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::Tilde));
-            } else if let Some(result) = self.plus_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-
-                return Some(Ok(Token::Plus));
-            } else if let Some(result) = self.asterisk_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-                return Some(Ok(Token::Asterisk));
-            } else if let Some(result) = self.forward_slash_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-                return Some(Ok(Token::ForwardSlash));
-            } else if let Some(result) = self.percent_regex.find(input) {
-                let (_, next) = input.split_at(result.len());
-                self.input = next;
-                return Some(Ok(Token::Percent));
-            } else {
-                return Some(Err("Unknown token".to_owned()))
+                value.push(escaped);
+                self.bump();
             }
         }
+
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn scan_punctuator(&mut self) -> Result<Token, Diagnostic> {
+        let Some((token, length)) = match_punctuator(&self.input[self.position..]) else {
+            let start = self.position;
+            // Skip the whole character so that a multibyte character is reported only once
+            self.bump();
+            self.bump_while(|byte| (byte & 0xC0) == 0x80);
+            return Err(Diagnostic::error(Code::UnknownToken, format!("Unknown token '{}'", self.text(start))));
+        };
+
+        for _ in 0..length {
+            self.bump();
+        }
+
+        Ok(token)
+    }
+}
+
+/**
+Encoding prefix of a character constant or a string literal.
+*/
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Encoding {
+    Plain,
+    Utf8,
+}
+
+/**
+Find the longest punctuator at the start of `input`, digraphs included.
+
+Longer punctuators have to be listed before their prefixes so that the maximal munch rule is respected.
+*/
+fn match_punctuator(input: &[u8]) -> Option<(Token, usize)> {
+    let punctuator = match input {
+        [b'%', b':', b'%', b':', ..] => (Token::TwoHashes, 4),
+        [b'<', b'<', b'=', ..] => (Token::TwoLessThanEqual, 3),
+        [b'>', b'>', b'=', ..] => (Token::TwoGreaterThanEqual, 3),
+        [b'.', b'.', b'.', ..] => (Token::Ellipsis, 3),
+        [b'-', b'>', ..] => (Token::Arrow, 2),
+        [b'-', b'-', ..] => (Token::TwoHyphens, 2),
+        [b'-', b'=', ..] => (Token::HyphenEqual, 2),
+        [b'+', b'+', ..] => (Token::TwoPluses, 2),
+        [b'+', b'=', ..] => (Token::PlusEqual, 2),
+        [b'<', b'<', ..] => (Token::TwoLessThan, 2),
+        [b'<', b'=', ..] => (Token::LessThanEqual, 2),
+        [b'<', b':', ..] => (Token::OpenBracket, 2),
+        [b'<', b'%', ..] => (Token::OpeningBrace, 2),
+        [b'>', b'>', ..] => (Token::TwoGreaterThan, 2),
+        [b'>', b'=', ..] => (Token::GreaterThanEqual, 2),
+        [b'=', b'=', ..] => (Token::TwoEquals, 2),
+        [b'!', b'=', ..] => (Token::ExclamationEqual, 2),
+        [b'&', b'&', ..] => (Token::TwoAmpersands, 2),
+        [b'&', b'=', ..] => (Token::AmpersandEqual, 2),
+        [b'|', b'|', ..] => (Token::TwoPipes, 2),
+        [b'|', b'=', ..] => (Token::PipeEqual, 2),
+        [b'^', b'=', ..] => (Token::CaretEqual, 2),
+        [b'*', b'=', ..] => (Token::AsteriskEqual, 2),
+        [b'/', b'=', ..] => (Token::ForwardSlashEqual, 2),
+        [b'%', b'=', ..] => (Token::PercentEqual, 2),
+        [b'%', b'>', ..] => (Token::ClosingBrace, 2),
+        [b'%', b':', ..] => (Token::Hash, 2),
+        [b':', b'>', ..] => (Token::CloseBracket, 2),
+        [b'#', b'#', ..] => (Token::TwoHashes, 2),
+        [b'(', ..] => (Token::OpenParenthesis, 1),
+        [b')', ..] => (Token::CloseParenthesis, 1),
+        [b'{', ..] => (Token::OpeningBrace, 1),
+        [b'}', ..] => (Token::ClosingBrace, 1),
+        [b'[', ..] => (Token::OpenBracket, 1),
+        [b']', ..] => (Token::CloseBracket, 1),
+        [b';', ..] => (Token::Semicolon, 1),
+        [b'~', ..] => (Token::Tilde, 1),
+        [b'+', ..] => (Token::Plus, 1),
+        [b'-', ..] => (Token::Hyphen, 1),
+        [b'*', ..] => (Token::Asterisk, 1),
+        [b'/', ..] => (Token::ForwardSlash, 1),
+        [b'%', ..] => (Token::Percent, 1),
+        [b'.', ..] => (Token::Dot, 1),
+        [b'&', ..] => (Token::Ampersand, 1),
+        [b'|', ..] => (Token::Pipe, 1),
+        [b'^', ..] => (Token::Caret, 1),
+        [b'!', ..] => (Token::Exclamation, 1),
+        [b'<', ..] => (Token::LessThan, 1),
+        [b'>', ..] => (Token::GreaterThan, 1),
+        [b'=', ..] => (Token::Equal, 1),
+        [b'?', ..] => (Token::QuestionMark, 1),
+        [b':', ..] => (Token::Colon, 1),
+        [b',', ..] => (Token::Comma, 1),
+        [b'#', ..] => (Token::Hash, 1),
+        _ => return None,
+    };
+
+    Some(punctuator)
+}
+
+/**
+Keyword spelled `identifier`, if any.
+*/
+pub fn keyword(identifier: &str) -> Option<Token> {
+    let keyword = match identifier {
+        "int" => Token::KwInt,
+        "return" => Token::KwReturn,
+        "void" => Token::KwVoid,
+        "double" => Token::KwDouble,
+        "long" => Token::KwLong,
+        "short" => Token::KwShort,
+        "char" => Token::KwChar,
+        "signed" => Token::KwSigned,
+        "unsigned" => Token::KwUnsigned,
+        "if" => Token::KwIf,
+        "else" => Token::KwElse,
+        "while" => Token::KwWhile,
+        "do" => Token::KwDo,
+        "for" => Token::KwFor,
+        "break" => Token::KwBreak,
+        "continue" => Token::KwContinue,
+        "switch" => Token::KwSwitch,
+        "case" => Token::KwCase,
+        "default" => Token::KwDefault,
+        "goto" => Token::KwGoto,
+        "static" => Token::KwStatic,
+        "extern" => Token::KwExtern,
+        _ => return None,
+    };
+
+    Some(keyword)
+}
+
+fn is_identifier_start(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'_'
+}
+
+fn is_identifier_continue(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
@@ -8,20 +8,29 @@ mod emitter;
 mod tacky;
 mod stack_allocator;
 mod asm_pass;
+mod standard;
+mod preprocessor;
+mod diagnostic;
+mod semantic;
 
-use crate::asm_pass::{AsmPass, BinaryOperation, InvalidMovRewrite, PseudoRegister};
+use crate::asm_pass::{AsmPass, BinaryOperation, ConversionOperation, InvalidMovRewrite, PseudoRegister};
 use crate::codegen::codegen;
 use crate::codewriter::CodeWriter;
+use crate::diagnostic::{Code, Diagnostic, DiagnosticFormat, SourceMap};
 use crate::emitter::emit;
-use crate::lexer::Tokenizer;
-use crate::parser::parse;
+use crate::lexer::PreprocessingToken;
+use crate::parser::{parse, TokenStream};
+use crate::preprocessor::{spell_output, Preprocessor};
+use crate::semantic::{IdentifierResolution, LabelResolution, LoopLabelling, SemanticPass, TypeChecking};
+use crate::standard::Standard;
 use crate::tacky::TackEmitter;
 use clap::Parser;
 use std::fs;
 use std::fs::File;
 use std::io::{Error, Write};
-use std::path::PathBuf;
-use std::process::{ExitCode, Termination};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
 
 #[derive(Parser)]
 struct Cli {
@@ -37,35 +46,61 @@ struct Cli {
     #[arg(long)]
     tacky: bool,
 
+    #[arg(long = "std", value_enum, default_value_t = Standard::C17)]
+    standard: Standard,
+
+    #[arg(long = "diagnostics-format", value_enum, default_value_t = DiagnosticFormat::Human)]
+    diagnostics_format: DiagnosticFormat,
+
+    #[arg(short = 'E')]
+    preprocess: bool,
+
+    #[arg(short = 'I', value_name = "DIR")]
+    include_paths: Vec<PathBuf>,
+
+    #[arg(short = 'D', value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+
+    #[arg(short = 'U', value_name = "NAME")]
+    undefines: Vec<String>,
+
     program: String,
 }
 
 #[derive(Debug)]
 enum CompilerError {
-    LexerError(String),
-    ParserError(String),
-    IoError(std::io::Error),
+    Preprocessor(Diagnostic),
+    Lexer(Diagnostic),
+    Parser(Diagnostic),
+    Semantic(Diagnostic),
+    // gcc failed to assemble or link the program
+    Assembler(Diagnostic),
+    Io(std::io::Error),
 }
 
-impl Termination for CompilerError {
-    fn report(self) -> ExitCode {
+impl CompilerError {
+    fn report(self, format: DiagnosticFormat, sources: &SourceMap) -> ExitCode {
         match self {
-            CompilerError::LexerError(_) => ExitCode::FAILURE,
-            CompilerError::ParserError(_) => ExitCode::FAILURE,
-            CompilerError::IoError(_) => ExitCode::FAILURE
+            CompilerError::Preprocessor(diagnostic) => eprint!("{}", format.render(&diagnostic, sources)),
+            CompilerError::Lexer(diagnostic) => eprint!("{}", format.render(&diagnostic, sources)),
+            CompilerError::Parser(diagnostic) => eprint!("{}", format.render(&diagnostic, sources)),
+            CompilerError::Semantic(diagnostic) => eprint!("{}", format.render(&diagnostic, sources)),
+            CompilerError::Assembler(diagnostic) => eprint!("{}", format.render(&diagnostic, sources)),
+            CompilerError::Io(err) => eprintln!("{}", err),
         }
+
+        ExitCode::FAILURE
     }
 }
 
 impl From<Error> for CompilerError {
     fn from(value: Error) -> Self {
-        CompilerError::IoError(value)
+        CompilerError::Io(value)
     }
 }
 
 struct FileSet {
     source: PathBuf,
-    preprocessed_source: PathBuf,
     assembly_file: PathBuf,
     executable: PathBuf,
 }
@@ -75,9 +110,6 @@ impl FileSet {
         let mut assembly_file = PathBuf::from(&source);
         assembly_file.set_extension("s");
 
-        let mut preprocessed_source = PathBuf::from(&source);
-        preprocessed_source.set_extension("pre.c");
-
         let mut executable = PathBuf::from(&source);
         executable.set_extension("");
 
@@ -85,7 +117,6 @@ impl FileSet {
             source: PathBuf::from(source),
             assembly_file,
             executable,
-            preprocessed_source,
         }
     }
 
@@ -93,10 +124,6 @@ impl FileSet {
         &self.source
     }
 
-    pub fn preprocessed_source(&self) -> &PathBuf {
-        &self.preprocessed_source
-    }
-
     pub fn assembly_file(&self) -> &PathBuf {
         &self.assembly_file
     }
@@ -106,42 +133,80 @@ impl FileSet {
     }
 }
 
-fn main() -> Result<(), CompilerError> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    let format = cli.diagnostics_format;
+    let sources = Rc::new(SourceMap::default());
 
+    match compile(cli, &sources) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(format, &sources),
+    }
+}
+
+fn compile(cli: Cli, sources: &Rc<SourceMap>) -> Result<(), CompilerError> {
     let file_set = FileSet::from_source_path(cli.program.as_str());
 
-    std::process::Command::new("gcc").args(["-E",
-        "-P",
-        file_set.source().to_str().unwrap(),
-        "-o",
-        file_set.preprocessed_source().to_str().unwrap()
-    ]).output()?;
+    let tokens = {
+        let mut preprocessor = Preprocessor::new(cli.standard, cli.include_paths, sources.clone());
+        let result = preprocess(&mut preprocessor, &cli.defines, &cli.undefines, file_set.source());
+
+        // Warnings come first, as they may explain an error
+        for warning in preprocessor.take_warnings() {
+            eprint!("{}", cli.diagnostics_format.render(&warning, sources));
+        }
+
+        result.map_err(CompilerError::Preprocessor)?
+    };
+
+    if cli.preprocess {
+        print!("{}", spell_output(&tokens, cli.standard));
+        return Ok(());
+    }
 
-    let source = fs::read_to_string(file_set.preprocessed_source())?;
-    fs::remove_file(file_set.preprocessed_source())?;
-    let tokenizer = Tokenizer::new(source.as_str());
+    let tokens = tokens.into_iter().map(|token| match token.token {
+        Ok(value) => Ok((value, token.span)),
+        // Tokens of a replacement list are lexed with the macro definition, before being expanded
+        Err(diagnostic) => Err(diagnostic.with_expansion_labels(&token.span)),
+    });
 
     if cli.lex {
-        let tokens: Result<Vec<_>, _> = tokenizer.collect();
+        let tokens: Result<Vec<_>, _> = tokens.collect();
         return match tokens {
             Ok(tokens) => {
                 println!("{:#?}", tokens);
                 Ok(())
             }
-            Err(err) => Err(CompilerError::LexerError(err))
+            Err(err) => Err(CompilerError::Lexer(err))
         };
     }
 
-    let ast = parse(&mut tokenizer.peekable()).map_err(|err| CompilerError::ParserError(err))?;
+    let ast = parse(&mut TokenStream::new(tokens.collect())).map_err(CompilerError::Parser)?;
 
     if cli.parse {
         println!("{:#?}", ast);
         return Ok(());
     }
 
-    let mut ir_emitter = TackEmitter::new();
-    let ir = ir_emitter.emit_program(&ast);
+    let ast = {
+        let semantic_passes: Vec<Box<dyn SemanticPass>> = vec![
+            Box::new(IdentifierResolution::new()),
+            Box::new(LoopLabelling::new()),
+            Box::new(LabelResolution::new()),
+            Box::new(TypeChecking::new()),
+        ];
+
+        semantic_passes.into_iter().try_fold(ast, |ast, mut pass| {
+            let result = pass.run(ast);
+            for warning in pass.take_warnings() {
+                eprint!("{}", cli.diagnostics_format.render(&warning, sources));
+            }
+
+            result
+        }).map_err(CompilerError::Semantic)?
+    };
+
+    let ir = TackEmitter::new().emit_program(&ast).map_err(CompilerError::Semantic)?;
 
     if cli.tacky {
         println!("{:#?}", ir);
@@ -154,7 +219,8 @@ fn main() -> Result<(), CompilerError> {
         let asm_passes: Vec<Box<dyn AsmPass>> = vec![
             Box::new(PseudoRegister::new()),
             Box::new(InvalidMovRewrite::new()),
-            Box::new(BinaryOperation::new())
+            Box::new(BinaryOperation::new()),
+            Box::new(ConversionOperation::new())
         ];
 
         asm_passes.into_iter().fold(asm, |instructions, mut pass| { pass.run(instructions) })
@@ -174,11 +240,33 @@ fn main() -> Result<(), CompilerError> {
     let mut output_file = File::create(file_set.assembly_file())?;
     output_file.write_all(writer.as_str().as_bytes())?;
 
-    let output = std::process::Command::new("gcc").args(["-arch", "x86_64", file_set.assembly_file().to_str().unwrap(), "-o", file_set.executable().to_str().unwrap()]).output()?;
-
-    eprintln!("{}", String::from_utf8(output.stderr).unwrap());
+    let output = std::process::Command::new("gcc").args([file_set.assembly_file().to_str().unwrap(), "-o", file_set.executable().to_str().unwrap()]).output()?;
 
     fs::remove_file(file_set.assembly_file)?;
 
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let mut diagnostic = Diagnostic::error(Code::AssemblerFailed, format!("gcc failed to assemble or link the program ({})", output.status));
+        if !stderr.trim().is_empty() {
+            diagnostic = diagnostic.with_note(stderr.trim_end());
+        }
+        return Err(CompilerError::Assembler(diagnostic));
+    }
+    if !stderr.trim().is_empty() {
+        let warning = Diagnostic::warning(Code::AssemblerWarning, "gcc reported warnings").with_note(stderr.trim_end());
+        eprint!("{}", cli.diagnostics_format.render(&warning, sources));
+    }
+
     Ok(())
 }
+
+fn preprocess(preprocessor: &mut Preprocessor, defines: &[String], undefines: &[String], source: &Path) -> Result<Vec<PreprocessingToken>, Diagnostic> {
+    for definition in defines {
+        preprocessor.define(definition)?;
+    }
+    for name in undefines {
+        preprocessor.undefine(name)?;
+    }
+
+    preprocessor.preprocess(source)
+}
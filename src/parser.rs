@@ -1,73 +1,649 @@
-use crate::ast::{BinaryOperator, Expression, Factor, Function, Program, Statement, UnaryOperator};
-use crate::lexer::{Token, Tokenizer};
+use crate::ast::{
+    BinaryOperator, Block, BlockItem, Constant, Declaration, Expression, Factor, ForInit, Function, IncrementOperator, Initializer, Parameter, Program,
+    Statement, StorageClass, SwitchCases, Type, VariableDeclaration,
+    UnaryOperator,
+};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::{IntegerConstant, Span, Token};
+use crate::semantic::evaluate_integer_constant;
 use std::iter::Peekable;
+use std::vec::IntoIter;
 
-pub type TokenStream<'a> = Peekable<Tokenizer<'a>>;
+/**
+Tokens of the translation unit, which remembers the span of the last token taken so that a missing token can be
+suggested right after it.
+*/
+pub struct TokenStream {
+    tokens: Peekable<IntoIter<Result<(Token, Span), Diagnostic>>>,
+    previous: Option<Span>,
+}
+
+impl TokenStream {
+    pub fn new(tokens: Vec<Result<(Token, Span), Diagnostic>>) -> Self {
+        TokenStream {
+            tokens: tokens.into_iter().peekable(),
+            previous: None,
+        }
+    }
+}
+
+// Assignment and the conditional operator bind looser than every binary operator
+const ASSIGNMENT_PRECEDENCE: usize = 1;
+pub(crate) const CONDITIONAL_PRECEDENCE: usize = 3;
 
 macro_rules! expect_token {
     ($tokenizer: ident, $token: expr) => {{
-        let token = next_token($tokenizer)?;
+        let previous = $tokenizer.previous.clone();
+        let (token, span) = next_token($tokenizer)?;
         if $token != token {
-            return Err(format!("Expected token {:?}, got {:?}", $token, token));
+            return Err(unexpected_token(format!("Expected token {:?}, got {:?}", $token, token), &span, &token, &$token, previous.as_ref()));
         }
         token
     }};
 }
 
-fn next_token(tokens: &mut TokenStream) -> Result<Token, String> {
-    tokens.next().unwrap_or(Err("Unexpected end-of-file".to_owned()))
-}
+/**
+Error for a token `found` which doesn't fit the grammar. When a semicolon was expected and the token starts something
+new, suggest to insert the semicolon right after the `previous` token.
+*/
+fn unexpected_token(message: String, span: &Span, found: &Token, expected: &Token, previous: Option<&Span>) -> Diagnostic {
+    let diagnostic = Diagnostic::error(Code::UnexpectedToken, message).with_primary_label(span, "");
 
-fn peek(tokens: &mut TokenStream) -> Result<Token, String> {
-    match tokens.peek() {
-        None => Err("Unexpected end-of-file".to_owned()),
-        Some(Err(err)) => Err(err.to_string()),
-        Some(Ok(token)) => Ok(token.clone())
+    match (expected, previous) {
+        (Token::Semicolon, Some(previous)) if previous.expansion().is_none() && starts_new_construct(found) => {
+            diagnostic.with_suggestion("add a semicolon", &previous.shrink_to_end(), ";")
+        }
+        _ => diagnostic,
     }
 }
 
-type ParserResult<T> = Result<T, String>;
+/**
+Whether a token can't continue what comes before it, because it closes the block or starts a statement or a
+declaration. Other tokens, like `,` or an operator, tell that something else than a semicolon is missing.
+*/
+fn starts_new_construct(token: &Token) -> bool {
+    is_specifier(token) || matches!(
+        token,
+        Token::ClosingBrace | Token::Identifier(_) | Token::KwReturn | Token::KwIf | Token::KwElse | Token::KwWhile | Token::KwDo
+            | Token::KwFor | Token::KwBreak | Token::KwContinue | Token::KwSwitch | Token::KwCase | Token::KwDefault | Token::KwGoto
+    )
+}
 
-pub fn parse(tokens: &mut TokenStream) -> ParserResult<Program> {
-    let function = parse_function(tokens)?;
+fn end_of_file() -> Diagnostic {
+    Diagnostic::error(Code::UnexpectedEndOfFile, "Unexpected end-of-file")
+}
+
+fn next_token(tokens: &mut TokenStream) -> ParserResult<(Token, Span)> {
+    let (token, span) = tokens.tokens.next().unwrap_or_else(|| Err(end_of_file()))?;
+    tokens.previous = Some(span.clone());
+    Ok((token, span))
+}
 
-    if tokens.count() > 0 {
-        return Err("Extra token in the source".into());
+fn peek(tokens: &mut TokenStream) -> ParserResult<Token> {
+    match tokens.tokens.peek() {
+        None => Err(end_of_file()),
+        Some(Err(err)) => Err(err.clone()),
+        Some(Ok((token, _))) => Ok(token.clone())
     }
+}
 
-    Ok(Program::new(function))
+fn peek_span(tokens: &mut TokenStream) -> ParserResult<Span> {
+    match tokens.tokens.peek() {
+        None => Err(end_of_file()),
+        Some(Err(err)) => Err(err.clone()),
+        Some(Ok((_, span))) => Ok(span.clone())
+    }
 }
 
-fn parse_function(tokens: &mut TokenStream) -> ParserResult<Function> {
-    if Token::KwInt != next_token(tokens)? {
-        return Err("Expected token 'int'".to_owned());
+type ParserResult<T> = Result<T, Diagnostic>;
+
+pub fn parse(tokens: &mut TokenStream) -> ParserResult<Program> {
+    let mut declarations = vec![];
+
+    while tokens.tokens.peek().is_some() {
+        declarations.extend(parse_declaration(tokens)?);
     }
 
-    let name = if let Token::Identifier(name) = next_token(tokens)? {
-        name
-    } else {
-        return Err("Expected identifier".to_owned())
-    };
+    Ok(Program::new(declarations))
+}
 
+/**
+Parse a parameter list, where `()` is the same as `(void)`.
+*/
+fn parse_parameters(tokens: &mut TokenStream) -> ParserResult<Vec<Parameter>> {
     expect_token!(tokens, Token::OpenParenthesis);
-    expect_token!(tokens, Token::KwVoid);
+
+    let mut params = vec![];
+    match peek(tokens)? {
+        Token::KwVoid => {
+            next_token(tokens)?;
+        }
+        Token::CloseParenthesis => {}
+        _ => loop {
+            let ty = parse_type(tokens)?;
+            let declared = process_declarator(parse_declarator(tokens)?, ty)?;
+            if declared.params.is_some() {
+                return Err(Diagnostic::error(Code::Unsupported, "Parameters of function type are not supported")
+                    .with_primary_label(&declared.span, ""));
+            }
+
+            // A parameter declared as an array is a pointer to its first element
+            params.push(Parameter { name: declared.name, ty: declared.ty.decay(), span: declared.span });
+
+            if peek(tokens)? != Token::Comma {
+                break;
+            }
+            next_token(tokens)?;
+        },
+    }
+
     expect_token!(tokens, Token::CloseParenthesis);
+
+    Ok(params)
+}
+
+fn parse_block(tokens: &mut TokenStream) -> ParserResult<Block> {
     expect_token!(tokens, Token::OpeningBrace);
 
-    let body = parse_statement(tokens)?;
+    let mut items = vec![];
+    while peek(tokens)? != Token::ClosingBrace {
+        items.extend(parse_block_item(tokens)?);
+    }
+
+    expect_token!(tokens, Token::ClosingBrace);
+
+    Ok(Block::new(items))
+}
+
+/**
+Parse a statement, or a declaration which gives an item for each of its declarators.
+*/
+fn parse_block_item(tokens: &mut TokenStream) -> ParserResult<Vec<BlockItem>> {
+    if !is_specifier(&peek(tokens)?) {
+        return Ok(vec![BlockItem::Statement(parse_statement(tokens)?)]);
+    }
+
+    let declarations = parse_declaration(tokens)?;
+    for declaration in &declarations {
+        match declaration {
+            Declaration::Function(function) if function.body().is_some() => {
+                return Err(Diagnostic::error(Code::NestedFunctionDefinition, "Function definition is not allowed here")
+                    .with_primary_label(function.span(), ""));
+            }
+            Declaration::Function(function) if function.storage_class() == Some(StorageClass::Static) => {
+                return Err(Diagnostic::error(Code::InvalidStorageClass, "Invalid storage class for block-scope function")
+                    .with_primary_label(function.span(), ""));
+            }
+            Declaration::Variable(declaration) if declaration.storage_class == Some(StorageClass::Extern) && declaration.init.is_some() => {
+                return Err(Diagnostic::error(Code::InvalidStorageClass, "`extern` variable has an initializer")
+                    .with_primary_label(&declaration.span, ""));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(declarations.into_iter().map(BlockItem::Declaration).collect())
+}
+
+/**
+Parse a declaration, which gives a declaration for each of its comma-separated declarators. Only a declaration with a
+single function declarator can be a definition, with a body rather than a semicolon.
+*/
+fn parse_declaration(tokens: &mut TokenStream) -> ParserResult<Vec<Declaration>> {
+    let (base_type, storage_class) = parse_specifiers(tokens)?;
+
+    let mut declarations = vec![];
+    loop {
+        let Declared { name, span, ty, params } = process_declarator(parse_declarator(tokens)?, base_type.clone())?;
+
+        let Some(name) = name else {
+            return Err(Diagnostic::error(Code::UnexpectedToken, "Expected identifier").with_primary_label(&span, ""));
+        };
+
+        if let Some(params) = params {
+            if declarations.is_empty() && peek(tokens)? == Token::OpeningBrace {
+                if let Some(param) = params.iter().find(|param| param.name.is_none()) {
+                    return Err(Diagnostic::error(Code::MissingParameterName, "Parameter name omitted in function definition")
+                        .with_primary_label(&param.span, ""));
+                }
+
+                let body = parse_block(tokens)?;
+                return Ok(vec![Declaration::Function(Function::new(name, ty, params, Some(body), storage_class, span))]);
+            }
+
+            declarations.push(Declaration::Function(Function::new(name, ty, params, None, storage_class, span)));
+        } else {
+            let init = if peek(tokens)? == Token::Equal {
+                next_token(tokens)?;
+                Some(parse_initializer(tokens)?)
+            } else {
+                None
+            };
+
+            // An array of unknown size takes its size from its initializer, and can only be defined elsewhere otherwise
+            let ty = match (ty, &init) {
+                (Type::Array(_, None), Some(Initializer::Compound(initializers, brace))) if initializers.is_empty() => {
+                    return Err(Diagnostic::error(Code::InvalidArraySize, "Array of unknown size has an empty initializer")
+                        .with_primary_label(brace, ""));
+                }
+                (Type::Array(element, None), Some(Initializer::Compound(initializers, _))) => Type::Array(element, Some(initializers.len() as u64)),
+                (ty @ Type::Array(_, None), None) if storage_class != Some(StorageClass::Extern) => {
+                    return Err(Diagnostic::error(Code::InvalidArraySize, format!("Array size missing in `{}` of type `{}`", name, ty))
+                        .with_primary_label(&span, ""));
+                }
+                (ty, _) => ty,
+            };
+
+            declarations.push(Declaration::Variable(VariableDeclaration { name, ty, init, storage_class, span }));
+        }
+
+        if peek(tokens)? != Token::Comma {
+            break;
+        }
+        next_token(tokens)?;
+    }
+
+    expect_token!(tokens, Token::Semicolon);
+
+    Ok(declarations)
+}
+
+/**
+Parse an initializer, which is either an expression or a brace-enclosed list of initializers. The list can end with a
+comma.
+*/
+fn parse_initializer(tokens: &mut TokenStream) -> ParserResult<Initializer> {
+    if peek(tokens)? != Token::OpeningBrace {
+        return Ok(Initializer::Single(parse_expression(tokens, 0)?));
+    }
 
+    let (_, span) = next_token(tokens)?;
+    let mut initializers = vec![];
+    while peek(tokens)? != Token::ClosingBrace {
+        initializers.push(parse_initializer(tokens)?);
+
+        if peek(tokens)? != Token::Comma {
+            break;
+        }
+        next_token(tokens)?;
+    }
     expect_token!(tokens, Token::ClosingBrace);
 
-    Ok(Function::new(name, body))
+    Ok(Initializer::Compound(initializers, span))
+}
+
+/**
+Parse the specifiers of a declaration, which are type specifiers and at most one storage class, in any order.
+*/
+fn parse_specifiers(tokens: &mut TokenStream) -> ParserResult<(Type, Option<StorageClass>)> {
+    let mut type_specifiers = vec![];
+    let mut storage_class = None;
+
+    while is_specifier(&peek(tokens)?) {
+        let (token, span) = next_token(tokens)?;
+        match token {
+            Token::KwStatic | Token::KwExtern => {
+                if storage_class.is_some() {
+                    return Err(Diagnostic::error(Code::InvalidStorageClass, "Multiple storage classes in declaration specifiers")
+                        .with_primary_label(&span, ""));
+                }
+                storage_class = Some(if token == Token::KwStatic { StorageClass::Static } else { StorageClass::Extern });
+            }
+            token => type_specifiers.push((token, span)),
+        }
+    }
+
+    Ok((resolve_type(tokens, &type_specifiers)?, storage_class))
+}
+
+fn is_specifier(token: &Token) -> bool {
+    is_type_specifier(token) || matches!(token, Token::KwStatic | Token::KwExtern)
+}
+
+fn is_type_specifier(token: &Token) -> bool {
+    matches!(token, Token::KwInt | Token::KwLong | Token::KwShort | Token::KwChar | Token::KwSigned | Token::KwUnsigned | Token::KwDouble)
+}
+
+fn parse_type(tokens: &mut TokenStream) -> ParserResult<Type> {
+    let mut type_specifiers = vec![];
+    while is_type_specifier(&peek(tokens)?) {
+        type_specifiers.push(next_token(tokens)?);
+    }
+
+    resolve_type(tokens, &type_specifiers)
+}
+
+/**
+Parse the type of a cast, which is a type followed by an abstract declarator, like `int *`.
+*/
+fn parse_type_name(tokens: &mut TokenStream) -> ParserResult<Type> {
+    let ty = parse_type(tokens)?;
+    let declared = process_declarator(parse_declarator(tokens)?, ty)?;
+
+    match (declared.name, declared.params) {
+        (None, None) => Ok(declared.ty),
+        (Some(name), _) => Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected identifier `{}` in type name", name))
+            .with_primary_label(&declared.span, "")),
+        (None, Some(_)) => Err(Diagnostic::error(Code::Unsupported, "Function types are not supported in type names")
+            .with_primary_label(&declared.span, "")),
+    }
+}
+
+/**
+Declarator of a declaration, which derives the type of the declared name from the base type given by the specifiers.
+The name is left out in abstract declarators.
+*/
+enum Declarator {
+    Name(String, Span),
+    // Where the name would be
+    Abstract(Span),
+    Pointer(Box<Declarator>),
+    // The size is left out in `int a[]`
+    Array(Box<Declarator>, Option<u64>),
+    Function(Vec<Parameter>, Box<Declarator>),
+}
+
+impl Declarator {
+    fn span(&self) -> &Span {
+        match self {
+            Declarator::Name(_, span) | Declarator::Abstract(span) => span,
+            Declarator::Pointer(declarator) | Declarator::Array(declarator, _) | Declarator::Function(_, declarator) => declarator.span(),
+        }
+    }
+}
+
+/**
+Name and type given by a declarator. A function declarator gives its parameters, and the return type as type.
+*/
+struct Declared {
+    name: Option<String>,
+    span: Span,
+    ty: Type,
+    params: Option<Vec<Parameter>>,
+}
+
+/**
+Parse a declarator, where `*` binds looser than the parameter list and the array sizes which follow the name:
+`int *f(void)` is a function returning a pointer, and `int *a[3]` an array of pointers.
+*/
+fn parse_declarator(tokens: &mut TokenStream) -> ParserResult<Declarator> {
+    if peek(tokens)? == Token::Asterisk {
+        next_token(tokens)?;
+        return Ok(Declarator::Pointer(Box::new(parse_declarator(tokens)?)));
+    }
+
+    let mut declarator = match peek(tokens)? {
+        Token::Identifier(name) => {
+            let (_, span) = next_token(tokens)?;
+            Declarator::Name(name, span)
+        }
+        Token::OpenParenthesis => {
+            next_token(tokens)?;
+            let declarator = parse_declarator(tokens)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+            declarator
+        }
+        _ => Declarator::Abstract(peek_span(tokens)?.shrink_to_start()),
+    };
+
+    // `int a[2][3]` is an array of 2 arrays of 3 ints
+    loop {
+        declarator = match peek(tokens)? {
+            Token::OpenParenthesis => Declarator::Function(parse_parameters(tokens)?, Box::new(declarator)),
+            Token::OpenBracket => Declarator::Array(Box::new(declarator), parse_array_size(tokens)?),
+            _ => return Ok(declarator),
+        };
+    }
+}
+
+/**
+Parse the size of an array declarator, which has to be a positive integer constant expression unless it is left out.
+*/
+fn parse_array_size(tokens: &mut TokenStream) -> ParserResult<Option<u64>> {
+    let (_, span) = next_token(tokens)?;
+    if peek(tokens)? == Token::CloseBracket {
+        next_token(tokens)?;
+        return Ok(None);
+    }
+
+    let size = parse_expression(tokens, 0)?;
+    expect_token!(tokens, Token::CloseBracket);
+
+    let Some(size) = evaluate_integer_constant(&size).and_then(|size| size.integer_value()) else {
+        return Err(Diagnostic::error(Code::InvalidArraySize, "Array size is not an integer constant").with_primary_label(&span, ""));
+    };
+    if size <= 0 {
+        return Err(Diagnostic::error(Code::InvalidArraySize, format!("Array size must be positive, found {}", size))
+            .with_primary_label(&span, ""));
+    }
+
+    Ok(Some(size as u64))
+}
+
+/**
+Apply `declarator` to `base`, from the outside in: the base type of the inner declarator is the type derived by the
+outer one.
+*/
+fn process_declarator(declarator: Declarator, base: Type) -> ParserResult<Declared> {
+    match declarator {
+        Declarator::Name(name, span) => Ok(Declared { name: Some(name), span, ty: base, params: None }),
+        Declarator::Abstract(span) => Ok(Declared { name: None, span, ty: base, params: None }),
+        Declarator::Pointer(declarator) => process_declarator(*declarator, Type::Pointer(Box::new(base))),
+        Declarator::Array(declarator, _) if !base.is_complete() => {
+            Err(Diagnostic::error(Code::InvalidArraySize, format!("Array has incomplete element type `{}`", base))
+                .with_primary_label(declarator.span(), ""))
+        }
+        Declarator::Array(declarator, size) => process_declarator(*declarator, Type::Array(Box::new(base), size)),
+        Declarator::Function(_, declarator) if base.is_array() => {
+            Err(Diagnostic::error(Code::ArrayReturnType, "Function cannot return an array").with_primary_label(declarator.span(), ""))
+        }
+        Declarator::Function(params, declarator) => match *declarator {
+            Declarator::Name(name, span) => Ok(Declared { name: Some(name), span, ty: base, params: Some(params) }),
+            declarator => Err(Diagnostic::error(Code::Unsupported, "Function pointers are not supported")
+                .with_primary_label(declarator.span(), "")),
+        },
+    }
+}
+
+/**
+Type named by a list of type specifiers, which can come in any order. `long long` has the same representation as
+`long`, so it is taken as `long`.
+*/
+fn resolve_type(tokens: &mut TokenStream, specifiers: &[(Token, Span)]) -> ParserResult<Type> {
+    let Some((_, span)) = specifiers.last() else {
+        let (token, span) = next_token(tokens)?;
+        return Err(Diagnostic::error(Code::UnexpectedToken, format!("Expected type, got {:?}", token)).with_primary_label(&span, ""));
+    };
+
+    let count = |token: Token| specifiers.iter().filter(|(specifier, _)| *specifier == token).count();
+    let (int, long, short, char, signed, unsigned, double) = (
+        count(Token::KwInt), count(Token::KwLong), count(Token::KwShort), count(Token::KwChar),
+        count(Token::KwSigned), count(Token::KwUnsigned), count(Token::KwDouble),
+    );
+
+    let invalid = || Diagnostic::error(Code::UnexpectedToken, "Invalid combination of type specifiers").with_primary_label(span, "");
+    if int > 1 || long > 2 || short > 1 || char > 1 || signed + unsigned > 1 || double > 1 {
+        return Err(invalid());
+    }
+
+    if double == 1 {
+        return match specifiers.len() {
+            1 => Ok(Type::Double),
+            2 if long == 1 => Err(Diagnostic::error(Code::Unsupported, "`long double` is not supported").with_primary_label(span, "")),
+            _ => Err(invalid()),
+        };
+    }
+
+    if char == 1 {
+        if int + long + short > 0 {
+            return Err(invalid());
+        }
+        return Ok(if signed == 1 { Type::SChar } else if unsigned == 1 { Type::UChar } else { Type::Char });
+    }
+
+    match (short, long, unsigned) {
+        (1, 0, 0) => Ok(Type::Short),
+        (1, 0, _) => Ok(Type::UShort),
+        (0, 0, 0) => Ok(Type::Int),
+        (0, 0, _) => Ok(Type::UInt),
+        (0, _, 0) => Ok(Type::Long),
+        (0, _, _) => Ok(Type::ULong),
+        _ => Err(invalid()),
+    }
+}
+
+/**
+Constant with the type of an integer literal, which is the first type of a list that can represent the value. The list
+depends on the suffix, and on whether the literal is decimal.
+*/
+fn integer_constant(constant: &IntegerConstant) -> Constant {
+    let candidates: &[Type] = match (constant.is_unsigned(), constant.is_long(), constant.is_decimal()) {
+        (false, false, true) => &[Type::Int, Type::Long],
+        (false, false, false) => &[Type::Int, Type::UInt, Type::Long, Type::ULong],
+        (false, true, true) => &[Type::Long],
+        (false, true, false) => &[Type::Long, Type::ULong],
+        (true, false, _) => &[Type::UInt, Type::ULong],
+        (true, true, _) => &[Type::ULong],
+    };
+
+    let ty = candidates.iter().find(|ty| constant.value() as i128 <= ty.range().1).unwrap_or(&Type::ULong);
+    Constant::integer(constant.value(), ty.clone())
 }
 
 fn parse_statement(tokens: &mut TokenStream) -> ParserResult<Statement> {
-    let statement = match next_token(tokens)? {
+    let statement = match peek(tokens)? {
         Token::KwReturn => {
+            let (_, span) = next_token(tokens)?;
+            let expression = parse_expression(tokens, 0)?;
+            Statement::Return { expr: expression, span }
+        }
+        Token::KwIf => {
+            next_token(tokens)?;
+            expect_token!(tokens, Token::OpenParenthesis);
+            let condition = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+
+            let then = Box::new(parse_statement(tokens)?);
+
+            // An `else` belongs to the innermost `if`
+            let otherwise = if peek(tokens)? == Token::KwElse {
+                next_token(tokens)?;
+                Some(Box::new(parse_statement(tokens)?))
+            } else {
+                None
+            };
+
+            return Ok(Statement::If { condition, then, otherwise });
+        }
+        Token::OpeningBrace => return Ok(Statement::Compound(parse_block(tokens)?)),
+        Token::KwWhile => {
+            next_token(tokens)?;
+            expect_token!(tokens, Token::OpenParenthesis);
+            let condition = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+            let body = Box::new(parse_statement(tokens)?);
+
+            return Ok(Statement::While { condition, body, label: String::new() });
+        }
+        Token::KwDo => {
+            next_token(tokens)?;
+            let body = Box::new(parse_statement(tokens)?);
+            expect_token!(tokens, Token::KwWhile);
+            expect_token!(tokens, Token::OpenParenthesis);
+            let condition = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+
+            Statement::DoWhile { body, condition, label: String::new() }
+        }
+        Token::KwFor => {
+            next_token(tokens)?;
+            expect_token!(tokens, Token::OpenParenthesis);
+
+            // A declaration ends with its own semicolon
+            let init = if is_specifier(&peek(tokens)?) {
+                let mut declarations = vec![];
+                for declaration in parse_declaration(tokens)? {
+                    match declaration {
+                        Declaration::Variable(declaration) if declaration.storage_class.is_some() => {
+                            return Err(Diagnostic::error(Code::InvalidStorageClass, "Storage class specified in a `for` loop initializer")
+                                .with_primary_label(&declaration.span, ""));
+                        }
+                        Declaration::Variable(declaration) => declarations.push(declaration),
+                        Declaration::Function(function) => {
+                            return Err(Diagnostic::error(Code::UnexpectedToken, "Function declared in a `for` loop initializer")
+                                .with_primary_label(function.span(), ""));
+                        }
+                    }
+                }
+                ForInit::Declaration(declarations)
+            } else {
+                let init = parse_optional_expression(tokens, Token::Semicolon)?;
+                expect_token!(tokens, Token::Semicolon);
+                ForInit::Expression(init)
+            };
+
+            let condition = parse_optional_expression(tokens, Token::Semicolon)?;
+            expect_token!(tokens, Token::Semicolon);
+            let post = parse_optional_expression(tokens, Token::CloseParenthesis)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+            let body = Box::new(parse_statement(tokens)?);
+
+            return Ok(Statement::For { init: Box::new(init), condition, post, body, label: String::new() });
+        }
+        Token::KwSwitch => {
+            let (_, span) = next_token(tokens)?;
+            expect_token!(tokens, Token::OpenParenthesis);
+            let condition = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+            let body = Box::new(parse_statement(tokens)?);
+
+            return Ok(Statement::Switch { condition, body, label: String::new(), cases: SwitchCases::default(), span });
+        }
+        Token::KwCase => {
+            let (_, span) = next_token(tokens)?;
+            let value = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::Colon);
+            let body = Box::new(parse_statement(tokens)?);
+
+            return Ok(Statement::Case { value, body, label: String::new(), span });
+        }
+        Token::KwDefault => {
+            let (_, span) = next_token(tokens)?;
+            expect_token!(tokens, Token::Colon);
+            let body = Box::new(parse_statement(tokens)?);
+
+            return Ok(Statement::Default { body, label: String::new(), span });
+        }
+        Token::KwGoto => {
+            next_token(tokens)?;
+            match next_token(tokens)? {
+                (Token::Identifier(label), span) => Statement::Goto { label, span },
+                (_, span) => return Err(Diagnostic::error(Code::UnexpectedToken, "Expected label").with_primary_label(&span, "")),
+            }
+        }
+        Token::KwBreak => {
+            let (_, span) = next_token(tokens)?;
+            Statement::Break { label: String::new(), span }
+        }
+        Token::KwContinue => {
+            let (_, span) = next_token(tokens)?;
+            Statement::Continue { label: String::new(), span }
+        }
+        Token::Semicolon => Statement::Null,
+        _ => {
             let expression = parse_expression(tokens, 0)?;
-            Statement::Return { expr: expression }
+
+            // A colon can't follow an expression statement, so an identifier followed by one is a label
+            match expression {
+                Expression::Factor(Factor::Var(label, span)) if peek(tokens)? == Token::Colon => {
+                    next_token(tokens)?;
+                    let body = Box::new(parse_statement(tokens)?);
+
+                    return Ok(Statement::Labeled { label, body, span });
+                }
+                expression => Statement::Expression(expression),
+            }
         }
-        token => { return Err(format!("Unexpected token {:?}", token)); }
     };
 
     expect_token!(tokens, Token::Semicolon);
@@ -75,89 +651,308 @@ fn parse_statement(tokens: &mut TokenStream) -> ParserResult<Statement> {
     Ok(statement)
 }
 
+/**
+Parse an expression, unless the next token is the one which follows it, like the empty condition of `for (;;)`.
+*/
+fn parse_optional_expression(tokens: &mut TokenStream, end: Token) -> ParserResult<Option<Expression>> {
+    if peek(tokens)? == end {
+        Ok(None)
+    } else {
+        Ok(Some(parse_expression(tokens, 0)?))
+    }
+}
+
 fn parse_factor(tokens: &mut TokenStream) -> ParserResult<Factor> {
+    match peek(tokens)? {
+        Token::Tilde | Token::Hyphen | Token::Exclamation => {
+            let (operator, span) = parse_unary_operator(tokens)?;
+            let expression = parse_factor(tokens)?;
+
+            Ok(Factor::Unary(operator, Box::new(expression), span))
+        }
+        Token::TwoPluses | Token::TwoHyphens => {
+            let (token, span) = next_token(tokens)?;
+            let operator = match token {
+                Token::TwoPluses => IncrementOperator::PrefixIncrement,
+                _ => IncrementOperator::PrefixDecrement,
+            };
+            let operand = parse_factor(tokens)?;
+
+            Ok(Factor::Increment(operator, Box::new(operand), span))
+        }
+        Token::Ampersand | Token::Asterisk => {
+            let (token, span) = next_token(tokens)?;
+            let operand = Box::new(parse_factor(tokens)?);
+
+            Ok(if token == Token::Ampersand { Factor::AddressOf(operand, span) } else { Factor::Dereference(operand, span) })
+        }
+        Token::OpenParenthesis => {
+            let (_, span) = next_token(tokens)?;
+
+            if is_type_specifier(&peek(tokens)?) {
+                let ty = parse_type_name(tokens)?;
+                expect_token!(tokens, Token::CloseParenthesis);
+                let factor = parse_factor(tokens)?;
+
+                return Ok(Factor::Cast(ty, Box::new(factor), span));
+            }
+
+            let expression = parse_expression(tokens, 0)?;
+            expect_token!(tokens, Token::CloseParenthesis);
+
+            parse_postfix(tokens, Factor::Expression(Box::new(expression)))
+        }
+        _ => {
+            let primary = parse_primary(tokens)?;
+            parse_postfix(tokens, primary)
+        }
+    }
+}
+
+/**
+Parse the postfix operators applied to `factor`, which bind tighter than the prefix ones.
+*/
+fn parse_postfix(tokens: &mut TokenStream, mut factor: Factor) -> ParserResult<Factor> {
+    loop {
+        let operator = match peek(tokens) {
+            Ok(Token::TwoPluses) => IncrementOperator::PostfixIncrement,
+            Ok(Token::TwoHyphens) => IncrementOperator::PostfixDecrement,
+            Ok(Token::OpenBracket) => {
+                let (_, span) = next_token(tokens)?;
+                let index = parse_expression(tokens, 0)?;
+                expect_token!(tokens, Token::CloseBracket);
+
+                factor = Factor::Subscript(Box::new(factor), Box::new(index), span);
+                continue;
+            }
+            _ => return Ok(factor),
+        };
+
+        let (_, span) = next_token(tokens)?;
+        factor = Factor::Increment(operator, Box::new(factor), span);
+    }
+}
+
+fn parse_primary(tokens: &mut TokenStream) -> ParserResult<Factor> {
     match peek(tokens)? {
         Token::Constant(_) => {
-            let token = next_token(tokens)?;
+            let (token, _) = next_token(tokens)?;
             if let Token::Constant(value) = token {
-                Ok(Factor::Constant(value))
+                Ok(Factor::Constant(integer_constant(&value)))
             } else {
                 unreachable!()
             }
         }
-        Token::Tilde | Token::Hyphen => {
-            let operator = parse_unary_operator(tokens)?;
-            let expression = parse_factor(tokens)?;
+        Token::FloatingConstant(_) => {
+            let (token, _) = next_token(tokens)?;
+            if let Token::FloatingConstant(value) = token {
+                Ok(Factor::Constant(Constant::Double(value)))
+            } else {
+                unreachable!()
+            }
+        }
+        Token::CharLiteral(_) => {
+            let (token, _) = next_token(tokens)?;
+            if let Token::CharLiteral(value) = token {
+                Ok(Factor::Constant(Constant::int(value)))
+            } else {
+                unreachable!()
+            }
+        }
+        Token::StringLiteral(_) => {
+            let mut value = vec![];
+
+            // Adjacent string literals are concatenated (translation phase 6)
+            while let Ok(Token::StringLiteral(_)) = peek(tokens) {
+                if let (Token::StringLiteral(bytes), _) = next_token(tokens)? {
+                    value.extend(bytes);
+                }
+            }
 
-            Ok(Factor::Unary(operator, Box::new(expression)))
+            Ok(Factor::StringLiteral(value))
         }
-        Token::OpenParenthesis => {
-            expect_token!(tokens, Token::OpenParenthesis);
-            let expression = parse_expression(tokens, 0)?;
-            expect_token!(tokens, Token::CloseParenthesis);
+        Token::Identifier(_) => {
+            let (token, span) = next_token(tokens)?;
+            let Token::Identifier(name) = token else { unreachable!() };
+
+            if peek(tokens)? == Token::OpenParenthesis {
+                return Ok(Factor::Call(name, parse_arguments(tokens)?, span));
+            }
 
-            Ok(Factor::Expression(Box::new(expression)))
+            Ok(Factor::Var(name, span))
+        }
+        _ => {
+            let (token, span) = next_token(tokens)?;
+            Err(Diagnostic::error(Code::UnexpectedToken, format!("Unexpected token {:?}", token)).with_primary_label(&span, ""))
         }
-        token => Err(format!("Unexpected token {:?}", token))
     }
 }
 
+fn parse_arguments(tokens: &mut TokenStream) -> ParserResult<Vec<Expression>> {
+    expect_token!(tokens, Token::OpenParenthesis);
+
+    let mut args = vec![];
+    if peek(tokens)? != Token::CloseParenthesis {
+        loop {
+            args.push(parse_expression(tokens, 0)?);
+
+            if peek(tokens)? != Token::Comma {
+                break;
+            }
+            next_token(tokens)?;
+        }
+    }
+
+    expect_token!(tokens, Token::CloseParenthesis);
+
+    Ok(args)
+}
+
 fn parse_expression(token: &mut TokenStream, min_precedence: usize) -> ParserResult<Expression> {
     let mut left = Expression::Factor(parse_factor(token)?);
 
-    while {
-        let ope = peek_is_binary_operator(token)?;
-        ope.is_some() && binary_operator_precedence(ope.as_ref().unwrap()) >= min_precedence
-    } {
-        let operator = parse_binary_operator(token)?;
+    loop {
+        let next = peek(token)?;
+        if (next == Token::Equal || compound_assignment_operator(&next).is_some()) && ASSIGNMENT_PRECEDENCE >= min_precedence {
+            let (_, span) = next_token(token)?;
+
+            // Assignment is right-associative: `a = b = c` is `a = (b = c)`
+            let right = parse_expression(token, ASSIGNMENT_PRECEDENCE)?;
+            left = match compound_assignment_operator(&next) {
+                Some(operator) => Expression::CompoundAssignment {
+                    operator,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    span,
+                },
+                None => Expression::Assignment {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    span,
+                },
+            };
+            continue;
+        }
+
+        if next == Token::QuestionMark && CONDITIONAL_PRECEDENCE >= min_precedence {
+            let (_, span) = next_token(token)?;
+
+            // The middle operand is parsed as if it was in parentheses, and the operator is right-associative:
+            // `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+            let then = parse_expression(token, 0)?;
+            expect_token!(token, Token::Colon);
+            let otherwise = parse_expression(token, CONDITIONAL_PRECEDENCE)?;
+
+            left = Expression::Conditional {
+                condition: Box::new(left),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+                span,
+            };
+            continue;
+        }
+
+        match peek_is_binary_operator(token)? {
+            Some(operator) if binary_operator_precedence(&operator) >= min_precedence => {}
+            _ => break,
+        }
+
+        let (operator, span) = parse_binary_operator(token)?;
         let right = parse_expression(token, binary_operator_precedence(&operator) + 1)?;
         left = Expression::Binary {
             left: Box::new(left),
             operator,
             right: Box::new(right),
+            span,
         };
     }
 
     Ok(left)
 }
 
-fn peek_is_binary_operator(tokens: &mut TokenStream) -> Result<Option<BinaryOperator>, String> {
+fn peek_is_binary_operator(tokens: &mut TokenStream) -> ParserResult<Option<BinaryOperator>> {
     let token = peek(tokens)?;
-    match token {
-        Token::Plus => Ok(Some(BinaryOperator::Add)),
-        Token::Hyphen => Ok(Some(BinaryOperator::Sub)),
-        Token::Asterisk => Ok(Some(BinaryOperator::Mul)),
-        Token::ForwardSlash => Ok(Some(BinaryOperator::Div)),
-        Token::Percent => Ok(Some(BinaryOperator::Rem)),
-        _ => Ok(None)
-    }
+    Ok(binary_operator(&token))
 }
 
-fn parse_binary_operator(tokens: &mut TokenStream) -> ParserResult<BinaryOperator> {
-    match next_token(tokens)? {
-        Token::Plus => Ok(BinaryOperator::Add),
-        Token::Hyphen => Ok(BinaryOperator::Sub),
-        Token::Asterisk => Ok(BinaryOperator::Mul),
-        Token::ForwardSlash => Ok(BinaryOperator::Div),
-        Token::Percent => Ok(BinaryOperator::Rem),
-        _ => Err("Expected binary operator".to_owned())
+fn parse_binary_operator(tokens: &mut TokenStream) -> ParserResult<(BinaryOperator, Span)> {
+    let (token, span) = next_token(tokens)?;
+    match binary_operator(&token) {
+        Some(operator) => Ok((operator, span)),
+        None => Err(Diagnostic::error(Code::UnexpectedToken, "Expected binary operator").with_primary_label(&span, ""))
     }
 }
 
-fn parse_unary_operator(tokens: &mut TokenStream) -> ParserResult<UnaryOperator> {
+pub(crate) fn binary_operator(token: &Token) -> Option<BinaryOperator> {
+    let operator = match token {
+        Token::Plus => BinaryOperator::Add,
+        Token::Hyphen => BinaryOperator::Sub,
+        Token::Asterisk => BinaryOperator::Mul,
+        Token::ForwardSlash => BinaryOperator::Div,
+        Token::Percent => BinaryOperator::Rem,
+        Token::Ampersand => BinaryOperator::BitwiseAnd,
+        Token::Pipe => BinaryOperator::BitwiseOr,
+        Token::Caret => BinaryOperator::BitwiseXor,
+        Token::TwoLessThan => BinaryOperator::ShiftLeft,
+        Token::TwoGreaterThan => BinaryOperator::ShiftRight,
+        Token::TwoAmpersands => BinaryOperator::And,
+        Token::TwoPipes => BinaryOperator::Or,
+        Token::TwoEquals => BinaryOperator::Equal,
+        Token::ExclamationEqual => BinaryOperator::NotEqual,
+        Token::LessThan => BinaryOperator::LessThan,
+        Token::LessThanEqual => BinaryOperator::LessOrEqual,
+        Token::GreaterThan => BinaryOperator::GreaterThan,
+        Token::GreaterThanEqual => BinaryOperator::GreaterOrEqual,
+        _ => return None
+    };
+
+    Some(operator)
+}
+
+/**
+Operator applied by a compound assignment such as `+=`.
+*/
+fn compound_assignment_operator(token: &Token) -> Option<BinaryOperator> {
+    let operator = match token {
+        Token::PlusEqual => BinaryOperator::Add,
+        Token::HyphenEqual => BinaryOperator::Sub,
+        Token::AsteriskEqual => BinaryOperator::Mul,
+        Token::ForwardSlashEqual => BinaryOperator::Div,
+        Token::PercentEqual => BinaryOperator::Rem,
+        Token::AmpersandEqual => BinaryOperator::BitwiseAnd,
+        Token::PipeEqual => BinaryOperator::BitwiseOr,
+        Token::CaretEqual => BinaryOperator::BitwiseXor,
+        Token::TwoLessThanEqual => BinaryOperator::ShiftLeft,
+        Token::TwoGreaterThanEqual => BinaryOperator::ShiftRight,
+        _ => return None
+    };
+
+    Some(operator)
+}
+
+fn parse_unary_operator(tokens: &mut TokenStream) -> ParserResult<(UnaryOperator, Span)> {
     match next_token(tokens)? {
-        Token::Hyphen => Ok(UnaryOperator::Negate),
-        Token::Tilde => Ok(UnaryOperator::Complement),
-        token => Err(format!("Expected unary operator ('~' or '-'), got {:?}", token))
+        (Token::Hyphen, span) => Ok((UnaryOperator::Negate, span)),
+        (Token::Tilde, span) => Ok((UnaryOperator::Complement, span)),
+        (Token::Exclamation, span) => Ok((UnaryOperator::Not, span)),
+        (token, span) => {
+            let message = format!("Expected unary operator ('~', '-' or '!'), got {:?}", token);
+            Err(Diagnostic::error(Code::UnexpectedToken, message).with_primary_label(&span, ""))
+        }
     }
 }
 
-fn binary_operator_precedence(binary_operator: &BinaryOperator) -> usize {
+pub(crate) fn binary_operator_precedence(binary_operator: &BinaryOperator) -> usize {
     match binary_operator {
-        BinaryOperator::Div => 50,
-        BinaryOperator::Add => 45,
-        BinaryOperator::Mul => 50,
-        BinaryOperator::Sub => 45,
-        BinaryOperator::Rem => 50
+        BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::Rem => 50,
+        BinaryOperator::Add | BinaryOperator::Sub => 45,
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 40,
+        BinaryOperator::LessThan | BinaryOperator::LessOrEqual | BinaryOperator::GreaterThan | BinaryOperator::GreaterOrEqual => 35,
+        BinaryOperator::Equal | BinaryOperator::NotEqual => 30,
+        BinaryOperator::BitwiseAnd => 25,
+        BinaryOperator::BitwiseXor => 20,
+        BinaryOperator::BitwiseOr => 15,
+        BinaryOperator::And => 10,
+        BinaryOperator::Or => 5,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,65 @@
+use crate::standard::Standard;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(super) const FILE: &str = "__FILE__";
+pub(super) const LINE: &str = "__LINE__";
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/**
+Definitions of the predefined macros whose value doesn't depend on where they are used.
+*/
+pub(super) fn predefined_source(standard: Standard) -> String {
+    let version = match standard {
+        Standard::C17 => "201710L",
+        Standard::C23 => "202311L",
+    };
+    let (date, time) = date_and_time();
+
+    [
+        ("__STDC__", "1"),
+        ("__STDC_HOSTED__", "1"),
+        ("__STDC_VERSION__", version),
+        ("__DATE__", date.as_str()),
+        ("__TIME__", time.as_str()),
+        ("__x86_64__", "1"),
+        ("__linux__", "1"),
+        ("__ctoy__", "1"),
+    ].iter().map(|(name, value)| format!("#define {} {}\n", name, value)).collect()
+}
+
+/**
+Spelling of `__DATE__` and `__TIME__`, in UTC. Like gcc, `SOURCE_DATE_EPOCH` overrides the current time to make
+builds reproducible.
+*/
+fn date_and_time() -> (String, String) {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or_default());
+
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time = seconds % 86400;
+
+    (
+        format!("\"{} {:>2} {}\"", MONTHS[month - 1], day, year),
+        format!("\"{:02}:{:02}:{:02}\"", time / 3600, time / 60 % 60, time % 60),
+    )
+}
+
+/**
+Convert a number of days since 1970-01-01 to a date of the proleptic Gregorian calendar.
+*/
+fn civil_from_days(days: i64) -> (i64, usize, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month as usize, day)
+}
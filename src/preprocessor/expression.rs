@@ -0,0 +1,281 @@
+use crate::ast::BinaryOperator;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::{PreprocessingToken, Span, Token};
+use crate::parser::{binary_operator, binary_operator_precedence, CONDITIONAL_PRECEDENCE};
+use crate::preprocessor::{identifier, PreprocessorResult};
+use crate::standard::Standard;
+
+/**
+Value of a preprocessor expression, in which every integer has the type `intmax_t` or `uintmax_t`.
+*/
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Signed(i64),
+    Unsigned(u64),
+}
+
+impl Value {
+    fn from_bool(value: bool) -> Self {
+        Value::Signed(value as i64)
+    }
+
+    fn is_true(self) -> bool {
+        match self {
+            Value::Signed(value) => value != 0,
+            Value::Unsigned(value) => value != 0,
+        }
+    }
+
+    fn as_unsigned(self) -> u64 {
+        match self {
+            Value::Signed(value) => value as u64,
+            Value::Unsigned(value) => value,
+        }
+    }
+
+    /**
+    Apply the usual arithmetic conversions: if either operand is unsigned, both are.
+    */
+    fn convert(left: Value, right: Value) -> (Value, Value) {
+        match (left, right) {
+            (Value::Signed(_), Value::Signed(_)) => (left, right),
+            _ => (Value::Unsigned(left.as_unsigned()), Value::Unsigned(right.as_unsigned())),
+        }
+    }
+}
+
+/**
+Evaluate the controlling expression of `#if` or `#elif`, once `defined` operators are applied and macros are
+expanded.
+*/
+pub(super) fn evaluate(tokens: &[PreprocessingToken], directive: &PreprocessingToken, standard: Standard) -> PreprocessorResult<bool> {
+    if tokens.is_empty() {
+        return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, format!("#{} with no expression", directive.spelling))
+            .with_primary_label(&directive.span, ""));
+    }
+
+    let mut evaluator = Evaluator {
+        tokens,
+        position: 0,
+        standard,
+        end: tokens.last().unwrap().span.clone(),
+    };
+
+    let value = evaluator.expression(0, true)?;
+    if let Some(token) = evaluator.tokens.get(evaluator.position) {
+        return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, format!("Missing binary operator before token '{}'", token.spelling))
+            .with_primary_label(&token.span, ""));
+    }
+
+    Ok(value.is_true())
+}
+
+struct Evaluator<'a> {
+    tokens: &'a [PreprocessingToken],
+    position: usize,
+    standard: Standard,
+    // Location reported when the expression ends too early
+    end: Span,
+}
+
+impl<'a> Evaluator<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).and_then(|token| token.token.as_ref().ok())
+    }
+
+    fn next(&mut self) -> PreprocessorResult<&'a PreprocessingToken> {
+        let token = self.tokens.get(self.position)
+            .ok_or_else(|| Diagnostic::error(Code::InvalidPreprocessorExpression, "Expected value in expression")
+                .with_primary_label(&self.end, ""))?;
+        self.position += 1;
+
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token, spelling: &str) -> PreprocessorResult<()> {
+        let token = self.tokens.get(self.position)
+            .ok_or_else(|| Diagnostic::error(Code::InvalidPreprocessorExpression, format!("Expected '{}' in preprocessor expression", spelling))
+                .with_primary_label(&self.end, ""))?;
+        self.position += 1;
+
+        if token.token.as_ref() != Ok(&expected) {
+            return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, format!("Expected '{}' in preprocessor expression, got '{}'", spelling, token.spelling))
+                .with_primary_label(&token.span, ""));
+        }
+
+        Ok(())
+    }
+
+    /**
+    Evaluate an expression by precedence climbing. Operands that are not `evaluated`, like the right operand of
+    `0 && x`, are only checked for syntax errors.
+    */
+    fn expression(&mut self, min_precedence: usize, evaluated: bool) -> PreprocessorResult<Value> {
+        let mut left = self.unary(evaluated)?;
+
+        loop {
+            if self.peek() == Some(&Token::QuestionMark) && CONDITIONAL_PRECEDENCE >= min_precedence {
+                self.position += 1;
+                let condition = left.is_true();
+
+                let then = self.expression(0, evaluated && condition)?;
+                self.expect(Token::Colon, ":")?;
+                let otherwise = self.expression(CONDITIONAL_PRECEDENCE, evaluated && !condition)?;
+
+                let (then, otherwise) = Value::convert(then, otherwise);
+                left = if condition { then } else { otherwise };
+                continue;
+            }
+
+            let Some(operator) = self.peek().and_then(binary_operator) else { break };
+            let precedence = binary_operator_precedence(&operator);
+            if precedence < min_precedence {
+                break;
+            }
+
+            let operator_token = self.next()?.clone();
+            let right_evaluated = match operator {
+                BinaryOperator::And => evaluated && left.is_true(),
+                BinaryOperator::Or => evaluated && !left.is_true(),
+                _ => evaluated,
+            };
+            let right = self.expression(precedence + 1, right_evaluated)?;
+
+            left = if evaluated {
+                binary(operator, left, right, &operator_token)?
+            } else {
+                Value::Signed(0)
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self, evaluated: bool) -> PreprocessorResult<Value> {
+        let token = self.next()?;
+
+        let value = match &token.token {
+            Ok(Token::Constant(constant)) => {
+                if constant.is_unsigned() || constant.value() > i64::MAX as u64 {
+                    Value::Unsigned(constant.value())
+                } else {
+                    Value::Signed(constant.value() as i64)
+                }
+            }
+            Ok(Token::CharLiteral(value)) => Value::Signed(*value as i64),
+            Ok(Token::OpenParenthesis) => {
+                let value = self.expression(0, evaluated)?;
+                self.expect(Token::CloseParenthesis, ")")?;
+                value
+            }
+            Ok(Token::Plus) => self.unary(evaluated)?,
+            Ok(Token::Hyphen) => match self.unary(evaluated)? {
+                Value::Signed(value) => Value::Signed(value.wrapping_neg()),
+                Value::Unsigned(value) => Value::Unsigned(value.wrapping_neg()),
+            },
+            Ok(Token::Tilde) => match self.unary(evaluated)? {
+                Value::Signed(value) => Value::Signed(!value),
+                Value::Unsigned(value) => Value::Unsigned(!value),
+            },
+            Ok(Token::Exclamation) => Value::from_bool(!self.unary(evaluated)?.is_true()),
+            Ok(Token::FloatingConstant(_)) => {
+                return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, "Floating constant in preprocessor expression")
+                    .with_primary_label(&token.span, ""));
+            }
+            Err(diagnostic) => return Err(diagnostic.clone()),
+            _ => match identifier(token) {
+                // Identifiers left after macro expansion are replaced with 0, except the C23 boolean constants
+                Some("true") if self.standard >= Standard::C23 => Value::Signed(1),
+                Some(_) => Value::Signed(0),
+                None => {
+                    return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, format!("Token '{}' is not valid in preprocessor expressions", token.spelling))
+                        .with_primary_label(&token.span, ""));
+                }
+            },
+        };
+
+        Ok(value)
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Value, right: Value, token: &PreprocessingToken) -> PreprocessorResult<Value> {
+    // The type of a shift is the type of its left operand, and logical operators always give an `int`
+    match operator {
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => return Ok(shift(operator, left, right)),
+        BinaryOperator::And => return Ok(Value::from_bool(left.is_true() && right.is_true())),
+        BinaryOperator::Or => return Ok(Value::from_bool(left.is_true() || right.is_true())),
+        _ => {}
+    }
+
+    let value = match Value::convert(left, right) {
+        (Value::Signed(left), Value::Signed(right)) => match operator {
+            BinaryOperator::Add => Value::Signed(left.wrapping_add(right)),
+            BinaryOperator::Sub => Value::Signed(left.wrapping_sub(right)),
+            BinaryOperator::Mul => Value::Signed(left.wrapping_mul(right)),
+            BinaryOperator::Div | BinaryOperator::Rem if right == 0 => {
+                return Err(Diagnostic::error(Code::DivisionByZero, "Division by zero in preprocessor expression")
+                    .with_primary_label(&token.span, ""));
+            }
+            BinaryOperator::Div => Value::Signed(left.wrapping_div(right)),
+            BinaryOperator::Rem => Value::Signed(left.wrapping_rem(right)),
+            BinaryOperator::BitwiseAnd => Value::Signed(left & right),
+            BinaryOperator::BitwiseOr => Value::Signed(left | right),
+            BinaryOperator::BitwiseXor => Value::Signed(left ^ right),
+            _ => Value::from_bool(compare(operator, left, right)),
+        },
+        (left, right) => {
+            let (left, right) = (left.as_unsigned(), right.as_unsigned());
+            match operator {
+                BinaryOperator::Add => Value::Unsigned(left.wrapping_add(right)),
+                BinaryOperator::Sub => Value::Unsigned(left.wrapping_sub(right)),
+                BinaryOperator::Mul => Value::Unsigned(left.wrapping_mul(right)),
+                BinaryOperator::Div | BinaryOperator::Rem if right == 0 => {
+                    return Err(Diagnostic::error(Code::DivisionByZero, "Division by zero in preprocessor expression")
+                        .with_primary_label(&token.span, ""));
+                }
+                BinaryOperator::Div => Value::Unsigned(left / right),
+                BinaryOperator::Rem => Value::Unsigned(left % right),
+                BinaryOperator::BitwiseAnd => Value::Unsigned(left & right),
+                BinaryOperator::BitwiseOr => Value::Unsigned(left | right),
+                BinaryOperator::BitwiseXor => Value::Unsigned(left ^ right),
+                _ => Value::from_bool(compare(operator, left, right)),
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+fn compare<T: Ord>(operator: BinaryOperator, left: T, right: T) -> bool {
+    match operator {
+        BinaryOperator::Equal => left == right,
+        BinaryOperator::NotEqual => left != right,
+        BinaryOperator::LessThan => left < right,
+        BinaryOperator::LessOrEqual => left <= right,
+        BinaryOperator::GreaterThan => left > right,
+        BinaryOperator::GreaterOrEqual => left >= right,
+        _ => unreachable!("{:?} is not a comparison", operator),
+    }
+}
+
+/**
+Shift `left` by `right` bits. Like gcc, a negative count shifts in the other direction, and shifting by the width of
+the type or more shifts every bit out.
+*/
+fn shift(operator: BinaryOperator, left: Value, right: Value) -> Value {
+    let count = match right {
+        Value::Signed(count) => count,
+        Value::Unsigned(count) => count.min(i64::MAX as u64) as i64,
+    };
+    let (left_shift, count) = match (operator, count < 0) {
+        (BinaryOperator::ShiftLeft, false) | (BinaryOperator::ShiftRight, true) => (true, count.unsigned_abs()),
+        _ => (false, count.unsigned_abs()),
+    };
+
+    match left {
+        Value::Signed(value) if left_shift => Value::Signed(if count >= 64 { 0 } else { value.wrapping_shl(count as u32) }),
+        Value::Signed(value) => Value::Signed(value >> count.min(63)),
+        Value::Unsigned(value) if left_shift => Value::Unsigned(if count >= 64 { 0 } else { value << count }),
+        Value::Unsigned(value) => Value::Unsigned(if count >= 64 { 0 } else { value >> count }),
+    }
+}
@@ -0,0 +1,387 @@
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::{PreprocessingToken, Span, Token};
+use crate::preprocessor::{identifier, lex, PpToken, PreprocessorResult};
+use crate::standard::Standard;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/**
+Names of the macros that must not be expanded again in a token, i.e. the macros whose expansion produced it.
+*/
+pub(super) type HideSet = Rc<BTreeSet<String>>;
+
+pub(super) const VA_ARGS: &str = "__VA_ARGS__";
+pub(super) const VA_OPT: &str = "__VA_OPT__";
+
+/**
+Result of the substitution of one operand of the replacement list. `None` is a placemarker, which stands for an
+empty argument until `##` operators are applied.
+*/
+type Operand = Vec<Option<PpToken>>;
+
+#[derive(Debug)]
+pub(super) struct Macro {
+    name: String,
+    // Parameters of a function-like macro, `__VA_ARGS__` included for variadic ones
+    parameters: Option<Vec<String>>,
+    variadic: bool,
+    replacement: Vec<PreprocessingToken>,
+    span: Span,
+}
+
+/**
+Invocation of a macro: its name, and the arguments when the macro is function-like.
+*/
+pub(super) struct Invocation {
+    pub name: PpToken,
+    pub arguments: Vec<Vec<PpToken>>,
+    pub close_parenthesis: Option<PpToken>,
+}
+
+impl Macro {
+    pub fn new(name: String, parameters: Option<Vec<String>>, variadic: bool, replacement: Vec<PreprocessingToken>, span: Span) -> PreprocessorResult<Self> {
+        let definition = Macro {
+            name,
+            parameters,
+            variadic,
+            replacement,
+            span,
+        };
+        definition.validate()?;
+
+        Ok(definition)
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn is_function_like(&self) -> bool {
+        self.parameters.is_some()
+    }
+
+    /**
+    Two definitions of the same macro are identical if they have the same parameters, and if their replacement
+    lists are spelled the same way, with whitespace at the same places.
+    */
+    pub fn is_identical(&self, other: &Macro) -> bool {
+        self.parameters == other.parameters
+            && self.replacement.len() == other.replacement.len()
+            && self.replacement.iter().zip(other.replacement.iter()).enumerate().all(|(index, (left, right))| {
+                left.spelling == right.spelling && (index == 0 || left.leading_whitespace == right.leading_whitespace)
+            })
+    }
+
+    fn validate(&self) -> PreprocessorResult<()> {
+        if let Some(first) = self.replacement.first().filter(|token| token.token == Ok(Token::TwoHashes)) {
+            return Err(Diagnostic::error(Code::InvalidMacroDefinition, "'##' cannot appear at either end of a macro expansion")
+                .with_primary_label(&first.span, ""));
+        }
+        if let Some(last) = self.replacement.last().filter(|token| token.token == Ok(Token::TwoHashes)) {
+            return Err(Diagnostic::error(Code::InvalidMacroDefinition, "'##' cannot appear at either end of a macro expansion")
+                .with_primary_label(&last.span, ""));
+        }
+
+        for (index, token) in self.replacement.iter().enumerate() {
+            match identifier(token) {
+                Some(VA_ARGS) if !self.variadic => {
+                    return Err(Diagnostic::error(Code::InvalidMacroDefinition, "__VA_ARGS__ can only appear in the expansion of a variadic macro")
+                        .with_primary_label(&token.span, ""));
+                }
+                Some(VA_OPT) if !self.variadic => {
+                    return Err(Diagnostic::error(Code::InvalidMacroDefinition, "__VA_OPT__ can only appear in the expansion of a variadic macro")
+                        .with_primary_label(&token.span, ""));
+                }
+                Some(VA_OPT) => {
+                    let end = va_opt_end(&self.replacement, index)?;
+                    if self.replacement[index + 2..end].first().is_some_and(|token| token.token == Ok(Token::TwoHashes))
+                        || self.replacement[index + 2..end].last().is_some_and(|token| token.token == Ok(Token::TwoHashes)) {
+                        return Err(Diagnostic::error(Code::InvalidMacroDefinition, "'##' cannot appear at either end of __VA_OPT__")
+                            .with_primary_label(&token.span, ""));
+                    }
+                }
+                _ => {}
+            }
+
+            if self.is_function_like() && token.token == Ok(Token::Hash) {
+                let operand = self.replacement.get(index + 1);
+                if !operand.is_some_and(|operand| self.parameter_index(operand).is_some() || identifier(operand) == Some(VA_OPT)) {
+                    return Err(Diagnostic::error(Code::InvalidMacroDefinition, "'#' is not followed by a macro parameter")
+                        .with_primary_label(&token.span, ""));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parameter_index(&self, token: &PreprocessingToken) -> Option<usize> {
+        let name = identifier(token)?;
+        self.parameters.as_ref()?.iter().position(|parameter| parameter == name)
+    }
+
+    /**
+    Whether a comma at the top level of the arguments starts a new argument, rather than being part of the
+    `__VA_ARGS__` argument.
+    */
+    pub fn separates_arguments(&self, invocation: &Invocation) -> bool {
+        let parameters = self.parameters.as_ref().map(Vec::len).unwrap_or_default();
+        !self.variadic || invocation.arguments.len() + 1 < parameters
+    }
+
+    /**
+    Check the number of arguments of an invocation. The `__VA_ARGS__` argument of a variadic macro may be omitted.
+    */
+    pub fn check_arguments(&self, invocation: &mut Invocation) -> PreprocessorResult<()> {
+        let parameters = self.parameters.as_ref().map(Vec::len).unwrap_or_default();
+
+        // `F()` passes a single empty argument, which is no argument at all for a macro without parameters
+        if parameters == 0 && invocation.arguments.len() == 1 && invocation.arguments[0].is_empty() {
+            invocation.arguments.clear();
+        }
+
+        if self.variadic && invocation.arguments.len() == parameters - 1 {
+            invocation.arguments.push(vec![]);
+        }
+
+        let given = invocation.arguments.len();
+        if given != parameters {
+            let expected = if self.variadic { parameters - 1 } else { parameters };
+            let requirement = if self.variadic { "at least " } else { "" };
+            return Err(Diagnostic::error(Code::MacroArgumentCount, format!(
+                "Macro `{}` requires {}{} arguments, but {} given", self.name, requirement, expected, given
+            ))
+                .with_primary_label(&invocation.name.token.span, "")
+                .with_secondary_label(&self.span, format!("macro `{}` defined here", self.name)));
+        }
+
+        Ok(())
+    }
+
+    /**
+    Replace an invocation of this macro with its replacement list, in which parameters are substituted with the
+    arguments of the invocation and `#` and `##` operators are applied.
+
+    The resulting tokens can't be expanded by this macro anymore when they are rescanned.
+    */
+    pub fn expand(&self, invocation: &Invocation, standard: Standard, expand_argument: &dyn Fn(Vec<PpToken>) -> PreprocessorResult<Vec<PpToken>>) -> PreprocessorResult<Vec<PpToken>> {
+        // A token is painted blue by a function-like macro only if both the name and the closing parenthesis were
+        let mut hide_set = match &invocation.close_parenthesis {
+            Some(close) => invocation.name.hide_set.intersection(&close.hide_set).cloned().collect(),
+            None => (*invocation.name.hide_set).clone(),
+        };
+        hide_set.insert(self.name.clone());
+
+        let mut substitution = Substitution {
+            definition: self,
+            invocation,
+            standard,
+            expand_argument,
+            expanded_arguments: vec![None; invocation.arguments.len()],
+        };
+        let tokens = substitution.substitute(&self.replacement)?;
+
+        Ok(tokens.into_iter().flatten().enumerate().map(|(index, mut token)| {
+            token.hide_set = Rc::new(token.hide_set.union(&hide_set).cloned().collect());
+
+            if index == 0 {
+                token.token.leading_whitespace = invocation.name.token.leading_whitespace;
+                token.token.line_start = invocation.name.token.line_start;
+            } else {
+                token.token.line_start = false;
+            }
+
+            token
+        }).collect())
+    }
+}
+
+struct Substitution<'a> {
+    definition: &'a Macro,
+    invocation: &'a Invocation,
+    standard: Standard,
+    expand_argument: &'a dyn Fn(Vec<PpToken>) -> PreprocessorResult<Vec<PpToken>>,
+    // Arguments are only macro-expanded once, even if the parameter is used several times
+    expanded_arguments: Vec<Option<Vec<PpToken>>>,
+}
+
+impl Substitution<'_> {
+    fn substitute(&mut self, replacement: &[PreprocessingToken]) -> PreprocessorResult<Operand> {
+        let mut result: Operand = vec![];
+        let mut index = 0;
+
+        while index < replacement.len() {
+            let token = &replacement[index];
+
+            if token.token == Ok(Token::TwoHashes) && !result.is_empty() {
+                let (right, length) = self.operand(replacement, index + 1, true)?;
+                let left = result.pop().unwrap();
+
+                let mut right = right.into_iter();
+                result.push(self.paste(left, right.next().flatten(), token)?);
+                result.extend(right);
+                index += 1 + length;
+            } else {
+                let (operand, length) = self.operand(replacement, index, false)?;
+                result.extend(operand);
+                index += length;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /**
+    Substitute the operand starting at `index` in the replacement list, and return it along with the number of tokens
+    it spans.
+    */
+    fn operand(&mut self, replacement: &[PreprocessingToken], index: usize, pasted: bool) -> PreprocessorResult<(Operand, usize)> {
+        let token = &replacement[index];
+        let followed_by_paste = replacement.get(index + 1).is_some_and(|token| token.token == Ok(Token::TwoHashes));
+
+        if self.definition.is_function_like() && token.token == Ok(Token::Hash) {
+            let (operand, length) = self.operand(replacement, index + 1, true)?;
+            let stringified = self.stringify(operand.into_iter().flatten().collect(), token)?;
+            return Ok((vec![Some(stringified)], 1 + length));
+        }
+
+        if identifier(token) == Some(VA_OPT) && self.definition.variadic {
+            let end = va_opt_end(replacement, index)?;
+            let variadic_index = self.definition.parameters.as_ref().unwrap().len() - 1;
+
+            let operand = if self.expanded_argument(variadic_index)?.is_empty() {
+                vec![None]
+            } else {
+                let mut content = self.substitute(&replacement[index + 2..end])?;
+                if let Some(Some(first)) = content.first_mut() {
+                    first.token.leading_whitespace = token.leading_whitespace;
+                }
+
+                if content.iter().all(Option::is_none) { vec![None] } else { content }
+            };
+
+            return Ok((operand, end - index + 1));
+        }
+
+        if let Some(parameter) = self.definition.parameter_index(token) {
+            // Operands of `#` and `##` are the spelling of the argument, the other parameters are fully expanded
+            let argument = if pasted || followed_by_paste {
+                self.invocation.arguments[parameter].clone()
+            } else {
+                self.expanded_argument(parameter)?
+            };
+
+            if argument.is_empty() {
+                return Ok((vec![None], 1));
+            }
+
+            let mut argument: Operand = argument.into_iter().map(Some).collect();
+            if let Some(Some(first)) = argument.first_mut() {
+                first.token.leading_whitespace = token.leading_whitespace;
+            }
+
+            return Ok((argument, 1));
+        }
+
+        let mut token = PpToken::new(token.clone());
+        token.token.span = token.token.span.expanded(&self.definition.name, &self.invocation.name.token.span);
+
+        Ok((vec![Some(token)], 1))
+    }
+
+    fn expanded_argument(&mut self, parameter: usize) -> PreprocessorResult<Vec<PpToken>> {
+        if self.expanded_arguments[parameter].is_none() {
+            let argument = (self.expand_argument)(self.invocation.arguments[parameter].clone())?;
+            self.expanded_arguments[parameter] = Some(argument);
+        }
+
+        Ok(self.expanded_arguments[parameter].clone().unwrap())
+    }
+
+    /**
+    Apply the `#` operator: spell the tokens as a string literal.
+    */
+    fn stringify(&self, tokens: Vec<PpToken>, hash: &PreprocessingToken) -> PreprocessorResult<PpToken> {
+        let mut spelling = String::from("\"");
+        for (index, token) in tokens.iter().enumerate() {
+            let token = &token.token;
+            if index > 0 && (token.leading_whitespace || token.line_start) {
+                spelling.push(' ');
+            }
+
+            if token.spelling.starts_with(['"', '\'']) || token.spelling.starts_with("u8") {
+                for c in token.spelling.chars() {
+                    if c == '"' || c == '\\' {
+                        spelling.push('\\');
+                    }
+                    spelling.push(c);
+                }
+            } else {
+                spelling.push_str(&token.spelling);
+            }
+        }
+        spelling.push('"');
+
+        let span = hash.span.expanded(&self.definition.name, &self.invocation.name.token.span);
+        lex(&spelling, &span, self.standard)
+            .map(PpToken::new)
+            .ok_or_else(|| Diagnostic::error(Code::InvalidTokenPaste, format!("Invalid string literal {} produced by '#'", spelling))
+                .with_primary_label(&span, ""))
+    }
+
+    /**
+    Apply the `##` operator: concatenate the spelling of two tokens, which must form a single token.
+    */
+    fn paste(&self, left: Option<PpToken>, right: Option<PpToken>, operator: &PreprocessingToken) -> PreprocessorResult<Option<PpToken>> {
+        let (left, right) = match (left, right) {
+            (Some(left), Some(right)) => (left, right),
+            (left, None) => return Ok(left),
+            (None, right) => return Ok(right),
+        };
+
+        let spelling = format!("{}{}", left.token.spelling, right.token.spelling);
+        let span = operator.span.expanded(&self.definition.name, &self.invocation.name.token.span);
+
+        let mut token = lex(&spelling, &left.token.span, self.standard).ok_or_else(|| Diagnostic::error(Code::InvalidTokenPaste, format!(
+            "Pasting \"{}\" and \"{}\" does not give a valid preprocessing token", left.token.spelling, right.token.spelling
+        )).with_primary_label(&span, ""))?;
+        token.leading_whitespace = left.token.leading_whitespace;
+        token.line_start = left.token.line_start;
+
+        Ok(Some(PpToken {
+            token,
+            hide_set: left.hide_set,
+        }))
+    }
+}
+
+/**
+Index of the parenthesis closing the `__VA_OPT__` at `index` in a replacement list.
+*/
+fn va_opt_end(replacement: &[PreprocessingToken], index: usize) -> PreprocessorResult<usize> {
+    let va_opt = &replacement[index];
+    if !replacement.get(index + 1).is_some_and(|token| token.token == Ok(Token::OpenParenthesis)) {
+        return Err(Diagnostic::error(Code::InvalidMacroDefinition, "__VA_OPT__ must be followed by an open parenthesis")
+            .with_primary_label(&va_opt.span, ""));
+    }
+
+    let mut depth = 0;
+    for (offset, token) in replacement[index + 1..].iter().enumerate() {
+        match token.token {
+            Ok(Token::OpenParenthesis) => depth += 1,
+            Ok(Token::CloseParenthesis) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(index + 1 + offset);
+                }
+            }
+            Ok(Token::Identifier(ref name)) if name == VA_OPT => {
+                return Err(Diagnostic::error(Code::InvalidMacroDefinition, "__VA_OPT__ may not appear in a __VA_OPT__ operand")
+                    .with_primary_label(&token.span, ""));
+            }
+            _ => {}
+        }
+    }
+
+    Err(Diagnostic::error(Code::InvalidMacroDefinition, "Unterminated __VA_OPT__").with_primary_label(&va_opt.span, ""))
+}
@@ -0,0 +1,830 @@
+use crate::diagnostic::{Code, Diagnostic, SourceMap};
+use crate::lexer::{keyword, IntegerConstant, IntegerSuffix, LogicalSource, PreprocessingToken, Radix, Span, Token, Tokenizer};
+use crate::preprocessor::macros::{HideSet, Invocation, Macro, VA_ARGS};
+use crate::standard::Standard;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+mod builtins;
+mod expression;
+mod macros;
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+const SYSTEM_INCLUDE_PATHS: [&str; 3] = [
+    "/usr/local/include",
+    "/usr/include/x86_64-linux-gnu",
+    "/usr/include",
+];
+
+const HAS_INCLUDE: &str = "__has_include";
+
+type PreprocessorResult<T> = Result<T, Diagnostic>;
+
+/**
+Preprocessing token along with the macros that can't expand it anymore.
+*/
+#[derive(Debug, Clone)]
+struct PpToken {
+    token: PreprocessingToken,
+    hide_set: HideSet,
+}
+
+impl PpToken {
+    fn new(token: PreprocessingToken) -> Self {
+        PpToken {
+            token,
+            hide_set: HideSet::default(),
+        }
+    }
+}
+
+/**
+Tokens of a file being preprocessed, with one token of lookahead.
+*/
+struct SourceFile<'a> {
+    path: &'a Path,
+    tokenizer: Tokenizer<'a>,
+    peeked: Option<PreprocessingToken>,
+}
+
+impl SourceFile<'_> {
+    fn next(&mut self) -> Option<PreprocessingToken> {
+        self.peeked.take().or_else(|| self.tokenizer.next_preprocessing_token())
+    }
+
+    fn peek(&mut self) -> Option<&PreprocessingToken> {
+        if self.peeked.is_none() {
+            self.peeked = self.tokenizer.next_preprocessing_token();
+        }
+        self.peeked.as_ref()
+    }
+
+    /**
+    Read the remaining tokens of the current line.
+    */
+    fn read_line(&mut self) -> Vec<PreprocessingToken> {
+        let mut tokens = vec![];
+        while self.peek().is_some_and(|token| !token.line_start) {
+            tokens.push(self.next().unwrap());
+        }
+        tokens
+    }
+}
+
+/**
+State of an `#if`/`#ifdef`/`#ifndef` section.
+*/
+struct Conditional {
+    span: Span,
+    // Whether the tokens of the current group are kept
+    active: bool,
+    // Whether a group of this section has already been kept
+    taken: bool,
+    // `#else` of the section, after which no other group may follow
+    else_span: Option<Span>,
+}
+
+/**
+Detection of the include guard idiom: a file whose whole content is wrapped in `#ifndef X` ... `#endif`.
+*/
+enum IncludeGuard {
+    Start,
+    Open(String),
+    Closed(String),
+    Invalid,
+}
+
+/**
+In-tree C preprocessor, covering the translation phases 1 to 4.
+*/
+pub struct Preprocessor {
+    standard: Standard,
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+
+    // Files containing `#pragma once`
+    once_files: HashSet<PathBuf>,
+    // Macro guarding the whole content of a file, which doesn't need to be read again while it is defined
+    include_guards: HashMap<PathBuf, String>,
+    include_depth: usize,
+
+    // Text of the files read, quoted by diagnostics
+    sources: Rc<SourceMap>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Preprocessor {
+    pub fn new(standard: Standard, include_paths: Vec<PathBuf>, sources: Rc<SourceMap>) -> Self {
+        let mut preprocessor = Preprocessor {
+            standard,
+            include_paths,
+            macros: HashMap::new(),
+            once_files: HashSet::new(),
+            include_guards: HashMap::new(),
+            include_depth: 0,
+            sources,
+            warnings: vec![],
+        };
+
+        preprocessor.preprocess_source(Path::new("<built-in>"), &builtins::predefined_source(standard), &mut vec![])
+            .expect("Predefined macros are valid definitions");
+
+        preprocessor
+    }
+
+    /**
+    Define a macro from the command line, with `NAME`, `NAME=VALUE` or `NAME(PARAMETERS)=VALUE`.
+    */
+    pub fn define(&mut self, definition: &str) -> PreprocessorResult<()> {
+        let directive = match definition.split_once('=') {
+            Some((name, value)) => format!("#define {} {}\n", name, value),
+            None => format!("#define {} 1\n", definition),
+        };
+
+        self.preprocess_source(Path::new("<command line>"), &directive, &mut vec![])
+    }
+
+    pub fn undefine(&mut self, name: &str) -> PreprocessorResult<()> {
+        self.preprocess_source(Path::new("<command line>"), &format!("#undef {}\n", name), &mut vec![])
+    }
+
+    pub fn preprocess(&mut self, path: &Path) -> PreprocessorResult<Vec<PreprocessingToken>> {
+        let mut output = vec![];
+
+        let physical = fs::read_to_string(path)
+            .map_err(|err| Diagnostic::error(Code::UnreadableFile, format!("{}: {}", path.display(), err)))?;
+        self.preprocess_source(path, &physical, &mut output)?;
+
+        Ok(output.into_iter().map(|token| token.token).collect())
+    }
+
+    /**
+    Warnings reported since the last call.
+    */
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn preprocess_source(&mut self, path: &Path, physical: &str, output: &mut Vec<PpToken>) -> PreprocessorResult<()> {
+        let name = path.to_string_lossy();
+        self.sources.add(&name, physical);
+
+        let source = LogicalSource::new(physical, self.standard);
+        let mut file = SourceFile {
+            path,
+            tokenizer: Tokenizer::new(name.as_ref(), &source, self.standard),
+            peeked: None,
+        };
+
+        let mut conditionals = vec![];
+        let mut include_guard = IncludeGuard::Start;
+        let mut pending = VecDeque::new();
+
+        loop {
+            let token = match pending.pop_front() {
+                Some(token) => token,
+                None => {
+                    let Some(token) = file.next() else { break };
+
+                    if token.line_start && token.token == Ok(Token::Hash) {
+                        self.handle_directive(&mut file, token, &mut conditionals, &mut include_guard, output)?;
+                        continue;
+                    }
+
+                    if conditionals.last().is_some_and(|conditional: &Conditional| !conditional.active) {
+                        continue;
+                    }
+
+                    if conditionals.is_empty() {
+                        include_guard = IncludeGuard::Invalid;
+                    }
+
+                    PpToken::new(token)
+                }
+            };
+
+            self.expand(token, &mut pending, Some(&mut file), output)?;
+        }
+
+        if let Some(conditional) = conditionals.last() {
+            return Err(Diagnostic::error(Code::UnterminatedConditional, "Unterminated conditional directive")
+                .with_primary_label(&conditional.span, "section starts here"));
+        }
+
+        if let IncludeGuard::Closed(name) = include_guard {
+            self.include_guards.insert(canonical_path(path), name);
+        }
+
+        Ok(())
+    }
+
+    /**
+    Macro that `token` invokes, unless `token` was produced by the expansion of that macro.
+    */
+    fn expandable_macro(&self, token: &PpToken) -> Option<&Macro> {
+        let name = identifier(&token.token)?;
+        if token.hide_set.contains(name) {
+            return None;
+        }
+
+        self.macros.get(name)
+    }
+
+    /**
+    Replace the macros in a list of tokens which doesn't come from a file, such as macro arguments or the operands of
+    `#include`.
+    */
+    fn expand_tokens(&self, tokens: Vec<PpToken>) -> PreprocessorResult<Vec<PpToken>> {
+        let mut pending: VecDeque<_> = tokens.into();
+        let mut output = vec![];
+
+        while let Some(token) = pending.pop_front() {
+            self.expand(token, &mut pending, None, &mut output)?;
+        }
+
+        Ok(output)
+    }
+
+    /**
+    Output `token`, or if it invokes a macro, put the expansion of the macro back in front of the pending tokens to
+    rescan it. Arguments of function-like macros are read from the pending tokens, then from the file.
+    */
+    fn expand(&self, token: PpToken, pending: &mut VecDeque<PpToken>, mut file: Option<&mut SourceFile>, output: &mut Vec<PpToken>) -> PreprocessorResult<()> {
+        if let Some(replacement) = self.dynamic_macro(&token.token) {
+            output.push(PpToken::new(replacement));
+            return Ok(());
+        }
+
+        let Some(definition) = self.expandable_macro(&token) else {
+            output.push(token);
+            return Ok(());
+        };
+
+        let mut invocation = Invocation {
+            name: token,
+            arguments: vec![],
+            close_parenthesis: None,
+        };
+
+        if definition.is_function_like() {
+            // The name of a function-like macro which isn't followed by a parenthesis is an ordinary identifier
+            let next = match pending.front() {
+                Some(next) => Some(&next.token),
+                None => file.as_mut().and_then(|file| file.peek()),
+            };
+            if !next.is_some_and(|next| next.token == Ok(Token::OpenParenthesis)) {
+                output.push(invocation.name);
+                return Ok(());
+            }
+
+            next_argument_token(pending, &mut file)?;
+            self.read_arguments(definition, &mut invocation, pending, &mut file)?;
+            definition.check_arguments(&mut invocation)?;
+        }
+
+        let expansion = definition.expand(&invocation, self.standard, &|argument| self.expand_tokens(argument))?;
+        for token in expansion.into_iter().rev() {
+            pending.push_front(token);
+        }
+
+        Ok(())
+    }
+
+    /**
+    Replacement of `__FILE__` and `__LINE__`, which depends on where they are used.
+    */
+    fn dynamic_macro(&self, token: &PreprocessingToken) -> Option<PreprocessingToken> {
+        let name = identifier(token).filter(|name| !self.macros.contains_key(*name))?;
+
+        // Macros expanded inside another macro invocation take the location of the outermost invocation
+        let location = token.span.source_location();
+        let mut replacement = match name {
+            builtins::FILE => {
+                let file = location.file().replace('\\', "\\\\").replace('"', "\\\"");
+                lex(&format!("\"{}\"", file), &token.span, self.standard)?
+            }
+            builtins::LINE => integer_token(location.line() as u64, token),
+            _ => return None,
+        };
+
+        replacement.leading_whitespace = token.leading_whitespace;
+        replacement.line_start = token.line_start;
+        Some(replacement)
+    }
+
+    /**
+    Read the comma-separated arguments of a macro invocation, up to the matching closing parenthesis.
+    */
+    fn read_arguments(&self, definition: &Macro, invocation: &mut Invocation, pending: &mut VecDeque<PpToken>, file: &mut Option<&mut SourceFile>) -> PreprocessorResult<()> {
+        let mut argument = vec![];
+        let mut depth = 0;
+
+        loop {
+            let token = next_argument_token(pending, file)?.ok_or_else(|| Diagnostic::error(Code::UnterminatedMacroInvocation, format!(
+                "Unterminated argument list invoking macro `{}`", invocation.name.token.spelling
+            )).with_primary_label(&invocation.name.token.span, ""))?;
+
+            match token.token.token {
+                Ok(Token::OpenParenthesis) => depth += 1,
+                Ok(Token::CloseParenthesis) if depth == 0 => {
+                    invocation.arguments.push(argument);
+                    invocation.close_parenthesis = Some(token);
+                    return Ok(());
+                }
+                Ok(Token::CloseParenthesis) => depth -= 1,
+                Ok(Token::Comma) if depth == 0 && definition.separates_arguments(invocation) => {
+                    invocation.arguments.push(std::mem::take(&mut argument));
+                    continue;
+                }
+                _ => {}
+            }
+
+            argument.push(token);
+        }
+    }
+
+    fn handle_directive(&mut self, file: &mut SourceFile, hash: PreprocessingToken, conditionals: &mut Vec<Conditional>, include_guard: &mut IncludeGuard, output: &mut Vec<PpToken>) -> PreprocessorResult<()> {
+        let mut line = file.read_line();
+        if line.is_empty() {
+            // Null directive
+            return Ok(());
+        }
+
+        let name_token = line.remove(0);
+        let name = identifier(&name_token).unwrap_or(name_token.spelling.as_str());
+        let skipping = conditionals.last().is_some_and(|conditional| !conditional.active);
+
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                if skipping {
+                    // The whole section is skipped, so its condition doesn't even have to be valid
+                    conditionals.push(Conditional { span: name_token.span, active: false, taken: true, else_span: None });
+                    return Ok(());
+                }
+
+                let condition = self.condition(file, &name_token, line.clone())?;
+
+                *include_guard = match (&include_guard, name, conditionals.is_empty()) {
+                    (IncludeGuard::Start, "ifndef", _) => IncludeGuard::Open(line[0].spelling.clone()),
+                    (_, _, true) => IncludeGuard::Invalid,
+                    (_, _, false) => std::mem::replace(include_guard, IncludeGuard::Invalid),
+                };
+
+                conditionals.push(Conditional { span: name_token.span, active: condition, taken: condition, else_span: None });
+            }
+            "elif" | "elifdef" | "elifndef" if name == "elif" || self.standard >= Standard::C23 => {
+                let conditional = conditionals.last()
+                    .ok_or_else(|| Diagnostic::error(Code::UnbalancedConditional, format!("#{} without #if", name))
+                        .with_primary_label(&name_token.span, ""))?;
+                if let Some(else_span) = &conditional.else_span {
+                    return Err(Diagnostic::error(Code::UnbalancedConditional, format!("#{} after #else", name))
+                        .with_primary_label(&name_token.span, "")
+                        .with_secondary_label(else_span, "previous #else is here"));
+                }
+
+                // Once a group is kept, the conditions of the following ones are not evaluated
+                let condition = !conditional.taken && self.condition(file, &name_token, line)?;
+
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = condition;
+                conditional.taken |= condition;
+
+                if conditionals.len() == 1 {
+                    *include_guard = IncludeGuard::Invalid;
+                }
+            }
+            "else" => {
+                let conditional = conditionals.last_mut()
+                    .ok_or_else(|| Diagnostic::error(Code::UnbalancedConditional, "#else without #if")
+                        .with_primary_label(&name_token.span, ""))?;
+                if let Some(else_span) = &conditional.else_span {
+                    return Err(Diagnostic::error(Code::UnbalancedConditional, "#else after #else")
+                        .with_primary_label(&name_token.span, "")
+                        .with_secondary_label(else_span, "previous #else is here"));
+                }
+
+                conditional.else_span = Some(name_token.span);
+                conditional.active = !conditional.taken;
+                conditional.taken = true;
+
+                if conditionals.len() == 1 {
+                    *include_guard = IncludeGuard::Invalid;
+                }
+            }
+            "endif" => {
+                conditionals.pop().ok_or_else(|| Diagnostic::error(Code::UnbalancedConditional, "#endif without #if")
+                    .with_primary_label(&name_token.span, ""))?;
+
+                if conditionals.is_empty() {
+                    *include_guard = match std::mem::replace(include_guard, IncludeGuard::Invalid) {
+                        IncludeGuard::Open(name) => IncludeGuard::Closed(name),
+                        _ => IncludeGuard::Invalid,
+                    };
+                }
+            }
+            _ if skipping => {}
+            _ => {
+                if conditionals.is_empty() {
+                    *include_guard = IncludeGuard::Invalid;
+                }
+
+                match name {
+                    "define" => self.handle_define(&name_token, line)?,
+                    "undef" => {
+                        let name = self.macro_name(&name_token, &line)?;
+                        self.macros.remove(name);
+                    }
+                    "include" => self.handle_include(file, &name_token, line, output)?,
+                    "error" => return Err(Diagnostic::error(Code::ErrorDirective, format!("#error {}", spell(&line)))
+                        .with_primary_label(&hash.span, "")),
+                    "warning" => {
+                        let warning = Diagnostic::warning(Code::WarningDirective, format!("#warning {}", spell(&line)))
+                            .with_primary_label(&hash.span, "");
+                        self.warnings.push(warning);
+                    }
+                    "pragma" => {
+                        if line.first().is_some_and(|token| token.spelling == "once") {
+                            self.once_files.insert(canonical_path(file.path));
+                        }
+                        // Other pragmas don't affect the compilation
+                    }
+                    _ => return Err(Diagnostic::error(Code::InvalidDirective, format!("Invalid preprocessing directive #{}", name))
+                        .with_primary_label(&name_token.span, "")),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+    Evaluate the condition of a conditional inclusion directive.
+    */
+    fn condition(&self, file: &SourceFile, directive: &PreprocessingToken, line: Vec<PreprocessingToken>) -> PreprocessorResult<bool> {
+        let name = directive.spelling.as_str();
+        match name {
+            "ifdef" | "elifdef" => return self.is_defined(directive, &line),
+            "ifndef" | "elifndef" => return Ok(!self.is_defined(directive, &line)?),
+            _ => {}
+        }
+
+        // `defined` and `__has_include` apply to the tokens as written, before macros are expanded
+        let mut tokens = vec![];
+        let mut index = 0;
+        while index < line.len() {
+            let token = &line[index];
+            let (value, length) = match identifier(token) {
+                Some("defined") => self.defined_operator(token, &line[index + 1..])?,
+                Some(HAS_INCLUDE) => self.has_include_operator(file, token, &line[index + 1..])?,
+                _ => {
+                    tokens.push(PpToken::new(token.clone()));
+                    index += 1;
+                    continue;
+                }
+            };
+
+            tokens.push(PpToken::new(integer_token(value as u64, token)));
+            index += 1 + length;
+        }
+
+        let tokens: Vec<_> = self.expand_tokens(tokens)?.into_iter().map(|token| token.token).collect();
+        expression::evaluate(&tokens, directive, self.standard)
+    }
+
+    /**
+    Apply `defined X` or `defined(X)` to `operand`, and return the result along with the number of tokens of the
+    operand.
+    */
+    fn defined_operator(&self, operator: &PreprocessingToken, operand: &[PreprocessingToken]) -> PreprocessorResult<(bool, usize)> {
+        let parenthesized = operand.first().is_some_and(|token| token.token == Ok(Token::OpenParenthesis));
+        let name = operand.get(parenthesized as usize).and_then(identifier)
+            .ok_or_else(|| Diagnostic::error(Code::InvalidPreprocessorExpression, "Operator \"defined\" requires an identifier")
+                .with_primary_label(&operator.span, ""))?;
+
+        if parenthesized && !operand.get(2).is_some_and(|token| token.token == Ok(Token::CloseParenthesis)) {
+            return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, "Missing ')' after \"defined\"")
+                .with_primary_label(&operator.span, ""));
+        }
+
+        let length = if parenthesized { 3 } else { 1 };
+        Ok((self.is_macro_defined(name), length))
+    }
+
+    /**
+    Apply `__has_include("file")` or `__has_include(<file>)`, and return the result along with the number of tokens of
+    the operand.
+    */
+    fn has_include_operator(&self, file: &SourceFile, operator: &PreprocessingToken, operand: &[PreprocessingToken]) -> PreprocessorResult<(bool, usize)> {
+        if !operand.first().is_some_and(|token| token.token == Ok(Token::OpenParenthesis)) {
+            return Err(Diagnostic::error(Code::InvalidPreprocessorExpression, "Missing '(' after \"__has_include\"")
+                .with_primary_label(&operator.span, ""));
+        }
+
+        let close = operand.iter().position(|token| token.token == Ok(Token::CloseParenthesis))
+            .ok_or_else(|| Diagnostic::error(Code::InvalidPreprocessorExpression, "Missing ')' after \"__has_include\" operand")
+                .with_primary_label(&operator.span, ""))?;
+        let header = operand[1..close].iter().cloned().map(PpToken::new).collect();
+        let (header, quoted) = self.header_name(operator, HAS_INCLUDE, header)?;
+
+        Ok((self.find_header(file, &header, quoted).is_some(), close + 1))
+    }
+
+    fn is_macro_defined(&self, name: &str) -> bool {
+        // `__has_include` is a special operator, which can be tested like a macro, and so are the dynamic macros
+        matches!(name, HAS_INCLUDE | builtins::FILE | builtins::LINE) || self.macros.contains_key(name)
+    }
+
+    /**
+    Name of the macro operand of `#ifdef`, `#ifndef` and `#undef`.
+    */
+    fn macro_name<'a>(&self, directive: &PreprocessingToken, line: &'a [PreprocessingToken]) -> PreprocessorResult<&'a str> {
+        match line.first() {
+            Some(token) => identifier(token).ok_or_else(|| Diagnostic::error(Code::InvalidMacroDefinition, "Macro names must be identifiers")
+                .with_primary_label(&token.span, "")),
+            None => Err(Diagnostic::error(Code::InvalidMacroDefinition, format!("No macro name given in #{} directive", directive.spelling))
+                .with_primary_label(&directive.span, "")),
+        }
+    }
+
+    fn is_defined(&self, directive: &PreprocessingToken, line: &[PreprocessingToken]) -> PreprocessorResult<bool> {
+        let name = self.macro_name(directive, line)?;
+        Ok(self.is_macro_defined(name))
+    }
+
+    fn handle_define(&mut self, directive: &PreprocessingToken, mut line: Vec<PreprocessingToken>) -> PreprocessorResult<()> {
+        let name = self.macro_name(directive, &line)?.to_owned();
+        let name_token = line.remove(0);
+
+        if name == "defined" {
+            return Err(Diagnostic::error(Code::InvalidMacroDefinition, "'defined' cannot be used as a macro name")
+                .with_primary_label(&name_token.span, ""));
+        }
+
+        let (parameters, variadic) = if line.first().is_some_and(|token| token.token == Ok(Token::OpenParenthesis) && !token.leading_whitespace) {
+            let (parameters, variadic, length) = parse_parameters(&name_token, &line)?;
+            line.drain(..length);
+            (Some(parameters), variadic)
+        } else {
+            (None, false)
+        };
+
+        let definition = Macro::new(name.clone(), parameters, variadic, line, name_token.span)?;
+        if let Some(previous) = self.macros.get(&name) {
+            if !previous.is_identical(&definition) {
+                let warning = Diagnostic::warning(Code::MacroRedefined, format!("Macro `{}` redefined", name))
+                    .with_primary_label(definition.span(), "")
+                    .with_secondary_label(previous.span(), "previous definition is here");
+                self.warnings.push(warning);
+            }
+        }
+
+        self.macros.insert(name, definition);
+        Ok(())
+    }
+
+    fn handle_include(&mut self, file: &SourceFile, directive: &PreprocessingToken, line: Vec<PreprocessingToken>, output: &mut Vec<PpToken>) -> PreprocessorResult<()> {
+        let line = line.into_iter().map(PpToken::new).collect();
+        let (header, quoted) = self.header_name(directive, "#include", line)?;
+
+        let path = self.find_header(file, &header, quoted).ok_or_else(|| {
+            let directories: Vec<_> = self.search_paths(file, quoted).map(|path| path.display().to_string()).collect();
+            Diagnostic::error(Code::IncludeNotFound, format!("'{}' file not found", header))
+                .with_primary_label(&directive.span, "")
+                .with_note(format!("searched in: {}", directories.join(", ")))
+        })?;
+
+        let canonical = canonical_path(&path);
+        if self.once_files.contains(&canonical) {
+            return Ok(());
+        }
+        if self.include_guards.get(&canonical).is_some_and(|guard| self.macros.contains_key(guard)) {
+            return Ok(());
+        }
+
+        if self.include_depth >= MAX_INCLUDE_DEPTH {
+            return Err(Diagnostic::error(Code::IncludeTooDeep, format!("#include nested depth {} exceeds maximum", MAX_INCLUDE_DEPTH))
+                .with_primary_label(&directive.span, ""));
+        }
+
+        let physical = fs::read_to_string(&path)
+            .map_err(|err| Diagnostic::error(Code::UnreadableFile, format!("{}: {}", path.display(), err))
+                .with_primary_label(&directive.span, ""))?;
+
+        self.include_depth += 1;
+        let result = self.preprocess_source(&path, &physical, output);
+        self.include_depth -= 1;
+
+        result
+    }
+
+    /**
+    Read the header name of `#include` or `__has_include`, and tell whether it is a quoted one.
+    */
+    fn header_name(&self, directive: &PreprocessingToken, operator: &str, mut tokens: Vec<PpToken>) -> PreprocessorResult<(String, bool)> {
+        // Computed includes get their macros replaced before the header name is looked for
+        if !tokens.first().is_some_and(|token| token.token.spelling.starts_with(['"', '<'])) {
+            tokens = self.expand_tokens(tokens)?;
+        }
+
+        match tokens.first().map(|token| token.token.spelling.as_str()) {
+            Some(spelling) if spelling.starts_with('"') && spelling.len() >= 2 && spelling.ends_with('"') => {
+                Ok((spelling[1..spelling.len() - 1].to_owned(), true))
+            }
+            Some("<") => {
+                let end = tokens.iter().position(|token| token.token.spelling == ">")
+                    .ok_or_else(|| Diagnostic::error(Code::InvalidInclude, "Missing terminating > character")
+                        .with_primary_label(&tokens[0].token.span, ""))?;
+                let tokens: Vec<_> = tokens[1..end].iter().map(|token| token.token.clone()).collect();
+                Ok((spell(&tokens), false))
+            }
+            _ => Err(Diagnostic::error(Code::InvalidInclude, format!("{} expects \"FILENAME\" or <FILENAME>", operator))
+                .with_primary_label(&directive.span, "")),
+        }
+    }
+
+    /**
+    Look for a header in the directory of the current file for quoted names, then in the `-I` directories and the
+    system directories.
+    */
+    fn find_header(&self, file: &SourceFile, header: &str, quoted: bool) -> Option<PathBuf> {
+        self.search_paths(file, quoted).map(|directory| directory.join(header)).find(|path| path.is_file())
+    }
+
+    fn search_paths<'a>(&'a self, file: &SourceFile, quoted: bool) -> impl Iterator<Item = PathBuf> + 'a {
+        let current_directory = match file.path.parent() {
+            Some(directory) if directory != Path::new("") => directory.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        quoted.then_some(current_directory).into_iter()
+            .chain(self.include_paths.iter().cloned())
+            .chain(SYSTEM_INCLUDE_PATHS.iter().map(PathBuf::from))
+    }
+}
+
+/**
+Name of an identifier or a keyword, which the preprocessor doesn't distinguish.
+*/
+fn identifier(token: &PreprocessingToken) -> Option<&str> {
+    match &token.token {
+        Ok(Token::Identifier(name)) => Some(name.as_str()),
+        Ok(_) if keyword(&token.spelling).is_some() => Some(token.spelling.as_str()),
+        _ => None,
+    }
+}
+
+/**
+Parse the parameter list of a function-like macro definition, and return the parameters, whether the macro is
+variadic, and the number of tokens of the list.
+*/
+fn parse_parameters(name: &PreprocessingToken, line: &[PreprocessingToken]) -> PreprocessorResult<(Vec<String>, bool, usize)> {
+    let mut parameters: Vec<String> = vec![];
+    let mut index = 1;
+
+    let unterminated = || Diagnostic::error(Code::InvalidMacroDefinition, "Missing ')' in macro parameter list")
+        .with_primary_label(&name.span, "");
+    if line.get(index).is_some_and(|token| token.token == Ok(Token::CloseParenthesis)) {
+        return Ok((parameters, false, index + 1));
+    }
+
+    loop {
+        let token = line.get(index).ok_or_else(unterminated)?;
+        index += 1;
+
+        if token.token == Ok(Token::Ellipsis) {
+            parameters.push(VA_ARGS.to_owned());
+            return match line.get(index) {
+                Some(close) if close.token == Ok(Token::CloseParenthesis) => Ok((parameters, true, index + 1)),
+                Some(token) => Err(Diagnostic::error(Code::InvalidMacroDefinition, "Expected ')' after '...'")
+                    .with_primary_label(&token.span, "")),
+                None => Err(unterminated()),
+            };
+        }
+
+        let parameter = match identifier(token) {
+            Some(VA_ARGS) => return Err(Diagnostic::error(Code::InvalidMacroDefinition, "__VA_ARGS__ can only appear in the expansion of a variadic macro")
+                .with_primary_label(&token.span, "")),
+            Some(parameter) => parameter,
+            None => return Err(Diagnostic::error(Code::InvalidMacroDefinition, "Expected a parameter name")
+                .with_primary_label(&token.span, "")),
+        };
+        if parameters.iter().any(|existing| existing == parameter) {
+            return Err(Diagnostic::error(Code::InvalidMacroDefinition, format!("Duplicate macro parameter \"{}\"", parameter))
+                .with_primary_label(&token.span, ""));
+        }
+        parameters.push(parameter.to_owned());
+
+        let separator = line.get(index).ok_or_else(unterminated)?;
+        index += 1;
+        match separator.token {
+            Ok(Token::CloseParenthesis) => return Ok((parameters, false, index)),
+            Ok(Token::Comma) => {}
+            _ => return Err(Diagnostic::error(Code::InvalidMacroDefinition, "Expected ',' or ')' in macro parameter list")
+                .with_primary_label(&separator.span, "")),
+        }
+    }
+}
+
+/**
+Next token of the arguments of a macro invocation, read from the pending tokens, then from the file.
+*/
+fn next_argument_token(pending: &mut VecDeque<PpToken>, file: &mut Option<&mut SourceFile>) -> PreprocessorResult<Option<PpToken>> {
+    if let Some(token) = pending.pop_front() {
+        return Ok(Some(token));
+    }
+
+    match file.as_mut().and_then(|file| file.next()) {
+        Some(token) if token.line_start && token.token == Ok(Token::Hash) => {
+            Err(Diagnostic::error(Code::UnterminatedMacroInvocation, "Directives in the arguments of a macro are not supported")
+                .with_primary_label(&token.span, ""))
+        }
+        token => Ok(token.map(PpToken::new)),
+    }
+}
+
+/**
+Decimal integer constant replacing `token`, such as an operator of a preprocessor expression.
+*/
+fn integer_token(value: u64, token: &PreprocessingToken) -> PreprocessingToken {
+    PreprocessingToken {
+        token: Ok(Token::Constant(IntegerConstant::new(value, Radix::Decimal, IntegerSuffix::None))),
+        span: token.span.clone(),
+        spelling: value.to_string(),
+        leading_whitespace: token.leading_whitespace,
+        line_start: false,
+    }
+}
+
+/**
+Lex a spelling that must form exactly one preprocessing token, which is then given `span`. Like the tokens of the
+source, a preprocessing token which isn't a valid C token (e.g. `1.5e+`) keeps its error, which is only reported if the
+token survives preprocessing.
+*/
+fn lex(spelling: &str, span: &Span, standard: Standard) -> Option<PreprocessingToken> {
+    let source = LogicalSource::spelled(spelling);
+    let mut tokenizer = Tokenizer::new("", &source, standard);
+
+    let mut token = tokenizer.next_preprocessing_token().filter(|token| !token.leading_whitespace)?;
+    if tokenizer.next_preprocessing_token().is_some() {
+        return None;
+    }
+
+    token.token = token.token.map_err(|diagnostic| diagnostic.relocated(span));
+    token.span = span.clone();
+    Some(token)
+}
+
+/**
+Spell the preprocessed tokens as source code, one line of output per line of input.
+*/
+pub fn spell_output(tokens: &[PreprocessingToken], standard: Standard) -> String {
+    let mut output = String::new();
+    let mut previous: Option<&PreprocessingToken> = None;
+
+    for token in tokens {
+        if token.line_start {
+            if previous.is_some() {
+                output.push('\n');
+            }
+        } else if token.leading_whitespace || previous.is_some_and(|previous| would_paste(previous, token, standard)) {
+            output.push(' ');
+        }
+
+        output.push_str(&token.spelling);
+        previous = Some(token);
+    }
+
+    output.push('\n');
+    output
+}
+
+/**
+Whether two tokens, like the `-` of `-NEGATE` and the one coming from the expansion of `NEGATE`, would be read as a
+different token if they were spelled without whitespace between them.
+*/
+fn would_paste(left: &PreprocessingToken, right: &PreprocessingToken, standard: Standard) -> bool {
+    let source = LogicalSource::spelled(&format!("{}{}", left.spelling, right.spelling));
+    let mut tokenizer = Tokenizer::new("", &source, standard);
+
+    tokenizer.next_preprocessing_token().is_none_or(|token| token.spelling != left.spelling)
+}
+
+/**
+Spell a list of tokens, keeping a single space where there was whitespace between them.
+*/
+fn spell(tokens: &[PreprocessingToken]) -> String {
+    let mut spelling = String::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 && token.leading_whitespace {
+            spelling.push(' ');
+        }
+        spelling.push_str(&token.spelling);
+    }
+    spelling
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,161 @@
+use crate::ast::{BinaryOperator, Constant, Expression, Factor, Type, UnaryOperator};
+
+/**
+Value of an integer constant expression, like the label of a `case`, or `None` if the expression isn't one. Operands
+are integer constants, and floating constants that are immediately cast to an integer type; operations which are
+undefined, like a division by zero or a signed overflow, don't give a constant.
+*/
+pub(crate) fn evaluate_integer_constant(expression: &Expression) -> Option<Constant> {
+    evaluate(expression, true)
+}
+
+/**
+Value of an arithmetic constant expression, like the initializer of a variable with static storage duration, or `None`
+if the expression isn't one. Unlike in integer constant expressions, floating operands can appear anywhere.
+*/
+pub(crate) fn evaluate_arithmetic_constant(expression: &Expression) -> Option<Constant> {
+    evaluate(expression, false)
+}
+
+fn evaluate(expression: &Expression, integer: bool) -> Option<Constant> {
+    match expression {
+        Expression::Factor(factor) => evaluate_factor(factor, integer),
+        Expression::Binary { left, operator, right, .. } => binary(*operator, evaluate(left, integer)?, evaluate(right, integer)?),
+        Expression::Conditional { condition, then, otherwise, .. } => {
+            // The result has the common type of both branches
+            let (then, otherwise) = (evaluate(then, integer)?, evaluate(otherwise, integer)?);
+            let ty = then.ty().common(&otherwise.ty());
+            if evaluate(condition, integer)?.is_zero() { otherwise.convert(ty) } else { then.convert(ty) }
+        }
+        Expression::Assignment { .. } | Expression::CompoundAssignment { .. } => None,
+    }
+}
+
+fn evaluate_factor(factor: &Factor, integer: bool) -> Option<Constant> {
+    match factor {
+        Factor::Constant(Constant::Double(_)) if integer => None,
+        Factor::Constant(constant) => Some(constant.clone()),
+        Factor::Unary(operator, operand, _) => {
+            let operand = evaluate_factor(operand, integer)?;
+            match (operator, operand) {
+                (UnaryOperator::Not, operand) => Some(Constant::int(operand.is_zero() as i32)),
+                (UnaryOperator::Negate, Constant::Double(value)) => Some(Constant::Double(-value)),
+                (UnaryOperator::Complement, Constant::Double(_)) => None,
+                (UnaryOperator::Negate, operand) => {
+                    let operand = operand.convert(operand.ty().promote())?;
+                    arithmetic(operand.ty(), -operand.integer_value()?)
+                }
+                (UnaryOperator::Complement, operand) => {
+                    let operand = operand.convert(operand.ty().promote())?;
+                    arithmetic(operand.ty(), !operand.integer_value()?)
+                }
+            }
+        }
+        Factor::Cast(ty, operand, _) => {
+            let operand = match operand.as_ref() {
+                Factor::Constant(constant @ Constant::Double(_)) if ty.is_integer() => constant.clone(),
+                operand => evaluate_factor(operand, integer)?,
+            };
+
+            if integer && !ty.is_integer() { None } else { operand.convert(ty.clone()) }
+        }
+        Factor::Expression(expression) => evaluate(expression, integer),
+        Factor::Var(_, _)
+        | Factor::Call(_, _, _)
+        | Factor::StringLiteral(_)
+        | Factor::Increment(_, _, _)
+        | Factor::AddressOf(_, _)
+        | Factor::Dereference(_, _)
+        | Factor::Subscript(_, _, _) => None,
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Constant, right: Constant) -> Option<Constant> {
+    match operator {
+        BinaryOperator::And => return Some(Constant::int((!left.is_zero() && !right.is_zero()) as i32)),
+        BinaryOperator::Or => return Some(Constant::int((!left.is_zero() || !right.is_zero()) as i32)),
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => return shift(operator, left, right),
+        _ => {}
+    }
+
+    let ty = left.ty().common(&right.ty());
+    if ty == Type::Double {
+        return double_binary(operator, left.to_double(), right.to_double());
+    }
+
+    let (left, right) = (left.convert(ty.clone())?.integer_value()?, right.convert(ty.clone())?.integer_value()?);
+    match operator {
+        BinaryOperator::Add => arithmetic(ty, left.wrapping_add(right)),
+        BinaryOperator::Sub => arithmetic(ty, left.wrapping_sub(right)),
+        BinaryOperator::Mul => arithmetic(ty, left.wrapping_mul(right)),
+        BinaryOperator::Div => arithmetic(ty, left.checked_div(right)?),
+        BinaryOperator::Rem => arithmetic(ty, left.checked_rem(right)?),
+        BinaryOperator::BitwiseAnd => arithmetic(ty, left & right),
+        BinaryOperator::BitwiseOr => arithmetic(ty, left | right),
+        BinaryOperator::BitwiseXor => arithmetic(ty, left ^ right),
+        BinaryOperator::Equal => Some(Constant::int((left == right) as i32)),
+        BinaryOperator::NotEqual => Some(Constant::int((left != right) as i32)),
+        BinaryOperator::LessThan => Some(Constant::int((left < right) as i32)),
+        BinaryOperator::LessOrEqual => Some(Constant::int((left <= right) as i32)),
+        BinaryOperator::GreaterThan => Some(Constant::int((left > right) as i32)),
+        BinaryOperator::GreaterOrEqual => Some(Constant::int((left >= right) as i32)),
+        BinaryOperator::And | BinaryOperator::Or | BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => unreachable!(),
+    }
+}
+
+/**
+The operands of a shift are promoted separately, and the result has the type of the left one. Shifting by the width
+of the type or more, or shifting a negative value left, is undefined.
+*/
+fn shift(operator: BinaryOperator, left: Constant, right: Constant) -> Option<Constant> {
+    let left = left.convert(left.ty().promote())?;
+    let ty = left.ty();
+    let (value, count) = (left.integer_value()?, right.integer_value()?);
+
+    if !(0..ty.size() as i128 * 8).contains(&count) {
+        return None;
+    }
+
+    match operator {
+        BinaryOperator::ShiftLeft if ty.is_signed() && value < 0 => None,
+        BinaryOperator::ShiftLeft if ty.is_signed() => arithmetic(ty, value << count),
+        BinaryOperator::ShiftLeft => Some(Constant::integer((value << count) as u64, ty)),
+        _ => arithmetic(ty, value >> count),
+    }
+}
+
+fn double_binary(operator: BinaryOperator, left: f64, right: f64) -> Option<Constant> {
+    match operator {
+        BinaryOperator::Add => Some(Constant::Double(left + right)),
+        BinaryOperator::Sub => Some(Constant::Double(left - right)),
+        BinaryOperator::Mul => Some(Constant::Double(left * right)),
+        BinaryOperator::Div => Some(Constant::Double(left / right)),
+        BinaryOperator::Equal => Some(Constant::int((left == right) as i32)),
+        BinaryOperator::NotEqual => Some(Constant::int((left != right) as i32)),
+        BinaryOperator::LessThan => Some(Constant::int((left < right) as i32)),
+        BinaryOperator::LessOrEqual => Some(Constant::int((left <= right) as i32)),
+        BinaryOperator::GreaterThan => Some(Constant::int((left > right) as i32)),
+        BinaryOperator::GreaterOrEqual => Some(Constant::int((left >= right) as i32)),
+        BinaryOperator::Rem
+        | BinaryOperator::BitwiseAnd
+        | BinaryOperator::BitwiseOr
+        | BinaryOperator::BitwiseXor
+        | BinaryOperator::ShiftLeft
+        | BinaryOperator::ShiftRight
+        | BinaryOperator::And
+        | BinaryOperator::Or => None,
+    }
+}
+
+/**
+Constant of integer type `ty` holding the result of an operation. Unsigned results wrap around, while signed ones which
+overflow are undefined.
+*/
+fn arithmetic(ty: Type, value: i128) -> Option<Constant> {
+    let (min, max) = ty.range();
+    if ty.is_signed() && !(min..=max).contains(&value) {
+        return None;
+    }
+
+    Some(Constant::integer(value as u64, ty))
+}
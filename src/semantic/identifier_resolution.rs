@@ -0,0 +1,396 @@
+use crate::ast::{
+    Block, BlockItem, Declaration, Expression, Factor, ForInit, Function, IncrementOperator, Initializer, Parameter, Program, Statement,
+    StorageClass, VariableDeclaration,
+};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::Span;
+use crate::semantic::{SemanticPass, SemanticResult};
+use std::collections::HashMap;
+
+/**
+What a name refers to in a scope.
+*/
+struct Binding {
+    unique_name: String,
+    span: Span,
+    // Names with linkage keep their name, and can be declared again in the same scope as long as they stay the same
+    // kind of symbol
+    has_linkage: bool,
+    is_function: bool,
+}
+
+/**
+This pass gives every variable without linkage a unique name, so that later stages don't have to care about scopes, and
+checks that variables are declared once in a scope before being used, and that only functions are called.
+*/
+pub struct IdentifierResolution {
+    // Names declared at file scope and in each enclosing block, innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    // Declarations whose block has ended, to explain why a variable can't be used anymore
+    ended: HashMap<String, Span>,
+    counter: usize,
+}
+
+impl IdentifierResolution {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            ended: HashMap::new(),
+            counter: 0,
+        }
+    }
+
+    fn handle_function(&mut self, function: Function) -> SemanticResult<Function> {
+        let (name, return_type, params, body, storage_class, span) = function.decompose();
+        self.declare(&name, name.clone(), &span, true, true)?;
+
+        // Parameters are in the same scope as the outermost block of the body
+        self.scopes.push(HashMap::new());
+        let result = self.handle_function_scope(params, body);
+        self.end_scope();
+        let (params, body) = result?;
+
+        Ok(Function::new(name, return_type, params, body, storage_class, span))
+    }
+
+    fn handle_function_scope(&mut self, params: Vec<Parameter>, body: Option<Block>) -> SemanticResult<(Vec<Parameter>, Option<Block>)> {
+        let params = params.into_iter().map(|param| {
+            let name = match param.name {
+                Some(name) => {
+                    let unique_name = self.make_unique_name(&name);
+                    self.declare(&name, unique_name.clone(), &param.span, false, false)?;
+                    Some(unique_name)
+                }
+                None => None,
+            };
+
+            Ok(Parameter { name, ty: param.ty, span: param.span })
+        }).collect::<SemanticResult<_>>()?;
+
+        let body = match body {
+            Some(body) => Some(Block::new(self.handle_block_items(body)?)),
+            None => None,
+        };
+
+        Ok((params, body))
+    }
+
+    fn handle_block(&mut self, block: Block) -> SemanticResult<Block> {
+        self.scopes.push(HashMap::new());
+        let items = self.handle_block_items(block);
+        self.end_scope();
+
+        Ok(Block::new(items?))
+    }
+
+    fn handle_block_items(&mut self, block: Block) -> SemanticResult<Vec<BlockItem>> {
+        block.into_items().into_iter().map(|item| self.handle_block_item(item)).collect()
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for (name, binding) in scope {
+            self.ended.insert(name, binding.span);
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn undeclared(&self, name: &str, span: &Span) -> Diagnostic {
+        let diagnostic = Diagnostic::error(Code::UndeclaredIdentifier, format!("Use of undeclared identifier `{}`", name))
+            .with_primary_label(span, "");
+
+        match self.ended.get(name) {
+            Some(declaration) => {
+                diagnostic.with_secondary_label(declaration, format!("`{}` is declared here, in a block which has ended", name))
+            }
+            None => diagnostic,
+        }
+    }
+
+    /**
+    Add `name` to the innermost scope. Only names with linkage can be declared more than once in a scope.
+    */
+    fn declare(&mut self, name: &str, unique_name: String, span: &Span, has_linkage: bool, is_function: bool) -> SemanticResult<()> {
+        let scope = self.scopes.last_mut().expect("There is always a file scope");
+        if let Some(previous) = scope.get(name) {
+            if !(has_linkage && previous.has_linkage) {
+                return Err(Diagnostic::error(Code::DuplicateDeclaration, format!("Redeclaration of `{}`", name))
+                    .with_primary_label(span, "")
+                    .with_secondary_label(&previous.span, "previous declaration is here"));
+            }
+
+            if is_function != previous.is_function {
+                return Err(Diagnostic::error(Code::ConflictingTypes, format!("`{}` redeclared as a different kind of symbol", name))
+                    .with_primary_label(span, "")
+                    .with_secondary_label(&previous.span, "previous declaration is here"));
+            }
+        }
+
+        scope.insert(name.to_owned(), Binding { unique_name, span: span.clone(), has_linkage, is_function });
+        Ok(())
+    }
+
+    fn make_unique_name(&mut self, name: &str) -> String {
+        // Dots can't appear in C identifiers, so unique names never clash with the user's ones
+        let unique_name = format!("{}.var.{}", name, self.counter);
+        self.counter += 1;
+        unique_name
+    }
+
+    fn handle_block_item(&mut self, item: BlockItem) -> SemanticResult<BlockItem> {
+        match item {
+            BlockItem::Statement(statement) => Ok(BlockItem::Statement(self.handle_statement(statement)?)),
+            BlockItem::Declaration(Declaration::Variable(declaration)) => {
+                Ok(BlockItem::Declaration(Declaration::Variable(self.handle_declaration(declaration)?)))
+            }
+            BlockItem::Declaration(Declaration::Function(function)) => {
+                Ok(BlockItem::Declaration(Declaration::Function(self.handle_function(function)?)))
+            }
+        }
+    }
+
+    fn handle_declaration(&mut self, declaration: VariableDeclaration) -> SemanticResult<VariableDeclaration> {
+        // Variables at file scope and `extern` ones have linkage, so they keep their name to refer to the same object
+        // as every other declaration of it
+        let has_linkage = self.scopes.len() == 1 || declaration.storage_class == Some(StorageClass::Extern);
+        let name = if has_linkage { declaration.name.clone() } else { self.make_unique_name(&declaration.name) };
+        self.declare(&declaration.name, name.clone(), &declaration.span, has_linkage, false)?;
+
+        // The variable is in scope in its own initializer
+        let init = declaration.init.map(|init| self.handle_initializer(init)).transpose()?;
+
+        Ok(VariableDeclaration {
+            name,
+            ty: declaration.ty,
+            init,
+            storage_class: declaration.storage_class,
+            span: declaration.span,
+        })
+    }
+
+    fn handle_initializer(&mut self, initializer: Initializer) -> SemanticResult<Initializer> {
+        match initializer {
+            Initializer::Single(expression) => Ok(Initializer::Single(self.handle_expression(expression)?)),
+            Initializer::Compound(initializers, span) => {
+                let initializers = initializers.into_iter().map(|initializer| self.handle_initializer(initializer)).collect::<SemanticResult<_>>()?;
+                Ok(Initializer::Compound(initializers, span))
+            }
+        }
+    }
+
+    fn handle_statement(&mut self, statement: Statement) -> SemanticResult<Statement> {
+        match statement {
+            Statement::Return { expr, span } => Ok(Statement::Return { expr: self.handle_expression(expr)?, span }),
+            Statement::Expression(expression) => Ok(Statement::Expression(self.handle_expression(expression)?)),
+            Statement::Compound(block) => Ok(Statement::Compound(self.handle_block(block)?)),
+            Statement::If { condition, then, otherwise } => {
+                Ok(Statement::If {
+                    condition: self.handle_expression(condition)?,
+                    then: Box::new(self.handle_statement(*then)?),
+                    otherwise: otherwise.map(|otherwise| self.handle_statement(*otherwise).map(Box::new)).transpose()?,
+                })
+            }
+            Statement::While { condition, body, label } => {
+                Ok(Statement::While {
+                    condition: self.handle_expression(condition)?,
+                    body: Box::new(self.handle_statement(*body)?),
+                    label,
+                })
+            }
+            Statement::DoWhile { body, condition, label } => {
+                Ok(Statement::DoWhile {
+                    body: Box::new(self.handle_statement(*body)?),
+                    condition: self.handle_expression(condition)?,
+                    label,
+                })
+            }
+            Statement::For { init, condition, post, body, label } => {
+                // A declaration in the first clause is scoped to the loop
+                self.scopes.push(HashMap::new());
+                let statement = self.handle_for(*init, condition, post, *body, label);
+                self.end_scope();
+
+                statement
+            }
+            Statement::Switch { condition, body, label, cases, span } => {
+                Ok(Statement::Switch {
+                    condition: self.handle_expression(condition)?,
+                    body: Box::new(self.handle_statement(*body)?),
+                    label,
+                    cases,
+                    span,
+                })
+            }
+            Statement::Case { value, body, label, span } => {
+                Ok(Statement::Case {
+                    value: self.handle_expression(value)?,
+                    body: Box::new(self.handle_statement(*body)?),
+                    label,
+                    span,
+                })
+            }
+            Statement::Default { body, label, span } => {
+                Ok(Statement::Default { body: Box::new(self.handle_statement(*body)?), label, span })
+            }
+            Statement::Labeled { label, body, span } => {
+                Ok(Statement::Labeled { label, body: Box::new(self.handle_statement(*body)?), span })
+            }
+            statement @ (Statement::Goto { .. } | Statement::Break { .. } | Statement::Continue { .. } | Statement::Null) => Ok(statement),
+        }
+    }
+
+    fn handle_for(&mut self, init: ForInit, condition: Option<Expression>, post: Option<Expression>, body: Statement, label: String) -> SemanticResult<Statement> {
+        let init = match init {
+            ForInit::Declaration(declarations) => ForInit::Declaration(
+                declarations.into_iter().map(|declaration| self.handle_declaration(declaration)).collect::<SemanticResult<_>>()?,
+            ),
+            ForInit::Expression(expression) => ForInit::Expression(self.handle_optional_expression(expression)?),
+        };
+
+        Ok(Statement::For {
+            init: Box::new(init),
+            condition: self.handle_optional_expression(condition)?,
+            post: self.handle_optional_expression(post)?,
+            body: Box::new(self.handle_statement(body)?),
+            label,
+        })
+    }
+
+    fn handle_optional_expression(&mut self, expression: Option<Expression>) -> SemanticResult<Option<Expression>> {
+        expression.map(|expression| self.handle_expression(expression)).transpose()
+    }
+
+    fn handle_expression(&mut self, expression: Expression) -> SemanticResult<Expression> {
+        match expression {
+            Expression::Factor(factor) => Ok(Expression::Factor(self.handle_factor(factor)?)),
+            Expression::Binary { left, operator, right, span } => {
+                Ok(Expression::Binary {
+                    left: Box::new(self.handle_expression(*left)?),
+                    operator,
+                    right: Box::new(self.handle_expression(*right)?),
+                    span,
+                })
+            }
+            Expression::Assignment { left, right, span } => {
+                if !is_lvalue(&left) {
+                    return Err(Diagnostic::error(Code::InvalidLvalue, "Invalid left operand of assignment")
+                        .with_primary_label(&span, "the left operand is not an lvalue"));
+                }
+
+                Ok(Expression::Assignment {
+                    left: Box::new(self.handle_expression(*left)?),
+                    right: Box::new(self.handle_expression(*right)?),
+                    span,
+                })
+            }
+            Expression::Conditional { condition, then, otherwise, span } => {
+                Ok(Expression::Conditional {
+                    condition: Box::new(self.handle_expression(*condition)?),
+                    then: Box::new(self.handle_expression(*then)?),
+                    otherwise: Box::new(self.handle_expression(*otherwise)?),
+                    span,
+                })
+            }
+            Expression::CompoundAssignment { operator, left, right, span } => {
+                if !is_lvalue(&left) {
+                    return Err(Diagnostic::error(Code::InvalidLvalue, "Invalid left operand of compound assignment")
+                        .with_primary_label(&span, "the left operand is not an lvalue"));
+                }
+
+                Ok(Expression::CompoundAssignment {
+                    operator,
+                    left: Box::new(self.handle_expression(*left)?),
+                    right: Box::new(self.handle_expression(*right)?),
+                    span,
+                })
+            }
+        }
+    }
+
+    fn handle_factor(&mut self, factor: Factor) -> SemanticResult<Factor> {
+        match factor {
+            Factor::Var(name, span) => {
+                let binding = self.resolve(&name).ok_or_else(|| self.undeclared(&name, &span))?;
+                if binding.is_function {
+                    return Err(Diagnostic::error(Code::FunctionAsValue, format!("Function `{}` is used as a value", name))
+                        .with_primary_label(&span, "functions can only be called"));
+                }
+
+                Ok(Factor::Var(binding.unique_name.clone(), span))
+            }
+            Factor::Call(name, args, span) => {
+                let binding = self.resolve(&name).ok_or_else(|| self.undeclared(&name, &span))?;
+                if !binding.is_function {
+                    return Err(Diagnostic::error(Code::NotAFunction, format!("Called object `{}` is not a function", name))
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(&binding.span, "declared here"));
+                }
+
+                let args = args.into_iter().map(|arg| self.handle_expression(arg)).collect::<SemanticResult<_>>()?;
+                Ok(Factor::Call(name, args, span))
+            }
+            Factor::Increment(operator, operand, span) => {
+                if !is_lvalue_factor(&operand) {
+                    let spelling = match operator {
+                        IncrementOperator::PrefixIncrement | IncrementOperator::PostfixIncrement => "++",
+                        IncrementOperator::PrefixDecrement | IncrementOperator::PostfixDecrement => "--",
+                    };
+                    return Err(Diagnostic::error(Code::InvalidLvalue, format!("Invalid operand of `{}`", spelling))
+                        .with_primary_label(&span, "the operand is not an lvalue"));
+                }
+
+                Ok(Factor::Increment(operator, Box::new(self.handle_factor(*operand)?), span))
+            }
+            Factor::AddressOf(operand, span) => {
+                if !is_lvalue_factor(&operand) {
+                    return Err(Diagnostic::error(Code::InvalidLvalue, "Invalid operand of unary `&`")
+                        .with_primary_label(&span, "the operand is not an lvalue"));
+                }
+
+                Ok(Factor::AddressOf(Box::new(self.handle_factor(*operand)?), span))
+            }
+            Factor::Dereference(operand, span) => Ok(Factor::Dereference(Box::new(self.handle_factor(*operand)?), span)),
+            Factor::Subscript(array, index, span) => {
+                Ok(Factor::Subscript(Box::new(self.handle_factor(*array)?), Box::new(self.handle_expression(*index)?), span))
+            }
+            Factor::Unary(operator, factor, span) => Ok(Factor::Unary(operator, Box::new(self.handle_factor(*factor)?), span)),
+            Factor::Cast(ty, factor, span) => Ok(Factor::Cast(ty, Box::new(self.handle_factor(*factor)?), span)),
+            Factor::Expression(expression) => Ok(Factor::Expression(Box::new(self.handle_expression(*expression)?))),
+            factor @ (Factor::Constant(_) | Factor::StringLiteral(_)) => Ok(factor),
+        }
+    }
+}
+
+/**
+Whether an expression designates an object, which is a variable, a dereferenced pointer or an element of an array,
+possibly in parentheses. Type checking then rejects the assignments to arrays.
+*/
+fn is_lvalue(expression: &Expression) -> bool {
+    match expression {
+        Expression::Factor(factor) => is_lvalue_factor(factor),
+        _ => false,
+    }
+}
+
+fn is_lvalue_factor(factor: &Factor) -> bool {
+    match factor {
+        Factor::Var(_, _) | Factor::Dereference(_, _) | Factor::Subscript(_, _, _) => true,
+        Factor::Expression(expression) => is_lvalue(expression),
+        _ => false,
+    }
+}
+
+impl SemanticPass for IdentifierResolution {
+    fn run(&mut self, program: Program) -> SemanticResult<Program> {
+        self.scopes.push(HashMap::new());
+        let declarations = program.into_declarations().into_iter().map(|declaration| match declaration {
+            Declaration::Variable(declaration) => Ok(Declaration::Variable(self.handle_declaration(declaration)?)),
+            Declaration::Function(function) => Ok(Declaration::Function(self.handle_function(function)?)),
+        }).collect::<SemanticResult<_>>()?;
+
+        Ok(Program::new(declarations))
+    }
+}
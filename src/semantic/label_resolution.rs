@@ -0,0 +1,119 @@
+use crate::ast::{Block, BlockItem, Declaration, Function, Program, Statement};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::Span;
+use crate::semantic::{SemanticPass, SemanticResult};
+use std::collections::{HashMap, HashSet};
+
+/**
+This pass checks that the labels of a function are unique, and that every `goto` jumps to one of them. Labels which
+are never jumped to are reported as warnings.
+*/
+pub struct LabelResolution {
+    // Where each label of the function is defined, and their names in the order they are defined
+    labels: HashMap<String, Span>,
+    order: Vec<String>,
+    gotos: Vec<(String, Span)>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl LabelResolution {
+    pub fn new() -> Self {
+        Self {
+            labels: HashMap::new(),
+            order: vec![],
+            gotos: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn handle_function(&mut self, function: &Function) -> SemanticResult<()> {
+        let Some(body) = function.body() else { return Ok(()) };
+
+        self.labels.clear();
+        self.order.clear();
+        self.gotos.clear();
+
+        // Labels have function scope, so a `goto` can jump forward to a label which isn't defined yet
+        self.handle_block(body)?;
+
+        for (label, span) in &self.gotos {
+            if !self.labels.contains_key(label) {
+                return Err(Diagnostic::error(Code::UndefinedLabel, format!("Label `{}` used but not defined", label))
+                    .with_primary_label(span, ""));
+            }
+        }
+
+        let used: HashSet<_> = self.gotos.iter().map(|(label, _)| label).collect();
+        for label in self.order.iter().filter(|label| !used.contains(label)) {
+            self.warnings.push(Diagnostic::warning(Code::UnusedLabel, format!("Label `{}` defined but not used", label))
+                .with_primary_label(&self.labels[label], ""));
+        }
+
+        Ok(())
+    }
+
+    fn handle_block(&mut self, block: &Block) -> SemanticResult<()> {
+        for item in block.items() {
+            if let BlockItem::Statement(statement) = item {
+                self.handle_statement(statement)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_statement(&mut self, statement: &Statement) -> SemanticResult<()> {
+        match statement {
+            Statement::Labeled { label, body, span } => {
+                if let Some(previous) = self.labels.get(label) {
+                    return Err(Diagnostic::error(Code::DuplicateLabel, format!("Duplicate label `{}`", label))
+                        .with_primary_label(span, "")
+                        .with_secondary_label(previous, "previous definition is here"));
+                }
+
+                self.labels.insert(label.clone(), span.clone());
+                self.order.push(label.clone());
+                self.handle_statement(body)
+            }
+            Statement::Goto { label, span } => {
+                self.gotos.push((label.clone(), span.clone()));
+                Ok(())
+            }
+            Statement::If { then, otherwise, .. } => {
+                self.handle_statement(then)?;
+                match otherwise {
+                    Some(otherwise) => self.handle_statement(otherwise),
+                    None => Ok(()),
+                }
+            }
+            Statement::Compound(block) => self.handle_block(block),
+            Statement::While { body, .. }
+            | Statement::DoWhile { body, .. }
+            | Statement::For { body, .. }
+            | Statement::Switch { body, .. }
+            | Statement::Case { body, .. }
+            | Statement::Default { body, .. } => self.handle_statement(body),
+            Statement::Return { .. }
+            | Statement::Expression(_)
+            | Statement::Break { .. }
+            | Statement::Continue { .. }
+            | Statement::Null => Ok(()),
+        }
+    }
+}
+
+impl SemanticPass for LabelResolution {
+    fn run(&mut self, program: Program) -> SemanticResult<Program> {
+        for declaration in program.declarations() {
+            if let Declaration::Function(function) = declaration {
+                self.handle_function(function)?;
+            }
+        }
+
+        Ok(program)
+    }
+
+    fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+}
@@ -0,0 +1,204 @@
+use crate::ast::{Block, BlockItem, Declaration, Function, Program, Statement, SwitchCases};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::Span;
+use crate::semantic::{evaluate_integer_constant, SemanticPass, SemanticResult};
+use std::collections::HashMap;
+
+/**
+Statement which a `break` can jump out of.
+*/
+enum Breakable {
+    Loop(String),
+    Switch(String),
+}
+
+/**
+`switch` whose body is being labelled, along with the `case` and `default` statements found so far.
+*/
+struct EnclosingSwitch {
+    label: String,
+    cases: SwitchCases,
+    case_spans: HashMap<i64, Span>,
+    default_span: Option<Span>,
+}
+
+/**
+This pass gives every loop and `switch` a unique label, attaches each `break` and `continue` to the statement it jumps
+out of, and collects the `case` and `default` statements of each `switch`.
+*/
+pub struct LoopLabelling {
+    // Enclosing loops and switches, innermost last
+    breakables: Vec<Breakable>,
+    switches: Vec<EnclosingSwitch>,
+    counter: usize,
+}
+
+impl LoopLabelling {
+    pub fn new() -> Self {
+        Self {
+            breakables: vec![],
+            switches: vec![],
+            counter: 0,
+        }
+    }
+
+    fn handle_function(&mut self, function: Function) -> SemanticResult<Function> {
+        let (name, return_type, params, body, storage_class, span) = function.decompose();
+        let body = body.map(|body| self.handle_block(body)).transpose()?;
+
+        Ok(Function::new(name, return_type, params, body, storage_class, span))
+    }
+
+    fn handle_block(&mut self, block: Block) -> SemanticResult<Block> {
+        let items = block.into_items().into_iter().map(|item| {
+            match item {
+                BlockItem::Statement(statement) => Ok(BlockItem::Statement(self.handle_statement(statement)?)),
+                declaration @ BlockItem::Declaration(_) => Ok(declaration),
+            }
+        }).collect::<SemanticResult<_>>()?;
+
+        Ok(Block::new(items))
+    }
+
+    fn handle_statement(&mut self, statement: Statement) -> SemanticResult<Statement> {
+        match statement {
+            Statement::If { condition, then, otherwise } => {
+                Ok(Statement::If {
+                    condition,
+                    then: Box::new(self.handle_statement(*then)?),
+                    otherwise: otherwise.map(|otherwise| self.handle_statement(*otherwise).map(Box::new)).transpose()?,
+                })
+            }
+            Statement::Compound(block) => Ok(Statement::Compound(self.handle_block(block)?)),
+            Statement::While { condition, body, .. } => {
+                let (body, label) = self.handle_loop(*body)?;
+                Ok(Statement::While { condition, body, label })
+            }
+            Statement::DoWhile { body, condition, .. } => {
+                let (body, label) = self.handle_loop(*body)?;
+                Ok(Statement::DoWhile { body, condition, label })
+            }
+            Statement::For { init, condition, post, body, .. } => {
+                let (body, label) = self.handle_loop(*body)?;
+                Ok(Statement::For { init, condition, post, body, label })
+            }
+            Statement::Switch { condition, body, span, .. } => {
+                let label = self.make_label("switch");
+                self.switches.push(EnclosingSwitch {
+                    label: label.clone(),
+                    cases: SwitchCases::default(),
+                    case_spans: HashMap::new(),
+                    default_span: None,
+                });
+
+                self.breakables.push(Breakable::Switch(label.clone()));
+                let body = self.handle_statement(*body);
+                self.breakables.pop();
+                let switch = self.switches.pop().unwrap();
+
+                Ok(Statement::Switch { condition, body: Box::new(body?), label, cases: switch.cases, span })
+            }
+            Statement::Case { value, body, span, .. } => {
+                let switch = self.switches.last_mut().ok_or_else(|| {
+                    Diagnostic::error(Code::CaseOutsideSwitch, "`case` label not within a switch statement")
+                        .with_primary_label(&span, "")
+                })?;
+
+                // Type checking converts the values to the type of the controlling expression, once it is known
+                let number = evaluate_integer_constant(&value).and_then(|value| value.integer_value()).ok_or_else(|| {
+                    Diagnostic::error(Code::InvalidCaseLabel, "`case` label does not reduce to an integer constant")
+                        .with_primary_label(&span, "")
+                })? as i64;
+
+                if let Some(previous) = switch.case_spans.get(&number) {
+                    return Err(Diagnostic::error(Code::DuplicateCase, format!("Duplicate case value `{}`", number))
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(previous, "previously used here"));
+                }
+
+                let label = format!("{}.case.{}", switch.label, switch.cases.cases.len());
+                switch.cases.cases.push((number, label.clone()));
+                switch.case_spans.insert(number, span.clone());
+
+                let body = Box::new(self.handle_statement(*body)?);
+                Ok(Statement::Case { value, body, label, span })
+            }
+            Statement::Default { body, span, .. } => {
+                let switch = self.switches.last_mut().ok_or_else(|| {
+                    Diagnostic::error(Code::CaseOutsideSwitch, "`default` label not within a switch statement")
+                        .with_primary_label(&span, "")
+                })?;
+
+                if let Some(previous) = &switch.default_span {
+                    return Err(Diagnostic::error(Code::DuplicateCase, "Multiple default labels in one switch")
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(previous, "previous default is here"));
+                }
+
+                let label = format!("{}.default", switch.label);
+                switch.cases.default = Some(label.clone());
+                switch.default_span = Some(span.clone());
+
+                let body = Box::new(self.handle_statement(*body)?);
+                Ok(Statement::Default { body, label, span })
+            }
+            Statement::Break { span, .. } => {
+                let label = match self.breakables.last() {
+                    Some(Breakable::Loop(label) | Breakable::Switch(label)) => label.clone(),
+                    None => {
+                        return Err(Diagnostic::error(Code::BreakOutsideLoop, "`break` statement not within a loop or switch")
+                            .with_primary_label(&span, ""));
+                    }
+                };
+
+                Ok(Statement::Break { label, span })
+            }
+            Statement::Continue { span, .. } => {
+                // `continue` goes through the switches to the innermost loop
+                let label = self.breakables.iter().rev().find_map(|breakable| match breakable {
+                    Breakable::Loop(label) => Some(label.clone()),
+                    Breakable::Switch(_) => None,
+                }).ok_or_else(|| {
+                    Diagnostic::error(Code::ContinueOutsideLoop, "`continue` statement not within a loop")
+                        .with_primary_label(&span, "")
+                })?;
+
+                Ok(Statement::Continue { label, span })
+            }
+            Statement::Labeled { label, body, span } => {
+                Ok(Statement::Labeled { label, body: Box::new(self.handle_statement(*body)?), span })
+            }
+            statement @ (Statement::Goto { .. } | Statement::Return { .. } | Statement::Expression(_) | Statement::Null) => Ok(statement),
+        }
+    }
+
+    /**
+    Make a label for a loop, and handle its body with the loop as the innermost one.
+    */
+    fn handle_loop(&mut self, body: Statement) -> SemanticResult<(Box<Statement>, String)> {
+        let label = self.make_label("loop");
+
+        self.breakables.push(Breakable::Loop(label.clone()));
+        let body = self.handle_statement(body);
+        self.breakables.pop();
+
+        Ok((Box::new(body?), label))
+    }
+
+    fn make_label(&mut self, name: &str) -> String {
+        let label = format!("{}.{}", name, self.counter);
+        self.counter += 1;
+        label
+    }
+}
+
+impl SemanticPass for LoopLabelling {
+    fn run(&mut self, program: Program) -> SemanticResult<Program> {
+        let declarations = program.into_declarations().into_iter().map(|declaration| match declaration {
+            Declaration::Function(function) => Ok(Declaration::Function(self.handle_function(function)?)),
+            variable @ Declaration::Variable(_) => Ok(variable),
+        }).collect::<SemanticResult<_>>()?;
+
+        Ok(Program::new(declarations))
+    }
+}
@@ -0,0 +1,27 @@
+use crate::ast;
+use crate::diagnostic::Diagnostic;
+
+mod constant_expression;
+mod identifier_resolution;
+mod label_resolution;
+mod loop_labelling;
+mod type_checking;
+
+pub(crate) use self::constant_expression::*;
+pub use self::identifier_resolution::*;
+pub use self::label_resolution::*;
+pub use self::loop_labelling::*;
+pub use self::type_checking::*;
+
+pub type SemanticResult<T> = Result<T, Diagnostic>;
+
+pub trait SemanticPass {
+    fn run(&mut self, program: ast::Program) -> SemanticResult<ast::Program>;
+
+    /**
+    Warnings reported since the last call.
+    */
+    fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        vec![]
+    }
+}
@@ -0,0 +1,712 @@
+use crate::ast::{
+    BinaryOperator, Block, BlockItem, Declaration, Expression, Factor, ForInit, Function, IncrementOperator, Initializer, Program, Statement,
+    StorageClass, SwitchCases, Type, UnaryOperator, VariableDeclaration,
+};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::lexer::Span;
+use crate::semantic::{evaluate_integer_constant, SemanticPass, SemanticResult};
+use std::collections::HashMap;
+
+/**
+Type and linkage of a function, along with its first declaration.
+*/
+struct FunctionType {
+    params: Vec<Type>,
+    return_type: Type,
+    span: Span,
+    global: bool,
+    defined: bool,
+}
+
+/**
+Type of a variable, along with its first declaration. The linkage only matters for variables declared more than once,
+which are the ones with linkage.
+*/
+struct VariableType {
+    ty: Type,
+    span: Span,
+    global: bool,
+    // Whether a declaration has an initializer, which defines the variable
+    initialized: bool,
+}
+
+/**
+`switch` whose body is being checked, along with the values of its cases converted to the promoted type of the
+controlling expression.
+*/
+struct EnclosingSwitch {
+    ty: Type,
+    values: HashMap<String, i64>,
+    case_spans: HashMap<i64, Span>,
+}
+
+/**
+This pass gives a type to every expression, and turns every implicit conversion into a cast: the integer promotions,
+the usual arithmetic conversions, and the conversions as if by assignment of initializers, arguments and returned
+values. Later stages then find the operands of each operation already converted to the type it is done in. Arrays
+decay to a pointer to their first element wherever their value is used, which is everywhere but as the operand of `&`.
+It also checks that all the declarations of a name agree on its type and linkage, that a name is defined at most once,
+that operators are given operands they accept, and that pointers are only converted implicitly from a pointer of the
+same type or from a null pointer constant.
+*/
+pub struct TypeChecking {
+    // Variables by unique name, and functions by name
+    variables: HashMap<String, VariableType>,
+    functions: HashMap<String, FunctionType>,
+    switches: Vec<EnclosingSwitch>,
+    // Return type of the function being checked
+    return_type: Type,
+}
+
+impl TypeChecking {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            switches: vec![],
+            return_type: Type::Int,
+        }
+    }
+
+    fn handle_function(&mut self, function: Function) -> SemanticResult<Function> {
+        let (name, return_type, params, body, storage_class, span) = function.decompose();
+        let param_types: Vec<_> = params.iter().map(|param| param.ty.clone()).collect();
+
+        if let Some(previous) = self.variables.get(&name) {
+            return Err(different_kind_of_symbol(&name, &span, &previous.span));
+        }
+
+        match self.functions.get_mut(&name) {
+            Some(previous) => {
+                if previous.params != param_types || previous.return_type != return_type {
+                    return Err(Diagnostic::error(Code::ConflictingTypes, format!("Conflicting types for `{}`", name))
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(&previous.span, "previous declaration is here"));
+                }
+
+                // Without `static`, a declaration keeps the linkage of the previous one
+                if previous.global && storage_class == Some(StorageClass::Static) {
+                    return Err(Diagnostic::error(Code::ConflictingLinkage, format!("Static declaration of `{}` follows non-static declaration", name))
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(&previous.span, "previous declaration is here"));
+                }
+
+                if previous.defined && body.is_some() {
+                    return Err(Diagnostic::error(Code::DuplicateDeclaration, format!("Redefinition of `{}`", name))
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(&previous.span, "previous definition is here"));
+                }
+
+                previous.defined |= body.is_some();
+            }
+            None => {
+                self.functions.insert(name.clone(), FunctionType {
+                    params: param_types,
+                    return_type: return_type.clone(),
+                    span: span.clone(),
+                    global: storage_class != Some(StorageClass::Static),
+                    defined: body.is_some(),
+                });
+            }
+        }
+
+        let body = match body {
+            Some(body) => {
+                for param in &params {
+                    if let Some(name) = &param.name {
+                        self.variables.insert(name.clone(), VariableType { ty: param.ty.clone(), span: param.span.clone(), global: false, initialized: false });
+                    }
+                }
+
+                self.return_type = return_type.clone();
+                Some(self.handle_block(body)?)
+            }
+            None => None,
+        };
+
+        Ok(Function::new(name, return_type, params, body, storage_class, span))
+    }
+
+    fn handle_block(&mut self, block: Block) -> SemanticResult<Block> {
+        let items = block.into_items().into_iter().map(|item| {
+            match item {
+                BlockItem::Statement(statement) => Ok(BlockItem::Statement(self.handle_statement(statement)?)),
+                BlockItem::Declaration(Declaration::Variable(declaration)) => {
+                    Ok(BlockItem::Declaration(Declaration::Variable(self.handle_declaration(declaration, false)?)))
+                }
+                BlockItem::Declaration(Declaration::Function(function)) => {
+                    Ok(BlockItem::Declaration(Declaration::Function(self.handle_function(function)?)))
+                }
+            }
+        }).collect::<SemanticResult<_>>()?;
+
+        Ok(Block::new(items))
+    }
+
+    /**
+    Check a variable declaration. A name with linkage declared again takes the composite type of its declarations, so
+    that the size of an array declared without one is known from then on, and the linkage of the previous declaration
+    when it is `extern`.
+    */
+    fn handle_declaration(&mut self, declaration: VariableDeclaration, file_scope: bool) -> SemanticResult<VariableDeclaration> {
+        let global = match declaration.storage_class {
+            Some(StorageClass::Static) => false,
+            Some(StorageClass::Extern) => true,
+            None => file_scope,
+        };
+
+        // Only names with linkage are declared more than once, as the others have a unique name by now
+        if let Some(previous) = self.functions.get(&declaration.name) {
+            return Err(different_kind_of_symbol(&declaration.name, &declaration.span, &previous.span));
+        }
+
+        let ty = match self.variables.get_mut(&declaration.name) {
+            Some(previous) => {
+                let Some(ty) = previous.ty.composite(&declaration.ty) else {
+                    return Err(Diagnostic::error(Code::ConflictingTypes, format!("Conflicting types for `{}`", declaration.name))
+                        .with_primary_label(&declaration.span, "")
+                        .with_secondary_label(&previous.span, "previous declaration is here"));
+                };
+
+                if declaration.storage_class != Some(StorageClass::Extern) && global != previous.global {
+                    let message = if global {
+                        format!("Non-static declaration of `{}` follows static declaration", declaration.name)
+                    } else {
+                        format!("Static declaration of `{}` follows non-static declaration", declaration.name)
+                    };
+                    return Err(Diagnostic::error(Code::ConflictingLinkage, message)
+                        .with_primary_label(&declaration.span, "")
+                        .with_secondary_label(&previous.span, "previous declaration is here"));
+                }
+
+                if previous.initialized && declaration.init.is_some() {
+                    return Err(Diagnostic::error(Code::DuplicateDeclaration, format!("Redefinition of `{}`", declaration.name))
+                        .with_primary_label(&declaration.span, "")
+                        .with_secondary_label(&previous.span, "previous declaration is here"));
+                }
+
+                previous.ty = ty.clone();
+                previous.initialized |= declaration.init.is_some();
+                ty
+            }
+            None => {
+                self.variables.insert(declaration.name.clone(), VariableType {
+                    ty: declaration.ty.clone(),
+                    span: declaration.span.clone(),
+                    global,
+                    initialized: declaration.init.is_some(),
+                });
+                declaration.ty
+            }
+        };
+
+        let init = declaration.init.map(|init| self.handle_initializer(init, &ty, &declaration.span)).transpose()?;
+
+        Ok(VariableDeclaration { ty, init, ..declaration })
+    }
+
+    /**
+    Check the initializer of an object of type `ty`. An array is initialized by a brace-enclosed list of at most as many
+    initializers as it has elements. A scalar can also be initialized by a list of one initializer, which is replaced by
+    that initializer.
+    */
+    fn handle_initializer(&mut self, initializer: Initializer, ty: &Type, span: &Span) -> SemanticResult<Initializer> {
+        match (initializer, ty) {
+            (Initializer::Single(Expression::Factor(Factor::StringLiteral(_))), Type::Array(..)) => {
+                Err(Diagnostic::error(Code::Unsupported, "Initializing an array with a string literal is not supported")
+                    .with_primary_label(span, ""))
+            }
+            (Initializer::Single(_), Type::Array(..)) => {
+                Err(Diagnostic::error(Code::InvalidInitializer, format!("Initializer of an array of type `{}` is not a brace-enclosed list", ty))
+                    .with_primary_label(span, ""))
+            }
+            (Initializer::Single(expression), ty) => Ok(Initializer::Single(self.handle_assigned(expression, ty, span)?)),
+            (Initializer::Compound(initializers, brace), Type::Array(element, count)) => {
+                if count.is_some_and(|count| initializers.len() as u64 > count) {
+                    let message = format!("Excess elements in initializer of an array of type `{}` (have {})", ty, initializers.len());
+                    return Err(Diagnostic::error(Code::InvalidInitializer, message).with_primary_label(&brace, ""));
+                }
+
+                let initializers = initializers.into_iter()
+                    .map(|initializer| self.handle_initializer(initializer, element, span))
+                    .collect::<SemanticResult<_>>()?;
+                Ok(Initializer::Compound(initializers, brace))
+            }
+            (Initializer::Compound(initializers, brace), ty) => match <[Initializer; 1]>::try_from(initializers) {
+                Ok([initializer]) => self.handle_initializer(initializer, ty, span),
+                Err(initializers) => {
+                    let message = if initializers.is_empty() { "Empty scalar initializer" } else { "Excess elements in scalar initializer" };
+                    Err(Diagnostic::error(Code::InvalidInitializer, message).with_primary_label(&brace, ""))
+                }
+            },
+        }
+    }
+
+    fn handle_statement(&mut self, statement: Statement) -> SemanticResult<Statement> {
+        match statement {
+            Statement::Return { expr, span } => {
+                let return_type = self.return_type.clone();
+                Ok(Statement::Return { expr: self.handle_assigned(expr, &return_type, &span)?, span })
+            }
+            Statement::Expression(expression) => Ok(Statement::Expression(self.handle_expression(expression)?.0)),
+            Statement::If { condition, then, otherwise } => {
+                Ok(Statement::If {
+                    condition: self.handle_expression(condition)?.0,
+                    then: Box::new(self.handle_statement(*then)?),
+                    otherwise: otherwise.map(|otherwise| self.handle_statement(*otherwise).map(Box::new)).transpose()?,
+                })
+            }
+            Statement::Compound(block) => Ok(Statement::Compound(self.handle_block(block)?)),
+            Statement::While { condition, body, label } => {
+                let condition = self.handle_expression(condition)?.0;
+                Ok(Statement::While { condition, body: Box::new(self.handle_statement(*body)?), label })
+            }
+            Statement::DoWhile { body, condition, label } => {
+                let body = Box::new(self.handle_statement(*body)?);
+                Ok(Statement::DoWhile { body, condition: self.handle_expression(condition)?.0, label })
+            }
+            Statement::For { init, condition, post, body, label } => {
+                let init = match *init {
+                    ForInit::Declaration(declarations) => ForInit::Declaration(
+                declarations.into_iter().map(|declaration| self.handle_declaration(declaration, false)).collect::<SemanticResult<_>>()?,
+            ),
+                    ForInit::Expression(expression) => ForInit::Expression(self.handle_optional_expression(expression)?),
+                };
+                let condition = self.handle_optional_expression(condition)?;
+                let post = self.handle_optional_expression(post)?;
+
+                Ok(Statement::For { init: Box::new(init), condition, post, body: Box::new(self.handle_statement(*body)?), label })
+            }
+            Statement::Switch { condition, body, label, cases, span } => {
+                let (condition, ty) = self.handle_expression(condition)?;
+                if !ty.is_integer() {
+                    return Err(Diagnostic::error(Code::InvalidOperands, "Switch quantity is not an integer")
+                        .with_primary_label(&span, ""));
+                }
+
+                let promoted = ty.promote();
+                self.switches.push(EnclosingSwitch { ty: promoted.clone(), values: HashMap::new(), case_spans: HashMap::new() });
+                let body = self.handle_statement(*body);
+                let switch = self.switches.pop().unwrap();
+                let body = Box::new(body?);
+
+                let cases = SwitchCases {
+                    cases: cases.cases.into_iter().map(|(_, label)| (switch.values[&label], label)).collect(),
+                    default: cases.default,
+                };
+
+                Ok(Statement::Switch { condition: convert(condition, &ty, &promoted, &span), body, label, cases, span })
+            }
+            Statement::Case { value, body, label, span } => {
+                let switch = self.switches.last_mut().expect("Loop labelling checks that cases are in a switch");
+                let converted = evaluate_integer_constant(&value)
+                    .and_then(|value| value.convert(switch.ty.clone()))
+                    .and_then(|value| value.integer_value())
+                    .expect("Loop labelling checks that cases are integer constants");
+
+                // Values which differ in the source can become the same once converted
+                let number = converted as i64;
+                if let Some(previous) = switch.case_spans.get(&number) {
+                    let message = format!("Duplicate case value `{}` once converted to `{}`", converted, switch.ty);
+                    return Err(Diagnostic::error(Code::DuplicateCase, message)
+                        .with_primary_label(&span, "")
+                        .with_secondary_label(previous, "previously used here"));
+                }
+
+                switch.case_spans.insert(number, span.clone());
+                switch.values.insert(label.clone(), number);
+
+                Ok(Statement::Case { value, body: Box::new(self.handle_statement(*body)?), label, span })
+            }
+            Statement::Default { body, label, span } => {
+                Ok(Statement::Default { body: Box::new(self.handle_statement(*body)?), label, span })
+            }
+            Statement::Labeled { label, body, span } => {
+                Ok(Statement::Labeled { label, body: Box::new(self.handle_statement(*body)?), span })
+            }
+            statement @ (Statement::Goto { .. } | Statement::Break { .. } | Statement::Continue { .. } | Statement::Null) => Ok(statement),
+        }
+    }
+
+    fn handle_optional_expression(&mut self, expression: Option<Expression>) -> SemanticResult<Option<Expression>> {
+        expression.map(|expression| Ok(self.handle_expression(expression)?.0)).transpose()
+    }
+
+    /**
+    Check `expression`, and convert it to `ty` as if by assignment. Arithmetic types convert to each other, while a
+    pointer only takes a pointer of the same type or a null pointer constant.
+    */
+    fn handle_assigned(&mut self, expression: Expression, ty: &Type, span: &Span) -> SemanticResult<Expression> {
+        let (expression, from) = self.handle_expression(expression)?;
+
+        let compatible = (from.is_arithmetic() && ty.is_arithmetic()) || from == *ty || (ty.is_pointer() && is_null_pointer_constant(&expression, &from));
+        if !compatible {
+            return Err(Diagnostic::error(Code::IncompatibleTypes, format!("Incompatible types: expected `{}`, found `{}`", ty, from))
+                .with_primary_label(span, ""));
+        }
+
+        Ok(convert(expression, &from, ty, span))
+    }
+
+    fn handle_expression(&mut self, expression: Expression) -> SemanticResult<(Expression, Type)> {
+        match expression {
+            Expression::Factor(factor) => {
+                let (factor, ty) = self.handle_value(factor)?;
+                Ok((Expression::Factor(factor), ty))
+            }
+            Expression::Binary { left, operator: operator @ (BinaryOperator::And | BinaryOperator::Or), right, span } => {
+                // Each operand is only compared with zero
+                let (left, _) = self.handle_expression(*left)?;
+                let (right, _) = self.handle_expression(*right)?;
+                Ok((Expression::Binary { left: Box::new(left), operator, right: Box::new(right), span }, Type::Int))
+            }
+            Expression::Binary { left, operator, right, span } => {
+                let (left, left_type) = self.handle_expression(*left)?;
+                let (right, right_type) = self.handle_expression(*right)?;
+                let (left_converted, right_converted, ty) = operand_types(operator, (&left, &left_type), (&right, &right_type), &span)?;
+
+                let left = Box::new(convert(left, &left_type, &left_converted, &span));
+                let right = Box::new(convert(right, &right_type, &right_converted, &span));
+
+                Ok((Expression::Binary { left, operator, right, span }, ty))
+            }
+            Expression::Assignment { left, right, span } => {
+                let (left, ty) = self.handle_assigned_object(*left, &span)?;
+                let right = self.handle_assigned(*right, &ty, &span)?;
+                Ok((Expression::Assignment { left: Box::new(left), right: Box::new(right), span }, ty))
+            }
+            Expression::CompoundAssignment { operator, left, right, span } => {
+                // The variable is converted where the operation is emitted, since the result is stored back into it
+                let (left, ty) = self.handle_assigned_object(*left, &span)?;
+                let (right, right_type) = self.handle_expression(*right)?;
+                let (_, right_converted, result) = operand_types(operator, (&left, &ty), (&right, &right_type), &span)?;
+
+                // The result is stored back, so pointer arithmetic has to give a pointer of the type of the left operand
+                if result != ty && !(result.is_arithmetic() && ty.is_arithmetic()) {
+                    let message = format!("Invalid operands to `{}=` (have `{}` and `{}`)", spelling(operator), ty, right_type);
+                    return Err(Diagnostic::error(Code::InvalidOperands, message).with_primary_label(&span, ""));
+                }
+
+                let right = Box::new(convert(right, &right_type, &right_converted, &span));
+                Ok((Expression::CompoundAssignment { operator, left: Box::new(left), right, span }, ty))
+            }
+            Expression::Conditional { condition, then, otherwise, span } => {
+                let (condition, _) = self.handle_expression(*condition)?;
+                let (then, then_type) = self.handle_expression(*then)?;
+                let (otherwise, otherwise_type) = self.handle_expression(*otherwise)?;
+
+                // Either both branches are arithmetic, or they are pointers of the same type, one of which can be a
+                // null pointer constant
+                let ty = if then_type.is_arithmetic() && otherwise_type.is_arithmetic() {
+                    then_type.common(&otherwise_type)
+                } else if then_type == otherwise_type || (then_type.is_pointer() && is_null_pointer_constant(&otherwise, &otherwise_type)) {
+                    then_type.clone()
+                } else if otherwise_type.is_pointer() && is_null_pointer_constant(&then, &then_type) {
+                    otherwise_type.clone()
+                } else {
+                    let message = format!("Type mismatch in conditional expression (have `{}` and `{}`)", then_type, otherwise_type);
+                    return Err(Diagnostic::error(Code::IncompatibleTypes, message).with_primary_label(&span, ""));
+                };
+
+                Ok((Expression::Conditional {
+                    condition: Box::new(condition),
+                    then: Box::new(convert(then, &then_type, &ty, &span)),
+                    otherwise: Box::new(convert(otherwise, &otherwise_type, &ty, &span)),
+                    span,
+                }, ty))
+            }
+        }
+    }
+
+    /**
+    Check the left operand of an assignment, which designates an object that can't be an array.
+    */
+    fn handle_assigned_object(&mut self, expression: Expression, span: &Span) -> SemanticResult<(Expression, Type)> {
+        let Expression::Factor(factor) = expression else {
+            unreachable!("Identifier resolution checks that only lvalues are assigned");
+        };
+
+        let (factor, ty) = self.handle_factor(factor)?;
+        if ty.is_array() {
+            return Err(Diagnostic::error(Code::InvalidLvalue, format!("Assignment to expression with array type `{}`", ty))
+                .with_primary_label(span, ""));
+        }
+
+        Ok((Expression::Factor(factor), ty))
+    }
+
+    /**
+    Check a factor whose value is used, so that an array decays to a pointer to its first element.
+    */
+    fn handle_value(&mut self, factor: Factor) -> SemanticResult<(Factor, Type)> {
+        let (factor, ty) = self.handle_factor(factor)?;
+        Ok((factor, ty.decay()))
+    }
+
+    /**
+    Check a factor, and give the type of the object it designates without decaying arrays.
+    */
+    fn handle_factor(&mut self, factor: Factor) -> SemanticResult<(Factor, Type)> {
+        match factor {
+            Factor::Constant(constant) => {
+                let ty = constant.ty();
+                Ok((Factor::Constant(constant), ty))
+            }
+            Factor::Var(name, span) => {
+                let ty = self.variables[&name].ty.clone();
+                Ok((Factor::Var(name, span), ty))
+            }
+            Factor::StringLiteral(value) => Ok((Factor::StringLiteral(value), Type::Pointer(Box::new(Type::Char)))),
+            Factor::Call(name, args, span) => {
+                let function = &self.functions[&name];
+                if args.len() != function.params.len() {
+                    let message = if args.len() > function.params.len() { "Too many arguments" } else { "Too few arguments" };
+                    return Err(Diagnostic::error(Code::ArgumentCount, format!("{} to function `{}`", message, name))
+                        .with_primary_label(&span, format!("expected {}, found {}", function.params.len(), args.len()))
+                        .with_secondary_label(&function.span, "declared here"));
+                }
+
+                let (params, return_type) = (function.params.clone(), function.return_type.clone());
+                let args = args.into_iter().zip(&params).map(|(arg, ty)| self.handle_assigned(arg, ty, &span)).collect::<SemanticResult<_>>()?;
+
+                Ok((Factor::Call(name, args, span), return_type))
+            }
+            Factor::Unary(operator, operand, span) => {
+                let (operand, ty) = self.handle_value(*operand)?;
+                match operator {
+                    // The operand is only compared with zero
+                    UnaryOperator::Not => Ok((Factor::Unary(operator, Box::new(operand), span), Type::Int)),
+                    UnaryOperator::Complement if !ty.is_integer() => {
+                        Err(Diagnostic::error(Code::InvalidOperands, format!("Wrong type argument to bit-complement (have `{}`)", ty))
+                            .with_primary_label(&span, ""))
+                    }
+                    UnaryOperator::Negate if !ty.is_arithmetic() => {
+                        Err(Diagnostic::error(Code::InvalidOperands, format!("Wrong type argument to unary minus (have `{}`)", ty))
+                            .with_primary_label(&span, ""))
+                    }
+                    UnaryOperator::Complement | UnaryOperator::Negate => {
+                        let promoted = ty.promote();
+                        Ok((Factor::Unary(operator, Box::new(convert_factor(operand, &ty, &promoted, &span)), span), promoted))
+                    }
+                }
+            }
+            Factor::Increment(operator, operand, span) => {
+                // Like a compound assignment, the variable is promoted where the operation is emitted
+                let (operand, ty) = self.handle_factor(*operand)?;
+                if !ty.is_arithmetic() && !ty.is_pointer() {
+                    let spelling = match operator {
+                        IncrementOperator::PrefixIncrement | IncrementOperator::PostfixIncrement => "++",
+                        IncrementOperator::PrefixDecrement | IncrementOperator::PostfixDecrement => "--",
+                    };
+                    return Err(Diagnostic::error(Code::InvalidOperands, format!("Wrong type argument to `{}` (have `{}`)", spelling, ty))
+                        .with_primary_label(&span, ""));
+                }
+                check_pointer_arithmetic(&ty, &span)?;
+
+                Ok((Factor::Increment(operator, Box::new(operand), span), ty))
+            }
+            Factor::AddressOf(operand, span) => {
+                let (operand, ty) = self.handle_factor(*operand)?;
+                Ok((Factor::AddressOf(Box::new(operand), span), Type::Pointer(Box::new(ty))))
+            }
+            Factor::Dereference(operand, span) => {
+                let (operand, ty) = self.handle_value(*operand)?;
+                let Some(referenced) = ty.pointee() else {
+                    return Err(Diagnostic::error(Code::InvalidOperands, format!("Invalid type argument of unary `*` (have `{}`)", ty))
+                        .with_primary_label(&span, ""));
+                };
+
+                let referenced = referenced.clone();
+                Ok((Factor::Dereference(Box::new(operand), span), referenced))
+            }
+            Factor::Subscript(array, index, span) => {
+                let (array, array_type) = self.handle_value(*array)?;
+                let (index, index_type) = self.handle_expression(*index)?;
+
+                // Either operand can be the pointer, as `i[a]` is `*(i + a)` too. The index is converted like the integer
+                // operand of pointer arithmetic.
+                let (array, index, ty) = match (&array_type, &index_type) {
+                    (Type::Pointer(element), index_type) if index_type.is_integer() => {
+                        (array, convert(index, index_type, &Type::Long, &span), (**element).clone())
+                    }
+                    (array_type, Type::Pointer(element)) if array_type.is_integer() => {
+                        (convert_factor(array, array_type, &Type::Long, &span), index, (**element).clone())
+                    }
+                    _ => {
+                        let message = format!("Subscripted value is neither an array nor a pointer (have `{}` and `{}`)", array_type, index_type);
+                        return Err(Diagnostic::error(Code::InvalidOperands, message).with_primary_label(&span, ""));
+                    }
+                };
+                check_pointer_arithmetic(&Type::Pointer(Box::new(ty.clone())), &span)?;
+
+                Ok((Factor::Subscript(Box::new(array), Box::new(index), span), ty))
+            }
+            Factor::Cast(ty, operand, span) => {
+                // Pointers only convert to and from integers and other pointers
+                if ty.is_array() {
+                    return Err(Diagnostic::error(Code::IncompatibleTypes, format!("Cast specifies array type `{}`", ty))
+                        .with_primary_label(&span, ""));
+                }
+
+                let (operand, from) = self.handle_value(*operand)?;
+                if (from.is_pointer() && ty == Type::Double) || (from == Type::Double && ty.is_pointer()) {
+                    return Err(Diagnostic::error(Code::IncompatibleTypes, format!("Invalid cast from `{}` to `{}`", from, ty))
+                        .with_primary_label(&span, ""));
+                }
+
+                Ok((Factor::Cast(ty.clone(), Box::new(operand), span), ty))
+            }
+            Factor::Expression(expression) => {
+                // An array in parentheses is still an array, whose address `&(a)` is
+                let (expression, ty) = match *expression {
+                    Expression::Factor(factor) => {
+                        let (factor, ty) = self.handle_factor(factor)?;
+                        (Expression::Factor(factor), ty)
+                    }
+                    expression => self.handle_expression(expression)?,
+                };
+                Ok((Factor::Expression(Box::new(expression)), ty))
+            }
+        }
+    }
+}
+
+impl SemanticPass for TypeChecking {
+    fn run(&mut self, program: Program) -> SemanticResult<Program> {
+        let declarations = program.into_declarations().into_iter().map(|declaration| match declaration {
+            Declaration::Function(function) => Ok(Declaration::Function(self.handle_function(function)?)),
+            Declaration::Variable(declaration) => Ok(Declaration::Variable(self.handle_declaration(declaration, true)?)),
+        }).collect::<SemanticResult<_>>()?;
+
+        Ok(Program::new(declarations))
+    }
+}
+
+/**
+Types to which the operands of a binary operator are converted, and the type of the result. The operands of a shift are
+promoted separately, as the result has the type of the left one, while the other operators convert both to their common
+type.
+
+An integer can be added to a pointer, or subtracted from it, after being converted to `long`. Two pointers of the same
+type can be subtracted, giving a `long` like `ptrdiff_t`, or compared. A pointer is also compared for equality with a
+null pointer constant, which is converted to the type of the pointer.
+*/
+fn operand_types(operator: BinaryOperator, (left, left_type): (&Expression, &Type), (right, right_type): (&Expression, &Type), span: &Span) -> SemanticResult<(Type, Type, Type)> {
+    let invalid = || {
+        let message = format!("Invalid operands to binary {} (have `{}` and `{}`)", spelling(operator), left_type, right_type);
+        Diagnostic::error(Code::InvalidOperands, message).with_primary_label(span, "")
+    };
+
+    if left_type.is_pointer() || right_type.is_pointer() {
+        if matches!(operator, BinaryOperator::Add | BinaryOperator::Sub) {
+            check_pointer_arithmetic(left_type, span)?;
+            check_pointer_arithmetic(right_type, span)?;
+        }
+
+        let ty = match operator {
+            BinaryOperator::Add | BinaryOperator::Sub if left_type.is_pointer() && right_type.is_integer() => {
+                return Ok((left_type.clone(), Type::Long, left_type.clone()));
+            }
+            BinaryOperator::Add if left_type.is_integer() => return Ok((Type::Long, right_type.clone(), right_type.clone())),
+            BinaryOperator::Sub if left_type == right_type => return Ok((left_type.clone(), right_type.clone(), Type::Long)),
+            _ if is_comparison(operator) && left_type == right_type => left_type.clone(),
+            BinaryOperator::Equal | BinaryOperator::NotEqual if is_null_pointer_constant(right, right_type) => left_type.clone(),
+            BinaryOperator::Equal | BinaryOperator::NotEqual if is_null_pointer_constant(left, left_type) => right_type.clone(),
+            _ => return Err(invalid()),
+        };
+        return Ok((ty.clone(), ty, Type::Int));
+    }
+
+    let integer_only = matches!(operator, BinaryOperator::Rem | BinaryOperator::BitwiseAnd | BinaryOperator::BitwiseOr
+        | BinaryOperator::BitwiseXor | BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight);
+    if integer_only && (!left_type.is_integer() || !right_type.is_integer()) {
+        return Err(invalid());
+    }
+
+    match operator {
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => Ok((left_type.promote(), right_type.promote(), left_type.promote())),
+        _ if is_comparison(operator) => {
+            let common = left_type.common(right_type);
+            Ok((common.clone(), common, Type::Int))
+        }
+        _ => {
+            let common = left_type.common(right_type);
+            Ok((common.clone(), common.clone(), common))
+        }
+    }
+}
+
+/**
+Check that a pointer used in arithmetic, if `ty` is one, points to a complete type: the elements of an array of unknown
+size have no size to scale the integer by.
+*/
+fn check_pointer_arithmetic(ty: &Type, span: &Span) -> SemanticResult<()> {
+    match ty.pointee() {
+        Some(referenced) if !referenced.is_complete() => {
+            Err(Diagnostic::error(Code::InvalidOperands, format!("Arithmetic on a pointer to incomplete type `{}`", referenced))
+                .with_primary_label(span, ""))
+        }
+        _ => Ok(()),
+    }
+}
+
+/**
+Whether `expression`, of type `ty`, is a null pointer constant: an integer constant expression whose value is zero.
+*/
+fn is_null_pointer_constant(expression: &Expression, ty: &Type) -> bool {
+    ty.is_integer() && evaluate_integer_constant(expression).is_some_and(|value| value.is_zero())
+}
+
+fn spelling(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Rem => "%",
+        BinaryOperator::BitwiseAnd => "&",
+        BinaryOperator::BitwiseOr => "|",
+        BinaryOperator::BitwiseXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::LessThan => "<",
+        BinaryOperator::LessOrEqual => "<=",
+        BinaryOperator::GreaterThan => ">",
+        BinaryOperator::GreaterOrEqual => ">=",
+    }
+}
+
+fn is_comparison(operator: BinaryOperator) -> bool {
+    matches!(operator, BinaryOperator::Equal | BinaryOperator::NotEqual | BinaryOperator::LessThan
+        | BinaryOperator::LessOrEqual | BinaryOperator::GreaterThan | BinaryOperator::GreaterOrEqual)
+}
+
+/**
+Make the conversion of `expression` from `from` to `to` explicit, with a cast at the span of the operation which
+requires it.
+*/
+fn convert(expression: Expression, from: &Type, to: &Type, span: &Span) -> Expression {
+    if from == to {
+        return expression;
+    }
+
+    let factor = match expression {
+        Expression::Factor(factor) => factor,
+        expression => Factor::Expression(Box::new(expression)),
+    };
+    Expression::Factor(convert_factor(factor, from, to, span))
+}
+
+fn convert_factor(factor: Factor, from: &Type, to: &Type, span: &Span) -> Factor {
+    if from == to { factor } else { Factor::Cast(to.clone(), Box::new(factor), span.clone()) }
+}
+
+fn different_kind_of_symbol(name: &str, span: &Span, previous: &Span) -> Diagnostic {
+    Diagnostic::error(Code::ConflictingTypes, format!("`{}` redeclared as a different kind of symbol", name))
+        .with_primary_label(span, "")
+        .with_secondary_label(previous, "previous declaration is here")
+}
@@ -15,11 +15,16 @@ impl StackAllocator {
         }
     }
 
-    pub fn get_stack_offset(&mut self, pseudo_register: &str) -> i64 {
+    /**
+    Offset from the frame base of the `size` bytes allocated to the pseudo register, which is a multiple of
+    `alignment`. The frame base is 16-byte aligned, so every alignment up to 16 holds for the address too.
+    */
+    pub fn get_stack_offset(&mut self, pseudo_register: &str, size: i64, alignment: i64) -> i64 {
         if let Some(offset) = self.mapping.get(pseudo_register) {
             *offset
         } else {
-            self.max_offset -= 4;
+            self.max_offset -= size;
+            self.max_offset -= self.max_offset.rem_euclid(alignment);
             self.mapping.insert(pseudo_register.to_owned(), self.max_offset);
             self.max_offset
         }
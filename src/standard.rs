@@ -0,0 +1,10 @@
+use clap::ValueEnum;
+
+/**
+Revision of the C standard the source code is compiled against.
+*/
+#[derive(Debug, Eq, PartialEq, Copy, Clone, PartialOrd, Ord, ValueEnum)]
+pub enum Standard {
+    C17,
+    C23,
+}
@@ -1,20 +1,40 @@
 use crate::ast;
+use crate::ast::{Constant, StorageClass, Type};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::semantic::evaluate_arithmetic_constant;
 use crate::tacky::Instruction::Return;
+use std::collections::HashMap;
+
+// A jump table pays off over a chain of comparisons from a few cases, as long as most of its entries are cases
+const JUMP_TABLE_MIN_CASES: usize = 4;
+const JUMP_TABLE_MAX_ENTRIES_PER_CASE: i128 = 3;
 
 #[derive(Debug)]
 pub struct Program {
-    function: Function,
+    functions: Vec<Function>,
+    static_variables: Vec<StaticVariable>,
+    symbols: HashMap<String, Type>,
 }
 
 impl Program {
-    pub fn function(&self) -> &Function {
-        &self.function
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub fn static_variables(&self) -> &[StaticVariable] {
+        &self.static_variables
     }
-}
 
+    pub fn symbols(&self) -> &HashMap<String, Type> {
+        &self.symbols
+    }
+}
 #[derive(Debug)]
 pub struct Function {
     identifier: String,
+    // Whether the function is visible from other files
+    global: bool,
+    params: Vec<String>,
     instructions: Vec<Instruction>,
 }
 
@@ -23,21 +43,88 @@ impl Function {
         &self.identifier
     }
 
+    pub fn global(&self) -> bool {
+        self.global
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
     pub fn instructions(&self) -> &Vec<Instruction> {
         &self.instructions
     }
 }
 
+/**
+Variable with static storage duration. Variables defined in another file have no initial value.
+*/
+#[derive(Debug)]
+pub struct StaticVariable {
+    name: String,
+    global: bool,
+    init: Option<Vec<StaticInit>>,
+}
+
+impl StaticVariable {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn global(&self) -> bool {
+        self.global
+    }
+
+    pub fn init(&self) -> Option<&[StaticInit]> {
+        self.init.as_deref()
+    }
+}
+
+/**
+Part of the initial value of a variable with static storage duration, in the order of their addresses: a scalar, or a
+number of zero bytes.
+*/
+#[derive(Debug, Clone)]
+pub enum StaticInit {
+    Constant(Constant),
+    Zero(u64),
+}
+
 #[derive(Debug)]
 pub enum Instruction {
     Return { val: Value },
     Unary { operator: UnaryOperator, src: Value, dst: Value },
     Binary { operator: BinaryOperator, lhs: Value, rhs: Value, dst: Value },
+    // Conversions between integer types of different sizes
+    SignExtend { src: Value, dst: Value },
+    ZeroExtend { src: Value, dst: Value },
+    Truncate { src: Value, dst: Value },
+    // Conversions between doubles and signed integers of 4 or 8 bytes
+    IntToDouble { src: Value, dst: Value },
+    DoubleToInt { src: Value, dst: Value },
+    Copy { src: Value, dst: Value },
+    // Address of a variable, and accesses to the object a pointer points to
+    GetAddress { src: Value, dst: Value },
+    Load { src_ptr: Value, dst: Value },
+    Store { src: Value, dst_ptr: Value },
+    // Pointer moved by `index` elements of `scale` bytes, where `index` is a long
+    AddPtr { ptr: Value, index: Value, scale: u64, dst: Value },
+    // Store in the part of the array variable `dst` which starts `offset` bytes from its beginning
+    CopyToOffset { src: Value, dst: Value, offset: u64 },
+    FunCall { name: String, args: Vec<Value>, dst: Value },
+    Jump { target: String },
+    JumpIfZero { condition: Value, target: String },
+    JumpIfNotZero { condition: Value, target: String },
+    // Jump to `targets[index]`, or to `default` when `index`, taken as unsigned, is past the end of `table`
+    JumpTable { index: Value, table: String, targets: Vec<String>, default: String },
+    Label(String),
 }
 
 #[derive(Clone, Debug)]
 pub enum Value {
-    Constant(u64),
+    Constant(Constant),
+    // Address of a read-only, null-terminated array holding the bytes of the literal
+    StringLiteral(Vec<u8>),
     Var { identifier: String },
 }
 
@@ -45,6 +132,7 @@ pub enum Value {
 pub enum UnaryOperator {
     Complement,
     Negate,
+    Not,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -54,6 +142,24 @@ pub enum BinaryOperator {
     Multiply,
     Divide,
     Remainder,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, BinaryOperator::Equal | BinaryOperator::NotEqual | BinaryOperator::LessThan
+            | BinaryOperator::LessOrEqual | BinaryOperator::GreaterThan | BinaryOperator::GreaterOrEqual)
+    }
 }
 
 struct VariableNameGenerator {
@@ -72,76 +178,759 @@ impl VariableNameGenerator {
         self.counter += 1;
         var_name
     }
+
+    pub fn make_label(&mut self, name: &str) -> String {
+        let label = format!("{}.{}", name, self.counter);
+        self.counter += 1;
+        label
+    }
+}
+
+/**
+Initial value of a variable with static storage duration, as far as its declarations so far tell.
+*/
+#[derive(Debug, Clone)]
+enum InitialValue {
+    // Declared at file scope without an initializer nor `extern`, which defines it as zero unless another declaration
+    // gives it a value
+    Tentative,
+    Initial(Vec<StaticInit>),
+    // Only declared with `extern`, so it is defined in another file unless another declaration defines it
+    NoInitializer,
+}
+
+/**
+Object designated by an lvalue: either a variable, or the object a pointer points to, which is accessed through the
+pointer.
+*/
+enum Lvalue {
+    Var(Value),
+    Dereferenced(Value),
+}
+
+/**
+Return type and linkage of a function, as its first declaration gives them.
+*/
+struct FunctionAttributes {
+    return_type: Type,
+    global: bool,
+}
+
+/**
+Variable with static storage duration, merged from all its declarations.
+*/
+struct StaticAttributes {
+    ty: Type,
+    init: InitialValue,
+    global: bool,
 }
 
 pub struct TackEmitter {
     variable_name_generator: VariableNameGenerator,
+    symbols: HashMap<String, Type>,
+    functions: HashMap<String, FunctionAttributes>,
+    // Variables with static storage duration, and their names in the order they are first declared
+    statics: HashMap<String, StaticAttributes>,
+    static_order: Vec<String>,
+    // Name of the function being emitted, which qualifies the labels of its labelled statements
+    function_name: String,
 }
 
+type EmitterResult<T> = Result<T, Diagnostic>;
+
 impl TackEmitter {
     pub fn new() -> Self {
         TackEmitter {
-            variable_name_generator: VariableNameGenerator::new()
+            variable_name_generator: VariableNameGenerator::new(),
+            symbols: HashMap::new(),
+            functions: HashMap::new(),
+            statics: HashMap::new(),
+            static_order: vec![],
+            function_name: String::new(),
         }
     }
 
-    pub fn emit_program(&mut self, program: &ast::Program) -> Program {
-        Program {
-            function: self.emit_function(program.function())
+    pub fn emit_program(mut self, program: &ast::Program) -> EmitterResult<Program> {
+        let mut functions = vec![];
+        for declaration in program.declarations() {
+            match declaration {
+                ast::Declaration::Function(function) => {
+                    self.declare_function(function);
+
+                    if let Some(body) = function.body() {
+                        functions.push(self.emit_function(function, body)?);
+                    }
+                }
+                ast::Declaration::Variable(declaration) => self.declare_file_scope_variable(declaration)?,
+            }
+        }
+
+        // A tentative definition which nothing else defines is a definition with a zero initializer
+        let static_variables = self.static_order.iter().map(|name| {
+            let attributes = &self.statics[name];
+            let init = match &attributes.init {
+                InitialValue::Initial(init) => Some(init.clone()),
+                InitialValue::Tentative => Some(vec![StaticInit::Zero(attributes.ty.size())]),
+                InitialValue::NoInitializer => None,
+            };
+
+            StaticVariable { name: name.clone(), global: attributes.global, init }
+        }).collect();
+
+        Ok(Program {
+            functions,
+            static_variables,
+            symbols: self.symbols,
+        })
+    }
+
+    /**
+    Record the linkage of a function. Type checking has made sure that every declaration agrees with the first one.
+    */
+    fn declare_function(&mut self, function: &ast::Function) {
+        self.functions.entry(function.name().to_owned()).or_insert_with(|| FunctionAttributes {
+            return_type: function.return_type().clone(),
+            global: function.storage_class() != Some(StorageClass::Static),
+        });
+    }
+
+    fn declare_file_scope_variable(&mut self, declaration: &ast::VariableDeclaration) -> EmitterResult<()> {
+        let init = match (&declaration.init, declaration.storage_class) {
+            (Some(init), _) => InitialValue::Initial(self.evaluate_static_initializer(declaration, init, &declaration.ty)?),
+            (None, Some(StorageClass::Extern)) => InitialValue::NoInitializer,
+            (None, _) => InitialValue::Tentative,
+        };
+
+        self.declare_static(declaration, init, declaration.storage_class != Some(StorageClass::Static));
+        Ok(())
+    }
+
+    /**
+    Record a declaration of a variable with static storage duration. Type checking has made sure that the declarations
+    agree on the linkage, which an `extern` one takes from the previous one, and that at most one has an initializer.
+    */
+    fn declare_static(&mut self, declaration: &ast::VariableDeclaration, init: InitialValue, global: bool) {
+        self.symbols.insert(declaration.name.clone(), declaration.ty.clone());
+
+        let Some(previous) = self.statics.get_mut(&declaration.name) else {
+            self.statics.insert(declaration.name.clone(), StaticAttributes { ty: declaration.ty.clone(), init, global });
+            self.static_order.push(declaration.name.clone());
+            return;
+        };
+
+        // Type checking has given the declaration the composite type of the previous ones
+        previous.ty = declaration.ty.clone();
+        previous.init = match (previous.init.clone(), init) {
+            (InitialValue::Initial(init), _) | (_, InitialValue::Initial(init)) => InitialValue::Initial(init),
+            (InitialValue::Tentative, _) | (_, InitialValue::Tentative) => InitialValue::Tentative,
+            (InitialValue::NoInitializer, InitialValue::NoInitializer) => InitialValue::NoInitializer,
+        };
+    }
+
+    /**
+    Value of the initializer of a part of type `ty` of a variable with static storage duration, which is computed before
+    the program starts so has to be made of constants. The elements left out of a brace-enclosed list are zero bytes.
+    */
+    fn evaluate_static_initializer(&self, declaration: &ast::VariableDeclaration, init: &ast::Initializer, ty: &Type) -> EmitterResult<Vec<StaticInit>> {
+        match (init, ty) {
+            (ast::Initializer::Single(expression), ty) => {
+                let constant = evaluate_arithmetic_constant(expression).and_then(|constant| constant.convert(ty.clone())).ok_or_else(|| {
+                    Diagnostic::error(Code::NonConstantInitializer, "Initializer element is not a constant")
+                        .with_primary_label(&declaration.span, "")
+                })?;
+                Ok(vec![StaticInit::Constant(constant)])
+            }
+            (ast::Initializer::Compound(initializers, _), Type::Array(element, Some(count))) => {
+                let mut init = vec![];
+                for initializer in initializers {
+                    init.extend(self.evaluate_static_initializer(declaration, initializer, element)?);
+                }
+                if (initializers.len() as u64) < *count {
+                    init.push(StaticInit::Zero((*count - initializers.len() as u64) * element.size()));
+                }
+
+                Ok(init)
+            }
+            (ast::Initializer::Compound(..), _) => unreachable!("Type checking only leaves brace-enclosed lists for arrays"),
         }
     }
-    fn emit_function(&mut self, function: &ast::Function) -> Function {
+
+    fn emit_function(&mut self, function: &ast::Function, body: &ast::Block) -> EmitterResult<Function> {
         let mut instructions = vec![];
+        self.function_name = function.name().to_owned();
 
-        self.emit_statement(function.body(), &mut instructions);
+        let params = function.params().iter().map(|param| {
+            let name = param.name.clone().expect("Parameters of a definition are named");
+            self.symbols.insert(name.clone(), param.ty.clone());
+            name
+        }).collect();
 
-        Function {
+        self.emit_block(body, function.return_type(), &mut instructions)?;
+
+        // Reaching the end of main returns 0, and the value returned by other functions is not used
+        let return_val = self.emit_conversion(Value::Constant(Constant::int(0)), function.return_type().clone(), &mut instructions);
+        instructions.push(Return { val: return_val });
+
+        Ok(Function {
             identifier: function.name().to_owned(),
+            global: self.functions[function.name()].global,
+            params,
             instructions,
+        })
+    }
+
+    fn emit_block(&mut self, block: &ast::Block, return_type: &Type, instructions: &mut Vec<Instruction>) -> EmitterResult<()> {
+        // Variables have unique names at this point, so scopes don't matter anymore
+        for item in block.items() {
+            match item {
+                ast::BlockItem::Statement(statement) => self.emit_statement(statement, return_type, instructions)?,
+                ast::BlockItem::Declaration(ast::Declaration::Variable(declaration)) => self.emit_declaration(declaration, instructions)?,
+                ast::BlockItem::Declaration(ast::Declaration::Function(function)) => self.declare_function(function),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit_declaration(&mut self, declaration: &ast::VariableDeclaration, instructions: &mut Vec<Instruction>) -> EmitterResult<()> {
+        match declaration.storage_class {
+            // Initialized once before the program starts, rather than each time the declaration is reached
+            Some(StorageClass::Static) => {
+                let init = match &declaration.init {
+                    Some(init) => self.evaluate_static_initializer(declaration, init, &declaration.ty)?,
+                    None => vec![StaticInit::Zero(declaration.ty.size())],
+                };
+                self.declare_static(declaration, InitialValue::Initial(init), false);
+                return Ok(());
+            }
+            Some(StorageClass::Extern) => {
+                self.declare_static(declaration, InitialValue::NoInitializer, true);
+                return Ok(());
+            }
+            None => {}
         }
+
+        self.symbols.insert(declaration.name.clone(), declaration.ty.clone());
+
+        if let Some(init) = &declaration.init {
+            let var = Value::Var { identifier: declaration.name.clone() };
+            self.emit_initializer(&var, init, &declaration.ty, 0, instructions)?;
+        }
+
+        Ok(())
     }
 
-    fn emit_statement(&mut self, statement: &ast::Statement, instructions: &mut Vec<Instruction>) {
+    /**
+    Initialize the part of type `ty` of the local variable `var` which starts `offset` bytes from its beginning. The
+    elements left out of a brace-enclosed list are zero.
+    */
+    fn emit_initializer(&mut self, var: &Value, init: &ast::Initializer, ty: &Type, offset: u64, instructions: &mut Vec<Instruction>) -> EmitterResult<()> {
+        match (init, ty) {
+            (ast::Initializer::Single(expression), _) if !self.value_type(var).is_array() => {
+                self.emit_assignment(Lvalue::Var(var.clone()), expression, instructions)?;
+            }
+            (ast::Initializer::Single(expression), ty) => {
+                let value = self.emit_expression(expression, instructions)?;
+                let value = self.emit_conversion(value, ty.clone(), instructions);
+                instructions.push(Instruction::CopyToOffset { src: value, dst: var.clone(), offset });
+            }
+            (ast::Initializer::Compound(initializers, _), Type::Array(element, _)) => {
+                for (index, initializer) in initializers.iter().enumerate() {
+                    self.emit_initializer(var, initializer, element, offset + index as u64 * element.size(), instructions)?;
+                }
+
+                let initialized = initializers.len() as u64 * element.size();
+                emit_zero(var, offset + initialized, ty.size() - initialized, instructions);
+            }
+            (ast::Initializer::Compound(..), _) => unreachable!("Type checking only leaves brace-enclosed lists for arrays"),
+        }
+
+        Ok(())
+    }
+
+    /**
+    Store the value of `expression` in the object designated by `lvalue`, converted to the type of the object.
+    */
+    fn emit_assignment(&mut self, lvalue: Lvalue, expression: &ast::Expression, instructions: &mut Vec<Instruction>) -> EmitterResult<Value> {
+        let value = self.emit_expression(expression, instructions)?;
+        let value = self.emit_conversion(value, self.lvalue_type(&lvalue), instructions);
+
+        Ok(self.emit_store(lvalue, value, instructions))
+    }
+
+    fn emit_lvalue(&mut self, expression: &ast::Expression, instructions: &mut Vec<Instruction>) -> EmitterResult<Lvalue> {
+        match expression {
+            ast::Expression::Factor(factor) => self.emit_lvalue_factor(factor, instructions),
+            _ => unreachable!("Identifier resolution checks that only lvalues are assigned"),
+        }
+    }
+
+    fn emit_lvalue_factor(&mut self, factor: &ast::Factor, instructions: &mut Vec<Instruction>) -> EmitterResult<Lvalue> {
+        match factor {
+            ast::Factor::Var(name, _) => Ok(Lvalue::Var(Value::Var { identifier: name.clone() })),
+            ast::Factor::Dereference(pointer, _) => Ok(Lvalue::Dereferenced(self.emit_factor(pointer, instructions)?)),
+            ast::Factor::Subscript(array, index, _) => {
+                let array = self.emit_factor(array, instructions)?;
+                let index = self.emit_expression(index, instructions)?;
+                Ok(Lvalue::Dereferenced(self.emit_binary(&ast::BinaryOperator::Add, array, index, instructions)))
+            }
+            ast::Factor::Expression(expression) => self.emit_lvalue(expression, instructions),
+            _ => unreachable!("Identifier resolution checks that only lvalues are assigned"),
+        }
+    }
+
+    fn lvalue_type(&self, lvalue: &Lvalue) -> Type {
+        match lvalue {
+            Lvalue::Var(var) => self.value_type(var),
+            Lvalue::Dereferenced(pointer) => self.value_type(pointer).pointee().expect("Type checking only dereferences pointers").clone(),
+        }
+    }
+
+    /**
+    Value of the object designated by `lvalue`. The value of an array is the address of its first element, as arrays
+    decay to pointers.
+    */
+    fn emit_load(&mut self, lvalue: &Lvalue, instructions: &mut Vec<Instruction>) -> Value {
+        if let Type::Array(element, _) = self.lvalue_type(lvalue) {
+            return self.emit_address(lvalue, Type::Pointer(element), instructions);
+        }
+
+        match lvalue {
+            Lvalue::Var(var) => var.clone(),
+            Lvalue::Dereferenced(pointer) => {
+                let dst = self.make_temporary(self.lvalue_type(lvalue));
+                instructions.push(Instruction::Load { src_ptr: pointer.clone(), dst: dst.clone() });
+                dst
+            }
+        }
+    }
+
+    /**
+    Address of the object designated by `lvalue`, as a pointer of type `ty`.
+    */
+    fn emit_address(&mut self, lvalue: &Lvalue, ty: Type, instructions: &mut Vec<Instruction>) -> Value {
+        match lvalue {
+            Lvalue::Var(var) => {
+                let dst = self.make_temporary(ty);
+                instructions.push(Instruction::GetAddress { src: var.clone(), dst: dst.clone() });
+                dst
+            }
+            // `&*p` is `p`, without accessing the object it points to
+            Lvalue::Dereferenced(pointer) => self.emit_conversion(pointer.clone(), ty, instructions),
+        }
+    }
+
+    /**
+    Store `value` in the object designated by `lvalue`, and give the value the object has then.
+    */
+    fn emit_store(&mut self, lvalue: Lvalue, value: Value, instructions: &mut Vec<Instruction>) -> Value {
+        match lvalue {
+            Lvalue::Var(var) => {
+                instructions.push(Instruction::Copy { src: value, dst: var.clone() });
+                var
+            }
+            Lvalue::Dereferenced(pointer) => {
+                instructions.push(Instruction::Store { src: value.clone(), dst_ptr: pointer });
+                value
+            }
+        }
+    }
+
+    fn emit_statement(&mut self, statement: &ast::Statement, return_type: &Type, instructions: &mut Vec<Instruction>) -> EmitterResult<()> {
         match statement {
-            ast::Statement::Return { expr } => {
-                let return_val = self.emit_expression(expr, instructions);
+            ast::Statement::Return { expr, .. } => {
+                let return_val = self.emit_expression(expr, instructions)?;
+                let return_val = self.emit_conversion(return_val, return_type.clone(), instructions);
                 instructions.push(Return {
                     val: return_val
                 });
             }
+            ast::Statement::Expression(expression) => {
+                self.emit_expression(expression, instructions)?;
+            }
+            ast::Statement::If { condition, then, otherwise } => {
+                let else_label = self.variable_name_generator.make_label("if_else");
+                let end = self.variable_name_generator.make_label("if_end");
+
+                let condition = self.emit_expression(condition, instructions)?;
+                let condition = self.emit_condition(condition, instructions);
+                instructions.push(Instruction::JumpIfZero { condition, target: else_label.clone() });
+
+                self.emit_statement(then, return_type, instructions)?;
+
+                match otherwise {
+                    Some(otherwise) => {
+                        instructions.push(Instruction::Jump { target: end.clone() });
+                        instructions.push(Instruction::Label(else_label));
+                        self.emit_statement(otherwise, return_type, instructions)?;
+                        instructions.push(Instruction::Label(end));
+                    }
+                    None => instructions.push(Instruction::Label(else_label)),
+                }
+            }
+            ast::Statement::Compound(block) => self.emit_block(block, return_type, instructions)?,
+            ast::Statement::While { condition, body, label } => {
+                let start = continue_label(label);
+                let end = break_label(label);
+
+                instructions.push(Instruction::Label(start.clone()));
+                self.emit_loop_condition(condition, &end, instructions)?;
+                self.emit_statement(body, return_type, instructions)?;
+                instructions.push(Instruction::Jump { target: start });
+                instructions.push(Instruction::Label(end));
+            }
+            ast::Statement::DoWhile { body, condition, label } => {
+                let start = self.variable_name_generator.make_label("do_start");
+
+                instructions.push(Instruction::Label(start.clone()));
+                self.emit_statement(body, return_type, instructions)?;
+                instructions.push(Instruction::Label(continue_label(label)));
+                let condition = self.emit_expression(condition, instructions)?;
+                let condition = self.emit_condition(condition, instructions);
+                instructions.push(Instruction::JumpIfNotZero { condition, target: start });
+                instructions.push(Instruction::Label(break_label(label)));
+            }
+            ast::Statement::For { init, condition, post, body, label } => {
+                let start = self.variable_name_generator.make_label("for_start");
+                let end = break_label(label);
+
+                match init.as_ref() {
+                    ast::ForInit::Declaration(declarations) => {
+                        for declaration in declarations {
+                            self.emit_declaration(declaration, instructions)?;
+                        }
+                    }
+                    ast::ForInit::Expression(Some(expression)) => {
+                        self.emit_expression(expression, instructions)?;
+                    }
+                    ast::ForInit::Expression(None) => {}
+                }
+
+                // Without a condition, the loop only ends with a jump out of it
+                instructions.push(Instruction::Label(start.clone()));
+                if let Some(condition) = condition {
+                    self.emit_loop_condition(condition, &end, instructions)?;
+                }
+
+                self.emit_statement(body, return_type, instructions)?;
+
+                instructions.push(Instruction::Label(continue_label(label)));
+                if let Some(post) = post {
+                    self.emit_expression(post, instructions)?;
+                }
+                instructions.push(Instruction::Jump { target: start });
+                instructions.push(Instruction::Label(end));
+            }
+            ast::Statement::Switch { condition, body, label, cases, .. } => {
+                // Type checking has promoted the value, and converted the cases to its type
+                let value = self.emit_expression(condition, instructions)?;
+                let ty = self.value_type(&value);
+
+                let end = break_label(label);
+                let default = cases.default.clone().unwrap_or_else(|| end.clone());
+
+                if is_dense(&cases.cases) {
+                    self.emit_jump_table(value, &cases.cases, default, instructions);
+                } else {
+                    for (number, target) in &cases.cases {
+                        let case = Value::Constant(Constant::integer(*number as u64, ty.clone()));
+                        let matches = self.emit_binary(&ast::BinaryOperator::Equal, value.clone(), case, instructions);
+                        instructions.push(Instruction::JumpIfNotZero { condition: matches, target: target.clone() });
+                    }
+                    instructions.push(Instruction::Jump { target: default });
+                }
+
+                // Cases fall through to the next one, until a `break` jumps to the end
+                self.emit_statement(body, return_type, instructions)?;
+                instructions.push(Instruction::Label(end));
+            }
+            ast::Statement::Case { body, label, .. } | ast::Statement::Default { body, label, .. } => {
+                instructions.push(Instruction::Label(label.clone()));
+                self.emit_statement(body, return_type, instructions)?;
+            }
+            ast::Statement::Labeled { label, body, .. } => {
+                instructions.push(Instruction::Label(self.user_label(label)));
+                self.emit_statement(body, return_type, instructions)?;
+            }
+            ast::Statement::Goto { label, .. } => instructions.push(Instruction::Jump { target: self.user_label(label) }),
+            ast::Statement::Break { label, .. } => instructions.push(Instruction::Jump { target: break_label(label) }),
+            ast::Statement::Continue { label, .. } => instructions.push(Instruction::Jump { target: continue_label(label) }),
+            ast::Statement::Null => {}
         }
+
+        Ok(())
     }
 
-    fn emit_expression(&mut self, expression: &ast::Expression, instructions: &mut Vec<Instruction>) -> Value {
+    /**
+    Jump to the case matching `value` through a table indexed by the distance from the smallest case. Values below it
+    wrap around to large unsigned indices, so a single comparison checks both bounds.
+    */
+    fn emit_jump_table(&mut self, value: Value, cases: &[(i64, String)], default: String, instructions: &mut Vec<Instruction>) {
+        let min = cases.iter().map(|(number, _)| *number).min().unwrap();
+        let max = cases.iter().map(|(number, _)| *number).max().unwrap();
+
+        let mut targets = vec![default.clone(); (max as i128 - min as i128 + 1) as usize];
+        for (number, target) in cases {
+            targets[(*number as i128 - min as i128) as usize] = target.clone();
+        }
+
+        let index = if min == 0 {
+            value
+        } else {
+            let ty = self.value_type(&value);
+            let index = self.make_temporary(ty.clone());
+            instructions.push(Instruction::Binary {
+                operator: BinaryOperator::Subtract,
+                lhs: value,
+                rhs: Value::Constant(Constant::integer(min as u64, ty)),
+                dst: index.clone(),
+            });
+            index
+        };
+
+        let table = self.variable_name_generator.make_label("switch_table");
+        instructions.push(Instruction::JumpTable { index, table, targets, default });
+    }
+
+    /**
+    Call a function, whose arguments type checking has converted to the types of the parameters.
+    */
+    fn emit_call(&mut self, name: &str, args: &[ast::Expression], instructions: &mut Vec<Instruction>) -> EmitterResult<Value> {
+        let return_type = self.functions[name].return_type.clone();
+        let args = args.iter().map(|arg| self.emit_expression(arg, instructions)).collect::<EmitterResult<_>>()?;
+
+        let dst = self.make_temporary(return_type);
+        instructions.push(Instruction::FunCall { name: name.to_owned(), args, dst: dst.clone() });
+
+        Ok(dst)
+    }
+
+    /**
+    Label of a labelled statement. Labels are local to their function, but every label of the assembly file shares
+    the same namespace.
+    */
+    fn user_label(&self, label: &str) -> String {
+        format!("goto.{}.{}", self.function_name, label)
+    }
+
+    /**
+    Evaluate the condition of a loop, and leave it when the condition is false.
+    */
+    fn emit_loop_condition(&mut self, condition: &ast::Expression, end: &str, instructions: &mut Vec<Instruction>) -> EmitterResult<()> {
+        let condition = self.emit_expression(condition, instructions)?;
+        let condition = self.emit_condition(condition, instructions);
+        instructions.push(Instruction::JumpIfZero { condition, target: end.to_owned() });
+
+        Ok(())
+    }
+
+    fn emit_expression(&mut self, expression: &ast::Expression, instructions: &mut Vec<Instruction>) -> EmitterResult<Value> {
         match expression {
             ast::Expression::Factor(factor) => {
                 self.emit_factor(factor, instructions)
             }
-            ast::Expression::Binary { left, right, operator } => {
-                let left_result = self.emit_expression(left, instructions);
-                let right_result = self.emit_expression(right, instructions);
-                let operator = self.emit_binary_operator(operator);
+            ast::Expression::Assignment { left, right, .. } => {
+                let lvalue = self.emit_lvalue(left, instructions)?;
+                self.emit_assignment(lvalue, right, instructions)
+            }
+            ast::Expression::Binary { left, right, operator: operator @ (ast::BinaryOperator::And | ast::BinaryOperator::Or), .. } => {
+                self.emit_logical(left, right, *operator == ast::BinaryOperator::And, instructions)
+            }
+            ast::Expression::Binary { left, right, operator, .. } => {
+                let left_result = self.emit_expression(left, instructions)?;
+                let right_result = self.emit_expression(right, instructions)?;
 
-                let result = Value::Var { identifier: self.variable_name_generator.make_temporary() };
+                Ok(self.emit_binary(operator, left_result, right_result, instructions))
+            }
+            ast::Expression::Conditional { condition, then, otherwise, .. } => {
+                let else_label = self.variable_name_generator.make_label("conditional_else");
+                let end = self.variable_name_generator.make_label("conditional_end");
 
-                instructions.push(Instruction::Binary {
-                    lhs: left_result,
-                    rhs: right_result,
-                    operator,
-                    dst: result.clone(),
-                });
+                let condition = self.emit_expression(condition, instructions)?;
+                let condition = self.emit_condition(condition, instructions);
+                instructions.push(Instruction::JumpIfZero { condition, target: else_label.clone() });
+
+                // Both branches are converted to their common type, which is known once both are emitted
+                let mut then_instructions = vec![];
+                let then = self.emit_expression(then, &mut then_instructions)?;
+                let mut else_instructions = vec![];
+                let otherwise = self.emit_expression(otherwise, &mut else_instructions)?;
+
+                let common_type = self.value_type(&then).common(&self.value_type(&otherwise));
+                let result = self.make_temporary(common_type.clone());
+
+                let then = self.emit_conversion(then, common_type.clone(), &mut then_instructions);
+                instructions.append(&mut then_instructions);
+                instructions.push(Instruction::Copy { src: then, dst: result.clone() });
+                instructions.push(Instruction::Jump { target: end.clone() });
+
+                instructions.push(Instruction::Label(else_label));
+                let otherwise = self.emit_conversion(otherwise, common_type, &mut else_instructions);
+                instructions.append(&mut else_instructions);
+                instructions.push(Instruction::Copy { src: otherwise, dst: result.clone() });
+                instructions.push(Instruction::Label(end));
+
+                Ok(result)
+            }
+            ast::Expression::CompoundAssignment { operator, left, right, .. } => {
+                // The left operand is evaluated once, and used both as an operand and as the destination
+                let lvalue = self.emit_lvalue(left, instructions)?;
+                let current = self.emit_load(&lvalue, instructions);
+                let right_result = self.emit_expression(right, instructions)?;
+
+                let result = self.emit_binary(operator, current, right_result, instructions);
+                let result = self.emit_conversion(result, self.lvalue_type(&lvalue), instructions);
+
+                Ok(self.emit_store(lvalue, result, instructions))
+            }
+        }
+    }
+
+    /**
+    Emit a binary operation, converting the operands the way type checking does. This only matters for the variable
+    updated by a compound assignment or an increment, which keeps its own type until here.
+    */
+    fn emit_binary(&mut self, operator: &ast::BinaryOperator, left: Value, right: Value, instructions: &mut Vec<Instruction>) -> Value {
+        let (left_type, right_type) = (self.value_type(&left), self.value_type(&right));
+
+        match operator {
+            ast::BinaryOperator::Sub if left_type.is_pointer() && right_type.is_pointer() => {
+                return self.emit_pointer_difference(left, right, instructions);
+            }
+            ast::BinaryOperator::Add | ast::BinaryOperator::Sub if left_type.is_pointer() => {
+                return self.emit_pointer_addition(left, right, *operator == ast::BinaryOperator::Sub, instructions);
+            }
+            ast::BinaryOperator::Add if right_type.is_pointer() => return self.emit_pointer_addition(right, left, false, instructions),
+            _ => {}
+        }
+
+        // The operands of a shift are promoted separately, and the result has the type of the left one
+        let (common_type, right_type) = match operator {
+            ast::BinaryOperator::ShiftLeft | ast::BinaryOperator::ShiftRight => (left_type.promote(), right_type.promote()),
+            _ => (left_type.common(&right_type), left_type.common(&right_type)),
+        };
 
-                result
+        let left = self.emit_conversion(left, common_type.clone(), instructions);
+        let right = self.emit_conversion(right, right_type, instructions);
+        let operator = self.emit_binary_operator(operator);
+
+        // Comparisons give an int whatever the type of their operands
+        let result = self.make_temporary(if operator.is_comparison() { Type::Int } else { common_type });
+
+        instructions.push(Instruction::Binary {
+            lhs: left,
+            rhs: right,
+            operator,
+            dst: result.clone(),
+        });
+
+        result
+    }
+
+    /**
+    Move `pointer` by `index` elements of the type it points to, forwards or backwards. The index is converted to a
+    `long` here, as the increments of a pointer add an `int` to it.
+    */
+    fn emit_pointer_addition(&mut self, pointer: Value, index: Value, subtract: bool, instructions: &mut Vec<Instruction>) -> Value {
+        let ty = self.value_type(&pointer);
+        let scale = ty.pointee().expect("Type checking only does arithmetic on pointers").size();
+
+        let index = self.emit_conversion(index, Type::Long, instructions);
+        let index = match index {
+            _ if !subtract => index,
+            Value::Constant(constant) => Value::Constant(Constant::integer((constant.integer_value().unwrap() as u64).wrapping_neg(), Type::Long)),
+            index => {
+                let negated = self.make_temporary(Type::Long);
+                instructions.push(Instruction::Unary { operator: UnaryOperator::Negate, src: index, dst: negated.clone() });
+                negated
             }
+        };
+
+        let dst = self.make_temporary(ty);
+        instructions.push(Instruction::AddPtr { ptr: pointer, index, scale, dst: dst.clone() });
+        dst
+    }
+
+    /**
+    Number of elements between two pointers of the same type, as a `long`.
+    */
+    fn emit_pointer_difference(&mut self, left: Value, right: Value, instructions: &mut Vec<Instruction>) -> Value {
+        let scale = self.value_type(&left).pointee().expect("Type checking only subtracts pointers").size();
+
+        let bytes = self.make_temporary(Type::Long);
+        instructions.push(Instruction::Binary { operator: BinaryOperator::Subtract, lhs: left, rhs: right, dst: bytes.clone() });
+        if scale == 1 {
+            return bytes;
         }
+
+        let dst = self.make_temporary(Type::Long);
+        instructions.push(Instruction::Binary {
+            operator: BinaryOperator::Divide,
+            lhs: bytes,
+            rhs: Value::Constant(Constant::integer(scale, Type::Long)),
+            dst: dst.clone(),
+        });
+        dst
     }
 
-    fn emit_factor(&mut self, factor: &ast::Factor, instructions: &mut Vec<Instruction>) -> Value {
+    fn emit_factor(&mut self, factor: &ast::Factor, instructions: &mut Vec<Instruction>) -> EmitterResult<Value> {
         match factor {
-            ast::Factor::Constant(value) => Value::Constant(value.clone()),
-            ast::Factor::Unary(op, unary_factor) => {
-                let src = self.emit_factor(unary_factor, instructions);
-                let dst = Value::Var { identifier: self.variable_name_generator.make_temporary() };
+            ast::Factor::Constant(value) => Ok(Value::Constant(value.clone())),
+            ast::Factor::StringLiteral(value) => Ok(Value::StringLiteral(value.clone())),
+            ast::Factor::Var(name, _) => Ok(self.emit_load(&Lvalue::Var(Value::Var { identifier: name.clone() }), instructions)),
+            ast::Factor::Call(name, args, _) => self.emit_call(name, args, instructions),
+            ast::Factor::Increment(operator, operand, _) => {
+                let lvalue = self.emit_lvalue_factor(operand, instructions)?;
+                let ty = self.lvalue_type(&lvalue);
+                let current = self.emit_load(&lvalue, instructions);
+
+                // A postfix operator gives the value the object had before being updated
+                let old = match operator {
+                    ast::IncrementOperator::PostfixIncrement | ast::IncrementOperator::PostfixDecrement => {
+                        let old = self.make_temporary(ty.clone());
+                        instructions.push(Instruction::Copy { src: current.clone(), dst: old.clone() });
+                        Some(old)
+                    }
+                    ast::IncrementOperator::PrefixIncrement | ast::IncrementOperator::PrefixDecrement => None,
+                };
+
+                let binary_operator = match operator {
+                    ast::IncrementOperator::PrefixIncrement | ast::IncrementOperator::PostfixIncrement => ast::BinaryOperator::Add,
+                    ast::IncrementOperator::PrefixDecrement | ast::IncrementOperator::PostfixDecrement => ast::BinaryOperator::Sub,
+                };
+                let one = Value::Constant(Constant::int(1));
+                let updated = self.emit_binary(&binary_operator, current, one, instructions);
+                let updated = self.emit_conversion(updated, ty, instructions);
+                let new = self.emit_store(lvalue, updated, instructions);
+
+                Ok(old.unwrap_or(new))
+            }
+            ast::Factor::AddressOf(operand, _) => {
+                let lvalue = self.emit_lvalue_factor(operand, instructions)?;
+                let ty = Type::Pointer(Box::new(self.lvalue_type(&lvalue)));
+                Ok(self.emit_address(&lvalue, ty, instructions))
+            }
+            ast::Factor::Dereference(_, _) | ast::Factor::Subscript(_, _, _) => {
+                let lvalue = self.emit_lvalue_factor(factor, instructions)?;
+                Ok(self.emit_load(&lvalue, instructions))
+            }
+            ast::Factor::Unary(op, unary_factor, _) => {
+                let src = self.emit_factor(unary_factor, instructions)?;
+                let ty = self.value_type(&src);
+
+                if let ast::UnaryOperator::Not = op {
+                    let condition = self.emit_condition(src, instructions);
+                    let dst = self.make_temporary(Type::Int);
+                    instructions.push(Instruction::Unary {
+                        operator: UnaryOperator::Not,
+                        src: condition,
+                        dst: dst.clone(),
+                    });
+
+                    return Ok(dst);
+                }
+
+                let dst = self.make_temporary(ty);
                 let operator = self.emit_unary_operator(op);
 
                 instructions.push(Instruction::Unary {
@@ -150,7 +939,11 @@ impl TackEmitter {
                     dst: dst.clone(),
                 });
 
-                dst
+                Ok(dst)
+            }
+            ast::Factor::Cast(ty, factor, _) => {
+                let value = self.emit_factor(factor, instructions)?;
+                Ok(self.emit_conversion(value, ty.clone(), instructions))
             }
             ast::Factor::Expression(expr) => {
                 self.emit_expression(expr, instructions)
@@ -158,10 +951,186 @@ impl TackEmitter {
         }
     }
 
+    /**
+    Emit `&&` or `||`, whose right operand is only evaluated when the left one doesn't decide the result.
+    */
+    fn emit_logical(&mut self, left: &ast::Expression, right: &ast::Expression, and: bool, instructions: &mut Vec<Instruction>) -> EmitterResult<Value> {
+        let (short_circuit, end) = if and {
+            (self.variable_name_generator.make_label("and_false"), self.variable_name_generator.make_label("and_end"))
+        } else {
+            (self.variable_name_generator.make_label("or_true"), self.variable_name_generator.make_label("or_end"))
+        };
+        let jump = |condition, target: &String| if and {
+            Instruction::JumpIfZero { condition, target: target.clone() }
+        } else {
+            Instruction::JumpIfNotZero { condition, target: target.clone() }
+        };
+
+        let left = self.emit_expression(left, instructions)?;
+        let left = self.emit_condition(left, instructions);
+        instructions.push(jump(left, &short_circuit));
+
+        let right = self.emit_expression(right, instructions)?;
+        let right = self.emit_condition(right, instructions);
+        instructions.push(jump(right, &short_circuit));
+
+        let result = self.make_temporary(Type::Int);
+        instructions.push(Instruction::Copy { src: Value::Constant(Constant::int(and as i32)), dst: result.clone() });
+        instructions.push(Instruction::Jump { target: end.clone() });
+        instructions.push(Instruction::Label(short_circuit));
+        instructions.push(Instruction::Copy { src: Value::Constant(Constant::int(!and as i32)), dst: result.clone() });
+        instructions.push(Instruction::Label(end));
+
+        Ok(result)
+    }
+
+    /**
+    Turn a scalar into an int which is zero when the scalar compares equal to zero, so that it can be tested by
+    jumps. Doubles need a real comparison, as NaN is not equal to zero.
+    */
+    fn emit_condition(&mut self, value: Value, instructions: &mut Vec<Instruction>) -> Value {
+        if self.value_type(&value) != Type::Double {
+            return value;
+        }
+
+        let dst = self.make_temporary(Type::Int);
+        instructions.push(Instruction::Binary {
+            operator: BinaryOperator::NotEqual,
+            lhs: value,
+            rhs: Value::Constant(Constant::Double(0.0)),
+            dst: dst.clone(),
+        });
+
+        dst
+    }
+
+    /**
+    Convert `value` to `ty`, folding the conversion of constants.
+    */
+    fn emit_conversion(&mut self, value: Value, ty: Type, instructions: &mut Vec<Instruction>) -> Value {
+        let from = self.value_type(&value);
+        if from == ty {
+            return value;
+        }
+
+        if let Value::Constant(constant) = &value {
+            if let Some(constant) = constant.convert(ty.clone()) {
+                return Value::Constant(constant);
+            }
+        }
+
+        // The hardware only converts between doubles and signed integers of 4 or 8 bytes, so the other integer types go
+        // through one of those
+        let make_instruction: fn(Value, Value) -> Instruction = match (&from, &ty) {
+            (Type::Double, Type::Int | Type::Long) => |src, dst| Instruction::DoubleToInt { src, dst },
+            (Type::Int | Type::Long, Type::Double) => |src, dst| Instruction::IntToDouble { src, dst },
+            (Type::Double, Type::ULong) => return self.emit_double_to_ulong(value, instructions),
+            (Type::ULong, Type::Double) => return self.emit_ulong_to_double(value, instructions),
+            (Type::Double, Type::UInt) | (Type::UInt, Type::Double) => {
+                let value = self.emit_conversion(value, Type::Long, instructions);
+                return self.emit_conversion(value, ty, instructions);
+            }
+            (Type::Double, _) | (_, Type::Double) => {
+                let value = self.emit_conversion(value, Type::Int, instructions);
+                return self.emit_conversion(value, ty, instructions);
+            }
+            // Integers of the same size have the same bits in both types
+            (from, ty) if from.size() == ty.size() => |src, dst| Instruction::Copy { src, dst },
+            (from, ty) if from.size() > ty.size() => |src, dst| Instruction::Truncate { src, dst },
+            (from, _) if from.is_signed() => |src, dst| Instruction::SignExtend { src, dst },
+            _ => |src, dst| Instruction::ZeroExtend { src, dst },
+        };
+
+        let dst = self.make_temporary(ty);
+        instructions.push(make_instruction(value, dst.clone()));
+        dst
+    }
+
+    /**
+    Convert an `unsigned long` to a double. Values which don't fit in a `long` are halved before the conversion and
+    doubled after it, keeping their lowest bit so that the result is rounded the same way.
+    */
+    fn emit_ulong_to_double(&mut self, value: Value, instructions: &mut Vec<Instruction>) -> Value {
+        let large = self.variable_name_generator.make_label("ulong_large");
+        let end = self.variable_name_generator.make_label("ulong_end");
+        let result = self.make_temporary(Type::Double);
+
+        let signed = self.make_temporary(Type::Long);
+        instructions.push(Instruction::Copy { src: value.clone(), dst: signed.clone() });
+        let negative = self.emit_binary(&ast::BinaryOperator::LessThan, signed.clone(), Value::Constant(Constant::integer(0, Type::Long)), instructions);
+        instructions.push(Instruction::JumpIfNotZero { condition: negative, target: large.clone() });
+        instructions.push(Instruction::IntToDouble { src: signed, dst: result.clone() });
+        instructions.push(Instruction::Jump { target: end.clone() });
+
+        instructions.push(Instruction::Label(large));
+        let one = Value::Constant(Constant::integer(1, Type::ULong));
+        let half = self.emit_binary(&ast::BinaryOperator::ShiftRight, value.clone(), Value::Constant(Constant::int(1)), instructions);
+        let lowest_bit = self.emit_binary(&ast::BinaryOperator::BitwiseAnd, value, one, instructions);
+        let half = self.emit_binary(&ast::BinaryOperator::BitwiseOr, half, lowest_bit, instructions);
+        let signed = self.make_temporary(Type::Long);
+        instructions.push(Instruction::Copy { src: half, dst: signed.clone() });
+        let half = self.make_temporary(Type::Double);
+        instructions.push(Instruction::IntToDouble { src: signed, dst: half.clone() });
+        instructions.push(Instruction::Binary { operator: BinaryOperator::Add, lhs: half.clone(), rhs: half, dst: result.clone() });
+        instructions.push(Instruction::Label(end));
+
+        result
+    }
+
+    /**
+    Convert a double to an `unsigned long`. Values which don't fit in a `long` are brought into its range by subtracting
+    2^63 before the conversion, and adding it back after.
+    */
+    fn emit_double_to_ulong(&mut self, value: Value, instructions: &mut Vec<Instruction>) -> Value {
+        let large = self.variable_name_generator.make_label("double_large");
+        let end = self.variable_name_generator.make_label("double_end");
+        let result = self.make_temporary(Type::ULong);
+        let limit = 9223372036854775808.0;
+
+        let is_large = self.emit_binary(&ast::BinaryOperator::GreaterOrEqual, value.clone(), Value::Constant(Constant::Double(limit)), instructions);
+        instructions.push(Instruction::JumpIfNotZero { condition: is_large, target: large.clone() });
+        let signed = self.make_temporary(Type::Long);
+        instructions.push(Instruction::DoubleToInt { src: value.clone(), dst: signed.clone() });
+        instructions.push(Instruction::Copy { src: signed, dst: result.clone() });
+        instructions.push(Instruction::Jump { target: end.clone() });
+
+        instructions.push(Instruction::Label(large));
+        let reduced = self.emit_binary(&ast::BinaryOperator::Sub, value, Value::Constant(Constant::Double(limit)), instructions);
+        let signed = self.make_temporary(Type::Long);
+        instructions.push(Instruction::DoubleToInt { src: reduced, dst: signed.clone() });
+        let unsigned = self.make_temporary(Type::ULong);
+        instructions.push(Instruction::Copy { src: signed, dst: unsigned.clone() });
+        instructions.push(Instruction::Binary {
+            operator: BinaryOperator::Add,
+            lhs: unsigned,
+            rhs: Value::Constant(Constant::integer(1 << 63, Type::ULong)),
+            dst: result.clone(),
+        });
+        instructions.push(Instruction::Label(end));
+
+        result
+    }
+
+    fn value_type(&self, value: &Value) -> Type {
+        match value {
+            Value::Constant(constant) => constant.ty(),
+            Value::StringLiteral(_) => Type::Pointer(Box::new(Type::Char)),
+            Value::Var { identifier } => self.symbols[identifier].clone(),
+        }
+    }
+
+    fn make_temporary(&mut self, ty: Type) -> Value {
+        let identifier = self.variable_name_generator.make_temporary();
+        self.symbols.insert(identifier.clone(), ty);
+
+        Value::Var { identifier }
+    }
+
     fn emit_unary_operator(&mut self, operator: &ast::UnaryOperator) -> UnaryOperator {
         match operator {
             ast::UnaryOperator::Complement => UnaryOperator::Complement,
-            ast::UnaryOperator::Negate => UnaryOperator::Negate
+            ast::UnaryOperator::Negate => UnaryOperator::Negate,
+            ast::UnaryOperator::Not => UnaryOperator::Not,
         }
     }
 
@@ -172,6 +1141,68 @@ impl TackEmitter {
             ast::BinaryOperator::Mul => BinaryOperator::Multiply,
             ast::BinaryOperator::Div => BinaryOperator::Divide,
             ast::BinaryOperator::Rem => BinaryOperator::Remainder,
+            ast::BinaryOperator::BitwiseAnd => BinaryOperator::BitwiseAnd,
+            ast::BinaryOperator::BitwiseOr => BinaryOperator::BitwiseOr,
+            ast::BinaryOperator::BitwiseXor => BinaryOperator::BitwiseXor,
+            ast::BinaryOperator::ShiftLeft => BinaryOperator::ShiftLeft,
+            ast::BinaryOperator::ShiftRight => BinaryOperator::ShiftRight,
+            ast::BinaryOperator::Equal => BinaryOperator::Equal,
+            ast::BinaryOperator::NotEqual => BinaryOperator::NotEqual,
+            ast::BinaryOperator::LessThan => BinaryOperator::LessThan,
+            ast::BinaryOperator::LessOrEqual => BinaryOperator::LessOrEqual,
+            ast::BinaryOperator::GreaterThan => BinaryOperator::GreaterThan,
+            ast::BinaryOperator::GreaterOrEqual => BinaryOperator::GreaterOrEqual,
+            ast::BinaryOperator::And | ast::BinaryOperator::Or => unreachable!("Logical operators are emitted with jumps"),
         }
     }
 }
+
+/**
+Target of `continue` in the loop labelled `label`, right before the next iteration is considered.
+*/
+fn continue_label(label: &str) -> String {
+    format!("continue_{}", label)
+}
+
+/**
+Target of `break` in the loop labelled `label`, right after the loop.
+*/
+fn break_label(label: &str) -> String {
+    format!("break_{}", label)
+}
+
+/**
+Whether the values of the cases of a `switch` are close enough for a jump table.
+*/
+fn is_dense(cases: &[(i64, String)]) -> bool {
+    if cases.len() < JUMP_TABLE_MIN_CASES {
+        return false;
+    }
+
+    let min = cases.iter().map(|(number, _)| *number).min().unwrap();
+    let max = cases.iter().map(|(number, _)| *number).max().unwrap();
+
+    // The values of an `unsigned long` switch can be further apart than an i64 can hold
+    let entries = max as i128 - min as i128 + 1;
+
+    entries <= cases.len() as i128 * JUMP_TABLE_MAX_ENTRIES_PER_CASE
+}
+
+/**
+Set `size` bytes of the local array `var` to zero from `offset`, 8 bytes at a time whatever the type of its elements.
+*/
+fn emit_zero(var: &Value, offset: u64, size: u64, instructions: &mut Vec<Instruction>) {
+    let end = offset + size;
+    let mut offset = offset;
+    while offset < end {
+        let ty = match end - offset {
+            8.. => Type::ULong,
+            4.. => Type::UInt,
+            2.. => Type::UShort,
+            _ => Type::UChar,
+        };
+        let size = ty.size();
+        instructions.push(Instruction::CopyToOffset { src: Value::Constant(Constant::integer(0, ty)), dst: var.clone(), offset });
+        offset += size;
+    }
+}
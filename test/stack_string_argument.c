@@ -0,0 +1,9 @@
+/* A string literal passed on the stack: exits with 120, the code of 'x' */
+
+int f(int a, int b, int c, int d, int e, int g, char *h) {
+    return *h;
+}
+
+int main(void) {
+    return f(1, 2, 3, 4, 5, 6, "xyz");
+}